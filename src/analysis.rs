@@ -0,0 +1,508 @@
+use crate::ast::*;
+use crate::optimize::is_value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    UnusedParameter(String),
+    UnusedBinder(String),
+    Shadowed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// Span of the innermost source construct binding the variable.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            WarningKind::UnusedParameter(x) => write!(f, "unused parameter `{}`", x),
+            WarningKind::UnusedBinder(x) => write!(f, "unused match binder `{}`", x),
+            WarningKind::Shadowed(x) => write!(f, "`{}` shadows an earlier binding", x),
+        }
+    }
+}
+
+/// Variables that occur in `expr` without being bound by it.
+pub fn free_vars(expr: &Expr) -> BTreeSet<String> {
+    use Expr::*;
+    match expr {
+        Var(x) => BTreeSet::from([x.clone()]),
+        Unit | Int(_) | Float(_) | Bool(_) | Str(_) | Char(_) | None(_) => BTreeSet::new(),
+        Negate(e)
+        | Ref(e)
+        | Deref(e)
+        | Project(e, _)
+        | Field(e, _)
+        | Raise(e, _)
+        | Spanned(_, e) => free_vars(e),
+        Tuple(es) => es.iter().flat_map(free_vars).collect(),
+        Record(fields) => fields.iter().flat_map(|(_, e)| free_vars(e)).collect(),
+        Update(e, fields) => {
+            let mut vars = free_vars(e);
+            vars.extend(fields.iter().flat_map(|(_, e)| free_vars(e)));
+            vars
+        }
+        Func(x, _, e) | Recursion(x, _, e) => {
+            let mut vars = free_vars(e);
+            vars.remove(x);
+            vars
+        }
+        LetRec(bindings, e) => {
+            let mut vars = free_vars_of_bindings(bindings);
+            vars.extend(free_vars(e));
+            for (x, _, _) in bindings {
+                vars.remove(x);
+            }
+            vars
+        }
+        Mult(e1, e2)
+        | Divide(e1, e2)
+        | Mod(e1, e2)
+        | Plus(e1, e2)
+        | Minus(e1, e2)
+        | Equal(e1, e2)
+        | Less(e1, e2)
+        | Concat(e1, e2)
+        | Index(e1, e2)
+        | Seq(e1, e2)
+        | Assign(e1, e2)
+        | Apply(e1, e2)
+        | Cons(e1, e2) => &free_vars(e1) | &free_vars(e2),
+        If(e1, e2, e3) => &(&free_vars(e1) | &free_vars(e2)) | &free_vars(e3),
+        Match(e1, _, e2, x, y, e3) => {
+            let mut vars = free_vars(e3);
+            vars.remove(x);
+            vars.remove(y);
+            &(&free_vars(e1) | &free_vars(e2)) | &vars
+        }
+        Try(e, handlers) => {
+            let mut vars = free_vars(e);
+            for (pattern, handler) in handlers {
+                let mut used = free_vars(handler);
+                if let Some(x) = pattern.binder() {
+                    used.remove(x);
+                }
+                vars.extend(used);
+            }
+            vars
+        }
+    }
+}
+
+/// Variables that the bodies of a `let rec` group use, including the names it
+/// defines.
+fn free_vars_of_bindings(bindings: &[Binding]) -> BTreeSet<String> {
+    bindings.iter().flat_map(|(_, _, e)| free_vars(e)).collect()
+}
+
+/// Drops toplevel definitions that no later expression can observe, either
+/// because nothing refers to them or because they are redefined first.
+/// Definitions that are not values are kept for their effects.
+pub fn eliminate_dead_code(commands: &[Commands]) -> Vec<Commands> {
+    commands
+        .iter()
+        .zip(live_commands(commands))
+        .filter(|(_, live)| *live)
+        .map(|(command, _)| command.clone())
+        .collect()
+}
+
+/// Whether each command is kept by [`eliminate_dead_code`].
+pub fn live_commands(commands: &[Commands]) -> Vec<bool> {
+    let mut needed = BTreeSet::new();
+    let mut live = vec![false; commands.len()];
+    for (i, command) in commands.iter().enumerate().rev() {
+        match command {
+            Commands::Expr(e) => {
+                needed.extend(free_vars(e));
+                live[i] = true;
+            }
+            Commands::Fn(name, e, _) => {
+                if needed.remove(name) || !is_value(e) {
+                    needed.extend(free_vars(e));
+                    live[i] = true;
+                }
+            }
+            Commands::Doc(name) => {
+                needed.insert(name.clone());
+                live[i] = true;
+            }
+            Commands::LetRec(bindings, _) => {
+                if bindings
+                    .iter()
+                    .any(|(x, _, e)| needed.contains(x) || !is_value(e))
+                {
+                    for (x, _, _) in bindings {
+                        needed.remove(x);
+                    }
+                    let mut used = free_vars_of_bindings(bindings);
+                    for (x, _, _) in bindings {
+                        used.remove(x);
+                    }
+                    needed.extend(used);
+                    live[i] = true;
+                }
+            }
+            // Type declarations are kept, since types cannot be tracked
+            // through the annotations that use them without resolving them.
+            // Imports are kept for the effects of loading the module, and
+            // conservatively leave whatever they define needed.
+            Commands::Type(_, _) | Commands::Import(_, _) | Commands::Exit => live[i] = true,
+            // What the arguments of toplevel commands use is not tracked, so
+            // everything before one is kept.
+            Commands::Meta(_, _, _) => {
+                live[..=i].fill(true);
+                break;
+            }
+        }
+    }
+    live
+}
+
+/// Warnings for every command, with toplevel definitions in scope of the
+/// commands that follow them.
+pub fn check_commands(commands: &[Commands]) -> Vec<Warning> {
+    let mut toplevel = Vec::new();
+    let mut warnings = Vec::new();
+    for command in commands {
+        warnings.extend(check_command(command, &toplevel));
+        match command {
+            Commands::Fn(name, _, _) => toplevel.push(name.clone()),
+            Commands::LetRec(bindings, _) => {
+                toplevel.extend(bindings.iter().map(|(x, _, _)| x.clone()))
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// Warnings for `command`, with the names defined by earlier commands in
+/// `toplevel`.
+pub fn check_command(command: &Commands, toplevel: &[String]) -> Vec<Warning> {
+    let mut scope = toplevel.to_vec();
+    let mut warnings = Vec::new();
+    match command {
+        Commands::Expr(e) | Commands::Fn(_, e, _) => check(e, &mut scope, None, &mut warnings),
+        Commands::LetRec(bindings, _) => {
+            scope.extend(bindings.iter().map(|(x, _, _)| x.clone()));
+            for (_, _, e) in bindings {
+                check(e, &mut scope, None, &mut warnings);
+            }
+        }
+        Commands::Type(_, _)
+        | Commands::Import(_, _)
+        | Commands::Doc(_)
+        | Commands::Exit
+        | Commands::Meta(_, _, _) => {}
+    }
+    warnings
+}
+
+/// Warnings for a single expression, with no names in scope.
+pub fn check_expr(expr: &Expr) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    check(expr, &mut Vec::new(), None, &mut warnings);
+    warnings
+}
+
+/// Adds the warnings for `expr` to `warnings`, where `span` is that of the
+/// innermost construct around `expr` that has one.
+fn check(expr: &Expr, scope: &mut Vec<String>, span: Option<&Span>, warnings: &mut Vec<Warning>) {
+    use Expr::*;
+    match expr {
+        Var(_) | Unit | Int(_) | Float(_) | Bool(_) | Str(_) | Char(_) | None(_) => {}
+        Negate(e) | Ref(e) | Deref(e) | Project(e, _) | Field(e, _) | Raise(e, _) => {
+            check(e, scope, span, warnings)
+        }
+        Spanned(span, e) => check(e, scope, Some(span), warnings),
+        Tuple(es) => es.iter().for_each(|e| check(e, scope, span, warnings)),
+        Record(fields) => fields
+            .iter()
+            .for_each(|(_, e)| check(e, scope, span, warnings)),
+        Update(e, fields) => {
+            check(e, scope, span, warnings);
+            fields
+                .iter()
+                .for_each(|(_, e)| check(e, scope, span, warnings));
+        }
+        Func(x, _, e) | Recursion(x, _, e) => {
+            if scope.contains(x) && !is_ignored(x) {
+                warn(warnings, span, WarningKind::Shadowed(x.clone()));
+            }
+            if matches!(expr, Func(_, _, _)) && !is_ignored(x) && !free_vars(e).contains(x) {
+                warn(warnings, span, WarningKind::UnusedParameter(x.clone()));
+            }
+            scope.push(x.clone());
+            check(e, scope, span, warnings);
+            scope.pop();
+        }
+        Mult(e1, e2)
+        | Divide(e1, e2)
+        | Mod(e1, e2)
+        | Plus(e1, e2)
+        | Minus(e1, e2)
+        | Equal(e1, e2)
+        | Less(e1, e2)
+        | Concat(e1, e2)
+        | Index(e1, e2)
+        | Seq(e1, e2)
+        | Assign(e1, e2)
+        | Apply(e1, e2)
+        | Cons(e1, e2) => {
+            check(e1, scope, span, warnings);
+            check(e2, scope, span, warnings);
+        }
+        If(e1, e2, e3) => {
+            check(e1, scope, span, warnings);
+            check(e2, scope, span, warnings);
+            check(e3, scope, span, warnings);
+        }
+        LetRec(bindings, e) => {
+            for (x, _, _) in bindings {
+                if scope.contains(x) && !is_ignored(x) {
+                    warn(warnings, span, WarningKind::Shadowed(x.clone()));
+                }
+            }
+            let depth = scope.len();
+            scope.extend(bindings.iter().map(|(x, _, _)| x.clone()));
+            for (_, _, body) in bindings {
+                check(body, scope, span, warnings);
+            }
+            check(e, scope, span, warnings);
+            scope.truncate(depth);
+        }
+        Match(e1, _, e2, x, y, e3) => {
+            check(e1, scope, span, warnings);
+            check(e2, scope, span, warnings);
+            let used = free_vars(e3);
+            for binder in [x, y] {
+                if is_ignored(binder) {
+                    continue;
+                }
+                if scope.contains(binder) {
+                    warn(warnings, span, WarningKind::Shadowed(binder.clone()));
+                }
+                if !used.contains(binder) {
+                    warn(warnings, span, WarningKind::UnusedBinder(binder.clone()));
+                }
+            }
+            scope.extend([x.clone(), y.clone()]);
+            check(e3, scope, span, warnings);
+            scope.truncate(scope.len() - 2);
+        }
+        Try(e, handlers) => {
+            check(e, scope, span, warnings);
+            for (pattern, handler) in handlers {
+                let Some(x) = pattern.binder() else {
+                    check(handler, scope, span, warnings);
+                    continue;
+                };
+                if scope.contains(x) && !is_ignored(x) {
+                    warn(warnings, span, WarningKind::Shadowed(x.clone()));
+                }
+                if !is_ignored(x) && !free_vars(handler).contains(x) {
+                    warn(warnings, span, WarningKind::UnusedBinder(x.clone()));
+                }
+                scope.push(x.clone());
+                check(handler, scope, span, warnings);
+                scope.pop();
+            }
+        }
+    }
+}
+
+/// Whether `x` is named so as to be left unused, as in `fun _ : int => 1`.
+fn is_ignored(x: &str) -> bool {
+    x.starts_with('_')
+}
+
+fn warn(warnings: &mut Vec<Warning>, span: Option<&Span>, kind: WarningKind) {
+    warnings.push(Warning {
+        kind,
+        span: span.cloned(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::num::Integer;
+    use crate::toplevel;
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(Integer::from(n)))
+    }
+
+    fn var(x: &str) -> Box<Expr> {
+        Box::new(Expr::Var(x.to_string()))
+    }
+
+    fn func(x: &str, body: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::Func(x.to_string(), Box::new(Type::Int), body))
+    }
+
+    fn kinds(warnings: Vec<Warning>) -> Vec<WarningKind> {
+        warnings.into_iter().map(|warning| warning.kind).collect()
+    }
+
+    #[test]
+    fn free_vars_respect_binders() {
+        // fun x : int -> match y with [int] -> x | h :: t -> h + z
+        let expr = func(
+            "x",
+            Box::new(Expr::Match(
+                var("y"),
+                Box::new(Type::Int),
+                var("x"),
+                "h".to_string(),
+                "t".to_string(),
+                Box::new(Expr::Plus(var("h"), var("z"))),
+            )),
+        );
+        assert_eq!(
+            free_vars(&expr),
+            BTreeSet::from(["y".to_string(), "z".to_string()])
+        );
+    }
+
+    #[test]
+    fn removes_unreachable_definitions() {
+        let commands = vec![
+            Commands::Fn("a".to_string(), Expr::Int(Integer::from(1)), None),
+            Commands::Fn("b".to_string(), *var("a"), None),
+            Commands::Fn("c".to_string(), Expr::Int(Integer::from(3)), None),
+            Commands::Fn("b".to_string(), Expr::Int(Integer::from(2)), None),
+            Commands::Expr(*var("b")),
+        ];
+        assert_eq!(
+            eliminate_dead_code(&commands),
+            vec![
+                Commands::Fn("b".to_string(), Expr::Int(Integer::from(2)), None),
+                Commands::Expr(*var("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_recursive_groups_that_are_used() {
+        let group = |used: &str| {
+            Commands::LetRec(
+                vec![
+                    ("f".to_string(), Type::Int, *var("g")),
+                    ("g".to_string(), Type::Int, *var(used)),
+                ],
+                None,
+            )
+        };
+        let commands = vec![
+            Commands::Fn("a".to_string(), Expr::Int(Integer::from(1)), None),
+            Commands::Fn("b".to_string(), Expr::Int(Integer::from(2)), None),
+            group("a"),
+            Commands::Expr(*var("f")),
+        ];
+        assert_eq!(
+            eliminate_dead_code(&commands),
+            [&commands[0], &commands[2], &commands[3]].map(Clone::clone)
+        );
+        let commands = vec![group("f"), Commands::Expr(*var("b"))];
+        assert_eq!(eliminate_dead_code(&commands), commands[1..]);
+    }
+
+    #[test]
+    fn keeps_definitions_used_by_later_definitions() {
+        let commands = vec![
+            Commands::Fn("a".to_string(), Expr::Int(Integer::from(1)), None),
+            Commands::Fn("b".to_string(), Expr::Plus(var("a"), var("a")), None),
+            Commands::Expr(*var("b")),
+        ];
+        assert_eq!(eliminate_dead_code(&commands), commands);
+    }
+
+    #[test]
+    fn keeps_definitions_with_effects() {
+        let commands = vec![
+            Commands::Fn("r".to_string(), Expr::Ref(int(0)), None),
+            Commands::Fn("a".to_string(), *int(1), None),
+            Commands::Meta("type".to_string(), "a".to_string(), 0),
+            Commands::Fn("b".to_string(), *int(2), None),
+        ];
+        assert_eq!(eliminate_dead_code(&commands), commands[..3]);
+        assert_eq!(live_commands(&commands), [true, true, true, false]);
+    }
+
+    #[test]
+    fn warns_about_unused_parameters() {
+        let expr = func("x", func("y", var("x")));
+        assert_eq!(
+            kinds(check_expr(&expr)),
+            vec![WarningKind::UnusedParameter("y".to_string())]
+        );
+    }
+
+    #[test]
+    fn allows_binders_marked_unused() {
+        for source in [
+            "fun _ : int => fun _unused : int => 1",
+            "match [int] with [int] -> 0 | _ :: _ -> 1",
+            "try 1 with _e -> 2",
+        ] {
+            let expr = toplevel::parse_expr(source).unwrap();
+            assert_eq!(kinds(check_expr(&expr)), vec![], "{}", source);
+        }
+    }
+
+    #[test]
+    fn warns_about_unused_match_binders() {
+        let expr = Expr::Match(
+            var("l"),
+            Box::new(Type::Int),
+            Box::new(Expr::Int(Integer::from(0))),
+            "x".to_string(),
+            "y".to_string(),
+            var("x"),
+        );
+        assert_eq!(
+            kinds(check_expr(&expr)),
+            vec![WarningKind::UnusedBinder("y".to_string())]
+        );
+    }
+
+    #[test]
+    fn warns_about_shadowing() {
+        let commands = vec![
+            Commands::Fn("n".to_string(), Expr::Int(Integer::from(1)), None),
+            Commands::Expr(*func(
+                "x",
+                func("x", func("n", Box::new(Expr::Int(Integer::from(0))))),
+            )),
+        ];
+        assert_eq!(
+            kinds(check_commands(&commands)),
+            vec![
+                WarningKind::UnusedParameter("x".to_string()),
+                WarningKind::Shadowed("x".to_string()),
+                WarningKind::UnusedParameter("x".to_string()),
+                WarningKind::Shadowed("n".to_string()),
+                WarningKind::UnusedParameter("n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings_point_at_the_binding_construct() {
+        let commands = toplevel::parse("let f = fun x : int => (fun y : int => y) 1").unwrap();
+        assert_eq!(
+            check_commands(&commands),
+            vec![Warning {
+                kind: WarningKind::UnusedParameter("x".to_string()),
+                span: Some(8..43),
+            }]
+        );
+    }
+}
@@ -1,17 +1,47 @@
+use crate::ir::Core;
+use crate::num::Integer;
+use crate::pretty::{self, Style};
+use std::fmt;
+use std::ops::Range;
+
+/// Byte range of a construct in the source it was parsed from.
+pub type Span = Range<usize>;
+
 #[derive(Debug, Clone)]
 pub enum Type {
+    Unit,
     Int,
+    Float,
     Bool,
-    Mult(Box<Type>, Box<Type>),
+    String,
+    Char,
+    /// `t1 * ... * tn`, with at least two components.
+    Tuple(Vec<Type>),
     Func(Box<Type>, Box<Type>),
     List(Box<Type>),
+    Ref(Box<Type>),
+    /// Exceptions, which are all built in.
+    Exn,
+    /// `{ x : t; ... }`, with the fields sorted by name. Build it with
+    /// `Type::record` so that field order does not matter.
+    Record(Vec<(String, Type)>),
+    /// A type alias declared with `type`, which the type checker resolves.
+    Named(String),
 }
 
-#[derive(Debug, Clone)]
+/// An expression. Equality is structural and includes the spans of
+/// `Spanned` nodes, so the same text parsed at different offsets is not
+/// equal; [`Expr::alpha_eq`] ignores spans as well as bound names.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Var(String),
-    Int(i64),
+    Unit,
+    Int(Integer),
+    Float(f64),
     Bool(bool),
+    Str(String),
+    Char(char),
+    Negate(Box<Expr>),
     Mult(Box<Expr>, Box<Expr>),
     Divide(Box<Expr>, Box<Expr>),
     Mod(Box<Expr>, Box<Expr>),
@@ -19,30 +49,125 @@ pub enum Expr {
     Minus(Box<Expr>, Box<Expr>),
     Equal(Box<Expr>, Box<Expr>),
     Less(Box<Expr>, Box<Expr>),
+    /// `s ^ t`
+    Concat(Box<Expr>, Box<Expr>),
+    /// `s.[i]`
+    Index(Box<Expr>, Box<Expr>),
     If(Box<Expr>, Box<Expr>, Box<Expr>),
     Func(String, Box<Type>, Box<Expr>),
     Apply(Box<Expr>, Box<Expr>),
-    Pair(Box<Expr>, Box<Expr>),
-    First(Box<Expr>),
-    Second(Box<Expr>),
+    /// `(e1, ..., en)`, with at least two components.
+    Tuple(Vec<Expr>),
+    /// `#n e` projects the `n`th component, counting from one, and is
+    /// `Project(e, n - 1)`. `fst` and `snd` are `#1` and `#2`.
+    Project(Box<Expr>, usize),
+    /// `{ x = e; ... }`, with the fields in source order.
+    Record(Vec<(String, Expr)>),
+    /// `e.x`
+    Field(Box<Expr>, String),
+    /// `{ e with x = e1; ... }`, a copy of a record with some fields replaced.
+    Update(Box<Expr>, Vec<(String, Expr)>),
     Recursion(String, Box<Type>, Box<Expr>),
+    /// `let rec f : t = e1 and ... in e`, where every `ei` and `e` see all
+    /// the names in the group.
+    LetRec(Vec<Binding>, Box<Expr>),
     None(Type),
     Cons(Box<Expr>, Box<Expr>),
     Match(Box<Expr>, Box<Type>, Box<Expr>, String, String, Box<Expr>),
+    /// `e1; e2`
+    Seq(Box<Expr>, Box<Expr>),
+    /// `ref e`
+    Ref(Box<Expr>),
+    /// `!e`
+    Deref(Box<Expr>),
+    /// `e1 := e2`
+    Assign(Box<Expr>, Box<Expr>),
+    /// `raise e : ty`, which can stand for an expression of any type.
+    Raise(Box<Expr>, Box<Type>),
+    /// `try e with | p1 -> e1 | ...`
+    Try(Box<Expr>, Vec<(Pattern, Expr)>),
+    Spanned(Span, Box<Expr>),
+}
+
+/// `f : t = e`, one of the definitions of a `let rec ... and ...` group.
+pub type Binding = (String, Type, Expr);
+
+/// What a `try ... with` handler matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// An exception constructor and the variable bound to its argument.
+    Constructor(String, Option<String>),
+    /// Any exception, bound to a variable.
+    Var(String),
+    /// `_`
+    Wildcard,
 }
 
+impl Pattern {
+    /// The variable the pattern binds, if any.
+    pub fn binder(&self) -> Option<&String> {
+        match self {
+            Pattern::Constructor(_, x) => x.as_ref(),
+            Pattern::Var(x) => Some(x),
+            Pattern::Wildcard => None,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Constructor(name, Some(x)) => write!(f, "{} {}", name, x),
+            Pattern::Constructor(name, None) => write!(f, "{}", name),
+            Pattern::Var(x) => write!(f, "{}", x),
+            Pattern::Wildcard => write!(f, "_"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Commands {
     Expr(Expr),
-    Fn(String, Expr),
+    /// A toplevel `let`, with the text of the `--|` comments before it.
+    Fn(String, Expr, Option<String>),
+    /// A toplevel `let rec ... and ...`, with the `--|` comments before it,
+    /// which document every name in the group.
+    LetRec(Vec<Binding>, Option<String>),
+    /// `type name = ty`
+    Type(String, Type),
+    /// `import Name`, with the span of the name.
+    Import(String, Span),
+    /// `:doc name`
+    Doc(String),
     Exit,
+    /// Any other `:name arg` command, with the offset of `arg` in the source,
+    /// which is left to the toplevel to make sense of.
+    Meta(String, String, usize),
+}
+
+/// On a single line but for documentation comments, in the syntax it is
+/// parsed from, and without the `;;` that ends every command.
+impl fmt::Display for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&pretty::command(self, &Style::single_line()))
+    }
 }
+
 impl PartialEq for Type {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (Type::Unit, Type::Unit) => true,
             (Type::Int, Type::Int) => true,
+            (Type::Float, Type::Float) => true,
             (Type::Bool, Type::Bool) => true,
+            (Type::String, Type::String) => true,
+            (Type::Char, Type::Char) => true,
             (Type::List(a), Type::List(b)) => a == b,
-            (Type::Mult(a1, b1), Type::Mult(a2, b2)) => a1 == a2 && b1 == b2,
+            (Type::Ref(a), Type::Ref(b)) => a == b,
+            (Type::Exn, Type::Exn) => true,
+            (Type::Tuple(a), Type::Tuple(b)) => a == b,
+            (Type::Record(a), Type::Record(b)) => a == b,
+            (Type::Named(a), Type::Named(b)) => a == b,
             (Type::Func(a1, b1), Type::Func(a2, b2)) => a1 == a2 && b1 == b2,
             _ => false,
         }
@@ -52,23 +177,91 @@ impl PartialEq for Type {
 impl Eq for Type {}
 
 impl Type {
-    fn to_string_with_precedence(&self, outer_precedence: i32) -> String {
+    /// A record type with `fields` in any order.
+    pub fn record(mut fields: Vec<(String, Type)>) -> Type {
+        fields.sort_by(|(x, _), (y, _)| x.cmp(y));
+        Type::Record(fields)
+    }
+
+    /// Applies `f` to the immediate components of `self`, stopping at the
+    /// first error.
+    pub fn try_map_children<E>(
+        &self,
+        f: &mut impl FnMut(&Type) -> Result<Type, E>,
+    ) -> Result<Type, E> {
+        Ok(match self {
+            Type::Unit
+            | Type::Int
+            | Type::Float
+            | Type::Bool
+            | Type::String
+            | Type::Char
+            | Type::Exn
+            | Type::Named(_) => self.clone(),
+            Type::Tuple(tys) => Type::Tuple(tys.iter().map(&mut *f).collect::<Result<_, _>>()?),
+            Type::Func(arg, ret) => Type::Func(Box::new(f(arg)?), Box::new(f(ret)?)),
+            Type::List(ty) => Type::List(Box::new(f(ty)?)),
+            Type::Ref(ty) => Type::Ref(Box::new(f(ty)?)),
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(x, ty)| Ok((x.clone(), f(ty)?)))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    /// The type as it is written in source, as in `int list`, rather than
+    /// as it is shown in messages.
+    pub fn to_source(&self) -> String {
+        self.render(-1, true)
+    }
+
+    /// The type with components of lower precedence than `outer_precedence`
+    /// parenthesized, and with the names of built-in types as written in
+    /// source if `source`.
+    fn render(&self, outer_precedence: i32, source: bool) -> String {
+        let base = |name: &str| {
+            let name = if source {
+                name.to_lowercase()
+            } else {
+                name.to_string()
+            };
+            (4, name)
+        };
         let (inner_precedence, result) = match self {
-            Type::Int => (4, "Int".to_string()),
-            Type::Bool => (4, "Bool".to_string()),
-            Type::Mult(left, right) => {
-                let left_str = left.to_string_with_precedence(2);
-                let right_str = right.to_string_with_precedence(2);
-                (2, format!("{} * {}", left_str, right_str))
+            Type::Named(x) => (4, x.clone()),
+            Type::Unit => base("Unit"),
+            Type::Int => base("Int"),
+            Type::Float => base("Float"),
+            Type::Bool => base("Bool"),
+            Type::Exn => base("Exn"),
+            Type::String => base("String"),
+            Type::Char => base("Char"),
+            Type::Tuple(tys) => {
+                let tys: Vec<_> = tys.iter().map(|ty| ty.render(2, source)).collect();
+                (2, tys.join(" * "))
+            }
+            Type::Record(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(x, ty)| format!("{} : {}", x, ty.render(-1, source)))
+                    .collect();
+                (4, format!("{{ {} }}", fields.join("; ")))
             }
             Type::Func(arg, ret) => {
-                let arg_str = arg.to_string_with_precedence(1);
-                let ret_str = ret.to_string_with_precedence(0);
+                let arg_str = arg.render(1, source);
+                let ret_str = ret.render(0, source);
                 (1, format!("{} -> {}", arg_str, ret_str))
             }
-            Type::List(ty) => {
-                let ty_str = ty.to_string_with_precedence(3);
-                (3, format!("{} List", ty_str))
+            Type::List(ty) | Type::Ref(ty) => {
+                let constructor = match (self, source) {
+                    (Type::List(_), false) => "List",
+                    (Type::List(_), true) => "list",
+                    (_, false) => "Ref",
+                    (_, true) => "ref",
+                };
+                (3, format!("{} {}", ty.render(3, source), constructor))
             }
         };
 
@@ -78,154 +271,58 @@ impl Type {
             format!("({})", result)
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        self.to_string_with_precedence(-1)
+/// `text` between `delimiter`s, with escape sequences wherever the lexer
+/// needs them to read it back.
+pub fn quote(text: &str, delimiter: char) -> String {
+    let mut result = String::from(delimiter);
+    for c in text.chars() {
+        match c {
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '\0' => result.push_str("\\0"),
+            '\\' => result.push_str("\\\\"),
+            c if c == delimiter => {
+                result.push('\\');
+                result.push(c);
+            }
+            c if c.is_control() => result.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => result.push(c),
+        }
     }
+    result.push(delimiter);
+    result
 }
 
-impl PartialEq for Expr {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Expr::Int(a), Expr::Int(b)) => a == b,
-            (Expr::Plus(a1, a2), Expr::Plus(b1, b2)) => a1 == b1 && a2 == b2,
-            (Expr::Minus(a1, a2), Expr::Minus(b1, b2)) => a1 == b1 && a2 == b2,
-            (Expr::Mult(a1, a2), Expr::Mult(b1, b2)) => a1 == b1 && a2 == b2,
-            (Expr::Divide(a1, a2), Expr::Divide(b1, b2)) => a1 == b1 && a2 == b2,
-            (Expr::Equal(a1, a2), Expr::Equal(b1, b2))
-            | (Expr::Less(a1, a2), Expr::Less(b1, b2)) => a1 == b1 && a2 == b2,
-            _ => false,
-        }
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(-1, false))
     }
 }
 
-impl Eq for Expr {}
+/// On a single line, in the syntax it is parsed from.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&pretty::expr(self, &Style::single_line()))
+    }
+}
 
 impl Expr {
-    fn to_string_with_precedence(&self, outer_precedence: i32) -> String {
-        use Expr::*;
-        let (inner_precedence, result) = match self {
-            Var(x) => (10, x.clone()),
-            Int(n) => (10, n.to_string()),
-            Bool(b) => (10, b.to_string()),
-            Pair(e1, e2) => (
-                10,
-                format!(
-                    "({}, {})",
-                    e1.to_string_with_precedence(0),
-                    e2.to_string_with_precedence(0)
-                ),
-            ),
-            None(ty) => (10, format!("[{}]", ty.to_string())),
-            First(e) => (9, format!("fst {}", e.to_string_with_precedence(9))),
-            Second(e) => (9, format!("snd {}", e.to_string_with_precedence(9))),
-            Apply(e1, e2) => (
-                9,
-                format!(
-                    "{} {}",
-                    e1.to_string_with_precedence(8),
-                    e2.to_string_with_precedence(9)
-                ),
-            ),
-            Mult(e1, e2) | Divide(e1, e2) | Mod(e1, e2) => {
-                let symbol = match self {
-                    Mult(_, _) => "*",
-                    Divide(_, _) => "/",
-                    Mod(_, _) => "%",
-                    _ => unreachable!(),
-                };
-                (
-                    8,
-                    format!(
-                        "{} {} {}",
-                        e1.to_string_with_precedence(7),
-                        symbol,
-                        e2.to_string_with_precedence(8)
-                    ),
-                )
-            }
-            Plus(e1, e2) | Minus(e1, e2) => {
-                let symbol = if let Plus(_, _) = self { "+" } else { "-" };
-                (
-                    7,
-                    format!(
-                        "{} {} {}",
-                        e1.to_string_with_precedence(6),
-                        symbol,
-                        e2.to_string_with_precedence(7)
-                    ),
-                )
-            }
-            Cons(e1, e2) => (
-                6,
-                format!(
-                    "{} :: {}",
-                    e1.to_string_with_precedence(6),
-                    e2.to_string_with_precedence(5)
-                ),
-            ),
-            Equal(e1, e2) | Less(e1, e2) => {
-                let symbol = if let Equal(_, _) = self { "=" } else { "<" };
-                (
-                    5,
-                    format!(
-                        "{} {} {}",
-                        e1.to_string_with_precedence(5),
-                        symbol,
-                        e2.to_string_with_precedence(5)
-                    ),
-                )
-            }
-            If(e1, e2, e3) => (
-                4,
-                format!(
-                    "if {} then {} else {}",
-                    e1.to_string_with_precedence(4),
-                    e2.to_string_with_precedence(4),
-                    e3.to_string_with_precedence(4)
-                ),
-            ),
-            Match(e1, ty, e2, x, y, e3) => (
-                3,
-                format!(
-                    "match {} with [{}] -> {} | {}::{} -> {}",
-                    e1.to_string_with_precedence(3),
-                    ty.to_string(),
-                    e2.to_string_with_precedence(3),
-                    x,
-                    y,
-                    e3.to_string_with_precedence(3)
-                ),
-            ),
-            Func(x, ty, e) => (
-                2,
-                format!(
-                    "fun {} : {} -> {}",
-                    x,
-                    ty.to_string(),
-                    e.to_string_with_precedence(0)
-                ),
-            ),
-            Recursion(x, ty, e) => (
-                1,
-                format!(
-                    "rec {} : {} is {}",
-                    x,
-                    ty.to_string(),
-                    e.to_string_with_precedence(0)
-                ),
-            ),
-        };
-
-        if inner_precedence > outer_precedence {
-            result
-        } else {
-            format!("({})", result)
-        }
+    /// Whether `self` and `other` are the same but for the names of bound
+    /// variables and for spans, so that `fun x : int => x` and
+    /// `fun y : int => y` are.
+    pub fn alpha_eq(&self, other: &Expr) -> bool {
+        Core::from_expr_unspanned(self) == Core::from_expr_unspanned(other)
     }
 
-    pub fn to_string(&self) -> String {
-        self.to_string_with_precedence(-1)
+    /// The expression without any `Spanned` wrappers around it.
+    pub fn unspanned(&self) -> &Expr {
+        match self {
+            Expr::Spanned(_, e) => e.unspanned(),
+            e => e,
+        }
     }
 
     pub fn subst(substitutions: &Vec<(String, Expr)>, expr: &Expr) -> Expr {
@@ -236,7 +333,8 @@ impl Expr {
                 .find(|(var_name, _)| var_name == x)
                 .map(|(_, expr)| expr.clone())
                 .unwrap_or_else(|| expr.clone()),
-            Int(_) | Bool(_) | None(_) => expr.clone(),
+            Unit | Int(_) | Float(_) | Bool(_) | Str(_) | Char(_) | None(_) => expr.clone(),
+            Negate(e) => Self::Negate(Box::new(Self::subst(substitutions, e))),
             Mult(e1, e2) => Self::Mult(
                 Box::new(Self::subst(substitutions, e1)),
                 Box::new(Self::subst(substitutions, e2)),
@@ -265,6 +363,14 @@ impl Expr {
                 Box::new(Self::subst(substitutions, e1)),
                 Box::new(Self::subst(substitutions, e2)),
             ),
+            Concat(e1, e2) => Self::Concat(
+                Box::new(Self::subst(substitutions, e1)),
+                Box::new(Self::subst(substitutions, e2)),
+            ),
+            Index(e1, e2) => Self::Index(
+                Box::new(Self::subst(substitutions, e1)),
+                Box::new(Self::subst(substitutions, e2)),
+            ),
             If(e1, e2, e3) => Self::If(
                 Box::new(Self::subst(substitutions, e1)),
                 Box::new(Self::subst(substitutions, e2)),
@@ -286,12 +392,14 @@ impl Expr {
                 Box::new(Self::subst(substitutions, e1)),
                 Box::new(Self::subst(substitutions, e2)),
             ),
-            Pair(e1, e2) => Self::Pair(
-                Box::new(Self::subst(substitutions, e1)),
-                Box::new(Self::subst(substitutions, e2)),
+            Tuple(es) => Self::Tuple(es.iter().map(|e| Self::subst(substitutions, e)).collect()),
+            Project(e, i) => Self::Project(Box::new(Self::subst(substitutions, e)), *i),
+            Record(fields) => Self::Record(Self::subst_fields(substitutions, fields)),
+            Field(e, x) => Self::Field(Box::new(Self::subst(substitutions, e)), x.clone()),
+            Update(e, fields) => Self::Update(
+                Box::new(Self::subst(substitutions, e)),
+                Self::subst_fields(substitutions, fields),
             ),
-            First(e) => Self::First(Box::new(Self::subst(substitutions, e))),
-            Second(e) => Self::Second(Box::new(Self::subst(substitutions, e))),
             Recursion(var, ty, e) => {
                 let filtered_subs = substitutions
                     .iter()
@@ -304,6 +412,20 @@ impl Expr {
                     Box::new(Self::subst(&filtered_subs, e)),
                 )
             }
+            LetRec(bindings, e) => {
+                let filtered_subs = substitutions
+                    .iter()
+                    .filter(|(var_name, _)| bindings.iter().all(|(x, _, _)| x != var_name))
+                    .cloned()
+                    .collect();
+                Self::LetRec(
+                    bindings
+                        .iter()
+                        .map(|(x, ty, e)| (x.clone(), ty.clone(), Self::subst(&filtered_subs, e)))
+                        .collect(),
+                    Box::new(Self::subst(&filtered_subs, e)),
+                )
+            }
             Cons(e1, e2) => Self::Cons(
                 Box::new(Self::subst(substitutions, e1)),
                 Box::new(Self::subst(substitutions, e2)),
@@ -323,6 +445,119 @@ impl Expr {
                     Box::new(Self::subst(&filtered_subs, e3)),
                 )
             }
+            Seq(e1, e2) => Self::Seq(
+                Box::new(Self::subst(substitutions, e1)),
+                Box::new(Self::subst(substitutions, e2)),
+            ),
+            Raise(e, ty) => Self::Raise(Box::new(Self::subst(substitutions, e)), ty.clone()),
+            Try(e, handlers) => Self::Try(
+                Box::new(Self::subst(substitutions, e)),
+                handlers
+                    .iter()
+                    .map(|(pattern, handler)| {
+                        let filtered_subs = substitutions
+                            .iter()
+                            .filter(|(var_name, _)| Some(var_name) != pattern.binder())
+                            .cloned()
+                            .collect();
+                        (pattern.clone(), Self::subst(&filtered_subs, handler))
+                    })
+                    .collect(),
+            ),
+            Ref(e) => Self::Ref(Box::new(Self::subst(substitutions, e))),
+            Deref(e) => Self::Deref(Box::new(Self::subst(substitutions, e))),
+            Assign(e1, e2) => Self::Assign(
+                Box::new(Self::subst(substitutions, e1)),
+                Box::new(Self::subst(substitutions, e2)),
+            ),
+            Spanned(span, e) => {
+                Self::Spanned(span.clone(), Box::new(Self::subst(substitutions, e)))
+            }
+        }
+    }
+
+    fn subst_fields(
+        substitutions: &Vec<(String, Expr)>,
+        fields: &[(String, Expr)],
+    ) -> Vec<(String, Expr)> {
+        fields
+            .iter()
+            .map(|(x, e)| (x.clone(), Self::subst(substitutions, e)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toplevel::parse_expr;
+
+    fn alpha_eq(a: &str, b: &str) -> bool {
+        parse_expr(a).unwrap().alpha_eq(&parse_expr(b).unwrap())
+    }
+
+    #[test]
+    fn compares_every_variant_structurally() {
+        assert_eq!(Expr::Bool(true), Expr::Bool(true));
+        assert_eq!(Expr::Var("x".to_string()), Expr::Var("x".to_string()));
+        assert_ne!(Expr::Var("x".to_string()), Expr::Var("y".to_string()));
+        for source in [
+            "fun x : int => if x < 1 then \"a\" else 'b' :: [char]",
+            "{ r with x = (1.5, ()) }.x; !r := #1 (ref s.[0])",
+            "try raise Failure \"f\" : int with | Failure m -> 0 | _ -> 1",
+            "let rec f : int -> int = rec g : int -> int is f and h : int = 0 in f",
+            "match l with [int] -> -1 | x :: xs -> x * 2 / 3 % 4 - 5 + 6",
+        ] {
+            assert_eq!(parse_expr(source), parse_expr(source));
         }
+        assert_ne!(
+            parse_expr("fun x : int => x"),
+            parse_expr("fun x : bool => x")
+        );
+        // Spans take part, so moving the text changes the expression.
+        assert_ne!(parse_expr("x + 1"), parse_expr(" x + 1"));
+        assert!(alpha_eq("x + 1", " x + 1"));
+    }
+
+    #[test]
+    fn compares_up_to_the_names_of_bound_variables() {
+        assert!(alpha_eq("fun x : int => x", "fun y : int => y"));
+        assert!(alpha_eq("fun x : int => x", "(fun x : int =>\n x)"));
+        assert!(alpha_eq(
+            "fun x : int => fun y : int => x",
+            "fun y : int => fun x : int => y"
+        ));
+        assert!(!alpha_eq(
+            "fun x : int => fun y : int => x",
+            "fun a : int => fun b : int => b"
+        ));
+        assert!(!alpha_eq("fun x : int => y", "fun y : int => y"));
+        assert!(!alpha_eq("fun x : int => x", "fun x : bool => x"));
+        assert!(!alpha_eq("x", "y"));
+        assert!(alpha_eq(
+            "match l with [int] -> 0 | x :: xs -> x + length xs",
+            "match l with [int] -> 0 | y :: ys -> y + length ys"
+        ));
+        assert!(!alpha_eq(
+            "match l with [int] -> 0 | x :: xs -> x",
+            "match l with [int] -> 0 | xs :: x -> x"
+        ));
+        assert!(alpha_eq(
+            "try f 1 with | Failure m -> m | e -> \"\"",
+            "try f 1 with | Failure s -> s | x -> \"\""
+        ));
+        assert!(!alpha_eq(
+            "try f 1 with Failure m -> m",
+            "try f 1 with Not_found -> m"
+        ));
+        assert!(alpha_eq(
+            "let rec f : int = g and g : int = f in f",
+            "let rec a : int = b and b : int = a in a"
+        ));
+        assert!(!alpha_eq(
+            "let rec f : int = g and g : int = f in f",
+            "let rec a : int = a and b : int = a in a"
+        ));
+        assert!(alpha_eq("rec f : int is f", "rec g : int is g"));
     }
 }
@@ -0,0 +1,285 @@
+//! Conversions between Rust values and flock values, for passing data in and
+//! out of an embedded interpreter.
+
+use crate::ast::Type;
+use crate::eval::{RuntimeError, RuntimeErrorKind, Thunk, Value};
+use crate::num::{self, Integer};
+
+/// Rust types with a counterpart among flock types.
+pub trait FlockType {
+    fn flock_type() -> Type;
+}
+
+pub trait IntoValue: FlockType {
+    fn into_value(self) -> Value;
+}
+
+/// Conversion out of flock values. Only the forced parts of a value are
+/// looked at, so it has to be evaluated fully first.
+pub trait FromValue: FlockType + Sized {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError>;
+}
+
+fn mismatch<T>(expected: &'static str) -> Result<T, RuntimeError> {
+    Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(expected)))
+}
+
+fn forced(thunk: &Thunk) -> Result<Value, RuntimeError> {
+    thunk.value().map_or_else(|| mismatch("a forced value"), Ok)
+}
+
+impl FlockType for () {
+    fn flock_type() -> Type {
+        Type::Unit
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Unit
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Unit => Ok(()),
+            _ => mismatch("unit"),
+        }
+    }
+}
+
+impl FlockType for bool {
+    fn flock_type() -> Type {
+        Type::Bool
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => mismatch("a boolean"),
+        }
+    }
+}
+
+impl FlockType for i64 {
+    fn flock_type() -> Type {
+        Type::Int
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Int(Integer::from(self))
+    }
+}
+
+/// Fails with an overflow for integers that do not fit.
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Int(n) => {
+                num::to_i64(n).ok_or_else(|| RuntimeError::new(RuntimeErrorKind::Overflow))
+            }
+            _ => mismatch("an integer"),
+        }
+    }
+}
+
+impl FlockType for f64 {
+    fn flock_type() -> Type {
+        Type::Float
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Float(x) => Ok(*x),
+            _ => mismatch("a float"),
+        }
+    }
+}
+
+impl FlockType for char {
+    fn flock_type() -> Type {
+        Type::Char
+    }
+}
+
+impl IntoValue for char {
+    fn into_value(self) -> Value {
+        Value::Char(self)
+    }
+}
+
+impl FromValue for char {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Char(c) => Ok(*c),
+            _ => mismatch("a character"),
+        }
+    }
+}
+
+impl FlockType for String {
+    fn flock_type() -> Type {
+        Type::String
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Str(s) => Ok(s.to_string()),
+            _ => mismatch("a string"),
+        }
+    }
+}
+
+impl FlockType for &str {
+    fn flock_type() -> Type {
+        Type::String
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+/// Vectors are lists.
+impl<T: FlockType> FlockType for Vec<T> {
+    fn flock_type() -> Type {
+        Type::List(Box::new(T::flock_type()))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        self.into_iter().rev().fold(Value::Nil, |tail, x| {
+            Value::Cons(Thunk::forced(x.into_value()), Thunk::forced(tail))
+        })
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        let mut items = Vec::new();
+        let mut value = value.clone();
+        loop {
+            match value {
+                Value::Nil => return Ok(items),
+                Value::Cons(head, tail) => {
+                    items.push(T::from_value(&forced(&head)?)?);
+                    value = forced(&tail)?;
+                }
+                _ => return mismatch("a list"),
+            }
+        }
+    }
+}
+
+macro_rules! tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: FlockType),+> FlockType for ($($t,)+) {
+            fn flock_type() -> Type {
+                Type::Tuple(vec![$($t::flock_type()),+])
+            }
+        }
+
+        impl<$($t: IntoValue),+> IntoValue for ($($t,)+) {
+            fn into_value(self) -> Value {
+                Value::Tuple(vec![$(Thunk::forced(self.$i.into_value())),+])
+            }
+        }
+
+        impl<$($t: FromValue),+> FromValue for ($($t,)+) {
+            fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+                match value {
+                    Value::Tuple(thunks) if thunks.len() == [$($i),+].len() => {
+                        Ok(($($t::from_value(&forced(&thunks[$i])?)?,)+))
+                    }
+                    _ => mismatch("a tuple"),
+                }
+            }
+        }
+    };
+}
+
+tuple!(A 0, B 1);
+tuple!(A 0, B 1, C 2);
+tuple!(A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: IntoValue + FromValue + Clone + PartialEq + std::fmt::Debug>(x: T) {
+        assert_eq!(T::from_value(&x.clone().into_value()), Ok(x));
+    }
+
+    #[test]
+    fn converts_values_both_ways() {
+        round_trip(());
+        round_trip(true);
+        round_trip(-42i64);
+        round_trip(1.5f64);
+        round_trip('λ');
+        round_trip("flock".to_string());
+        round_trip(vec![(1i64, vec!['a']), (2, vec![])]);
+        round_trip((1i64, false, "x".to_string(), vec![0.5f64]));
+    }
+
+    #[test]
+    fn maps_rust_types_to_flock_types() {
+        assert_eq!(
+            <Vec<(i64, bool)>>::flock_type().to_string(),
+            "(Int * Bool) List"
+        );
+        assert_eq!(<&str>::flock_type(), Type::String);
+    }
+
+    #[test]
+    fn rejects_values_of_other_types() {
+        let kind = |err: RuntimeError| err.kind;
+        assert_eq!(
+            bool::from_value(&Value::Unit).map_err(kind),
+            Err(RuntimeErrorKind::TypeMismatch("a boolean"))
+        );
+        assert_eq!(
+            <(i64, i64)>::from_value(&(1i64, 2i64, 3i64).into_value()).map_err(kind),
+            Err(RuntimeErrorKind::TypeMismatch("a tuple"))
+        );
+        let unforced = Value::Cons(Thunk::forced(Value::Int(Integer::from(1))), lazy());
+        assert_eq!(
+            <Vec<i64>>::from_value(&unforced).map_err(kind),
+            Err(RuntimeErrorKind::TypeMismatch("a forced value"))
+        );
+    }
+
+    fn lazy() -> Thunk {
+        Thunk::delayed(Default::default(), crate::ast::Expr::Unit)
+    }
+}
@@ -0,0 +1,153 @@
+use crate::analysis::Warning;
+use crate::ast::Span;
+use crate::eval::RuntimeError;
+use crate::lexer::LexError;
+use crate::typecheck::TypeError;
+use ariadne::{Config, Label, Report, ReportKind, Source};
+use std::io::{self, IsTerminal};
+
+/// A message about a location in the source, rendered with `ariadne`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    pub label: Option<String>,
+    /// Whether this is only a warning, which does not stop anything from
+    /// running.
+    pub warning: bool,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            span: None,
+            label: None,
+            warning: false,
+        }
+    }
+
+    pub fn as_warning(mut self) -> Self {
+        self.warning = true;
+        self
+    }
+
+    pub fn with_span(mut self, span: Option<Span>) -> Self {
+        self.span = span;
+        self
+    }
+
+    pub fn with_label(mut self, label: impl ToString) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// Renders the diagnostic for `source`, which is called `name` in the
+    /// output.
+    pub fn write(
+        &self,
+        name: &str,
+        source: &str,
+        color: bool,
+        out: impl io::Write,
+    ) -> io::Result<()> {
+        let span = self.span.clone().unwrap_or(source.len()..source.len());
+        let mut label = Label::new((name, span.clone()));
+        if let Some(text) = &self.label {
+            label = label.with_message(text);
+        }
+        let kind = if self.warning {
+            ReportKind::Warning
+        } else {
+            ReportKind::Error
+        };
+        Report::build(kind, name, span.start)
+            .with_config(Config::default().with_color(color))
+            .with_message(&self.message)
+            .with_label(label)
+            .finish()
+            .write((name, Source::from(source)), out)
+    }
+
+    /// Renders the diagnostic to stderr, in colour if that is a terminal
+    /// and `NO_COLOR` is not set.
+    pub fn eprint(&self, name: &str, source: &str) {
+        let stderr = io::stderr();
+        let color = use_color(
+            stderr.is_terminal(),
+            std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+        );
+        // Failing to write to stderr leaves us with nowhere to report it.
+        let _ = self.write(name, source, color, stderr);
+    }
+}
+
+/// Whether to colour output to a terminal or not, following
+/// <https://no-color.org>.
+fn use_color(terminal: bool, no_color: bool) -> bool {
+    terminal && !no_color
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Self {
+        Diagnostic::new(format!("runtime error: {}", err))
+            .with_span(err.span.clone())
+            .with_label("evaluation failed here")
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Diagnostic::new(warning)
+            .with_span(warning.span.clone())
+            .with_label("in this expression")
+            .as_warning()
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Self {
+        Diagnostic::new(format!("lexical error: {}", err))
+            .with_span(Some(err.span.clone()))
+            .with_label("not a valid token")
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(err: &TypeError) -> Self {
+        Diagnostic::new(format!("type error: {}", err))
+            .with_span(err.span.clone())
+            .with_label("in this expression")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::RuntimeErrorKind;
+
+    #[test]
+    fn renders_runtime_errors_at_their_span() {
+        let source = "10 / (2 - 2)";
+        let err = RuntimeError {
+            kind: RuntimeErrorKind::DivisionByZero,
+            span: Some(0..12),
+        };
+        let mut out = Vec::new();
+        Diagnostic::from(&err)
+            .write("input", source, false, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("runtime error: division by zero"));
+        assert!(out.contains("input:1:1"));
+        assert!(out.contains("evaluation failed here"));
+        assert!(!out.contains('\x1b'));
+    }
+
+    #[test]
+    fn colours_only_terminals_without_no_color() {
+        assert!(use_color(true, false));
+        assert!(!use_color(true, true));
+        assert!(!use_color(false, false));
+    }
+}
@@ -0,0 +1,189 @@
+//! Line editing for the REPL: when input makes up complete commands, and
+//! what a word can be completed to.
+
+use crate::lexer::{self, LexErrorKind, Token};
+use crate::toplevel::COMMANDS;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+const KEYWORDS: &[&str] = &[
+    "and", "bool", "char", "else", "exn", "false", "float", "fst", "fun", "if", "import", "in",
+    "int", "is", "let", "list", "match", "raise", "rec", "ref", "snd", "string", "then", "true",
+    "try", "type", "unit", "with",
+];
+
+/// How much of a command has been typed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Pending {
+    /// Ready to run, or to report as an error: either a toplevel command like
+    /// `:type e`, which fits on one line, or input ending with `;;`, or input
+    /// that no more lines could fix, like an unmatched `)`.
+    Complete,
+    /// Still to be continued, inside the innermost `bracket` left open if
+    /// there is one.
+    Incomplete { bracket: Option<char> },
+}
+
+/// How much of a command `text` is.
+pub fn pending(text: &str) -> Pending {
+    if text.trim_start().starts_with(':') {
+        return Pending::Complete;
+    }
+    let tokens = match lexer::lex(text) {
+        Ok(tokens) => tokens,
+        // Block comments are the only tokens that can span lines.
+        Err(err) if err.kind == LexErrorKind::UnterminatedComment => {
+            return Pending::Incomplete { bracket: None }
+        }
+        Err(_) => return Pending::Complete,
+    };
+    let mut open = Vec::new();
+    for (token, _) in &tokens {
+        match token {
+            Token::LParen => open.push('('),
+            Token::LSquareBrack | Token::DotLSquareBrack => open.push('['),
+            Token::LBrace => open.push('{'),
+            Token::RParen | Token::RSquareBrack | Token::RBrace => {
+                let Some(_) = open.pop() else {
+                    return Pending::Complete;
+                };
+            }
+            // Commands cannot continue past `;;`, even with brackets open.
+            Token::DoubleSemicolon => open.clear(),
+            _ => {}
+        }
+    }
+    match tokens.last() {
+        Some((Token::DoubleSemicolon, _)) => Pending::Complete,
+        _ => Pending::Incomplete {
+            bracket: open.last().copied(),
+        },
+    }
+}
+
+/// Where the word before `pos` in `line` starts, and what it can be
+/// completed to: a toplevel command at the start of the line, or else a
+/// keyword or one of `names`.
+pub fn complete(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '\'' || c == '.'))
+        .map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, char::len_utf8)
+        });
+    let word = &before[start..];
+    let candidates: Vec<&str> = if before[..start].trim_start() == ":" {
+        COMMANDS.to_vec()
+    } else if word.is_empty() {
+        Vec::new()
+    } else {
+        KEYWORDS
+            .iter()
+            .copied()
+            .chain(names.iter().map(String::as_str))
+            .collect()
+    };
+    let mut words: Vec<String> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(str::to_string)
+        .collect();
+    words.sort();
+    words.dedup();
+    (start, words)
+}
+
+/// Completes keywords, toplevel commands and the names defined in the
+/// session, which have to be kept up to date in `names`.
+#[derive(Default)]
+pub struct Completions {
+    pub names: Vec<String>,
+}
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, words) = complete(line, pos, &self.names);
+        let pairs = words
+            .into_iter()
+            .map(|word| Pair {
+                display: word.clone(),
+                replacement: word,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_double_semicolons() {
+        let incomplete = |bracket| Pending::Incomplete { bracket };
+        assert_eq!(pending("1 + 2;;\n"), Pending::Complete);
+        assert_eq!(pending(":type 1 + 2\n"), Pending::Complete);
+        assert_eq!(pending("  :env\n"), Pending::Complete);
+        assert_eq!(pending("let x = 1\n"), incomplete(None));
+        assert_eq!(pending("let x = (1,\n [int\n"), incomplete(Some('[')));
+        assert_eq!(pending("s.[0] + (f {\n} 1)\n"), incomplete(None));
+        assert_eq!(pending("{- ;; \n"), incomplete(None));
+        assert_eq!(pending("{- ;; -} (1, [int]);;\n"), Pending::Complete);
+        assert_eq!(pending("\"a;;\" ^ \"b\"\n"), incomplete(None));
+        // No more lines could fix these, so they are reported right away.
+        assert_eq!(pending("(fun x : int => x;;\n"), Pending::Complete);
+        assert_eq!(pending("1)\n"), Pending::Complete);
+        assert_eq!(pending("\"unterminated\n"), Pending::Complete);
+    }
+
+    #[test]
+    fn completes_keywords_names_and_commands() {
+        let names = vec![
+            "map".to_string(),
+            "match_all".to_string(),
+            "Geometry.origin".to_string(),
+        ];
+        assert_eq!(
+            complete("ma", 2, &names),
+            (
+                0,
+                vec![
+                    "map".to_string(),
+                    "match".to_string(),
+                    "match_all".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            complete("f (Geo", 6, &names),
+            (3, vec!["Geometry.origin".to_string()])
+        );
+        assert_eq!(
+            complete(":re", 3, &names),
+            (1, vec!["reload".to_string(), "reset".to_string()])
+        );
+        assert_eq!(complete(":type ma", 8, &names).1.len(), 3);
+        assert_eq!(complete("1 + ", 4, &names), (4, vec![]));
+        assert_eq!(complete("λ mat", 6, &names).0, 3);
+    }
+}
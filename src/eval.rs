@@ -0,0 +1,1218 @@
+use crate::ast::*;
+use crate::ir::{Core, Handler};
+use crate::num::{self, Integer};
+use crate::prim::Primitive;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+/// How much native stack is kept free when evaluating further, for the
+/// primitives and the formatting of values that recurse without checking.
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// When the arguments of functions and the components of tuples, records and
+/// lists are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Call-by-need: evaluate on first use and remember the result. The
+    /// effects of an expression that is never used, such as an argument a
+    /// function ignores, never happen.
+    Lazy,
+    /// Call-by-value: evaluate before binding.
+    Strict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    ModuloByZero,
+    Overflow,
+    IndexOutOfBounds,
+    EndOfInput,
+    Io(String),
+    /// A primitive, named here, cannot handle its argument.
+    InvalidArgument(&'static str),
+    UnboundVariable(String),
+    /// A recursive definition needed its own value to compute it.
+    RecursiveValue,
+    /// Evaluation nested too deeply for the native stack.
+    StackOverflow,
+    /// An operation was applied to a value of the wrong shape, which only
+    /// happens to programs that were not type checked.
+    TypeMismatch(&'static str),
+    /// An exception raised with `raise` that no handler caught.
+    Raised(Exception),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// Span of the innermost source construct the failure happened in.
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+
+    fn at(mut self, span: &Span) -> Self {
+        self.span.get_or_insert_with(|| span.clone());
+        self
+    }
+
+    /// The exception a `try` handler sees for this error, or `None` if it
+    /// cannot be caught.
+    pub fn exception(&self) -> Option<Exception> {
+        Some(match &self.kind {
+            RuntimeErrorKind::DivisionByZero | RuntimeErrorKind::ModuloByZero => {
+                Exception::new("Division_by_zero", None)
+            }
+            RuntimeErrorKind::Overflow => Exception::new("Overflow", None),
+            RuntimeErrorKind::IndexOutOfBounds => {
+                Exception::new("Invalid_argument", Some("index out of bounds"))
+            }
+            RuntimeErrorKind::InvalidArgument(name) => {
+                Exception::new("Invalid_argument", Some(name))
+            }
+            RuntimeErrorKind::EndOfInput => Exception::new("End_of_file", None),
+            RuntimeErrorKind::Io(message) => Exception::new("Sys_error", Some(message)),
+            RuntimeErrorKind::Raised(exn) => exn.clone(),
+            RuntimeErrorKind::UnboundVariable(_)
+            | RuntimeErrorKind::RecursiveValue
+            | RuntimeErrorKind::StackOverflow
+            | RuntimeErrorKind::TypeMismatch(_) => return None,
+        })
+    }
+}
+
+/// An exception: one of the constructors in `prim::EXCEPTIONS` and its
+/// argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exception {
+    pub name: String,
+    pub arg: Option<String>,
+}
+
+impl Exception {
+    pub fn new(name: &str, arg: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            arg: arg.map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.arg {
+            Some(arg) => write!(f, "{} {}", self.name, quote(arg, '"')),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorKind::ModuloByZero => write!(f, "modulo by zero"),
+            RuntimeErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            RuntimeErrorKind::IndexOutOfBounds => write!(f, "string index out of bounds"),
+            RuntimeErrorKind::EndOfInput => write!(f, "end of input"),
+            RuntimeErrorKind::Io(message) => write!(f, "I/O error: {}", message),
+            RuntimeErrorKind::InvalidArgument(name) => write!(f, "invalid argument to `{}`", name),
+            RuntimeErrorKind::UnboundVariable(x) => write!(f, "unbound variable `{}`", x),
+            RuntimeErrorKind::RecursiveValue => {
+                write!(f, "recursive value forced during its own definition")
+            }
+            RuntimeErrorKind::StackOverflow => write!(f, "stack overflow: recursion too deep"),
+            RuntimeErrorKind::TypeMismatch(expected) => write!(f, "expected {}", expected),
+            RuntimeErrorKind::Raised(exn) => write!(f, "uncaught exception {}", exn),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Unit,
+    Int(Integer),
+    Float(f64),
+    Bool(bool),
+    Str(Rc<str>),
+    Char(char),
+    /// A function and the environment its body is evaluated in, which binds
+    /// the argument as local variable 0.
+    Closure(Env, Core),
+    Primitive(Rc<Primitive>),
+    Tuple(Vec<Thunk>),
+    /// Fields sorted by name, like in `Type::Record`.
+    Record(Vec<(String, Thunk)>),
+    Nil,
+    Cons(Thunk, Thunk),
+    /// A location in the evaluator's store.
+    Ref(usize),
+    Exn(Rc<Exception>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", quote(s, '"')),
+            Value::Char(c) => write!(f, "{}", quote(&c.to_string(), '\'')),
+            Value::Closure(_, _) | Value::Primitive(_) => write!(f, "<fun>"),
+            Value::Ref(_) => write!(f, "<ref>"),
+            Value::Exn(exn) => write!(f, "{}", exn),
+            Value::Tuple(thunks) => {
+                let thunks: Vec<_> = thunks.iter().map(Thunk::to_string).collect();
+                write!(f, "({})", thunks.join(", "))
+            }
+            Value::Record(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(x, thunk)| format!("{} = {}", x, thunk))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join("; "))
+            }
+            Value::Nil => write!(f, "[]"),
+            Value::Cons(head, tail) => {
+                write!(f, "[{}", head)?;
+                let mut tail = tail.clone();
+                loop {
+                    let next = match &*tail.0.borrow() {
+                        ThunkState::Forced(Value::Cons(head, tail)) => {
+                            write!(f, ", {}", head)?;
+                            tail.clone()
+                        }
+                        ThunkState::Forced(Value::Nil) => break,
+                        _ => return write!(f, ", ...]"),
+                    };
+                    tail = next;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ThunkState {
+    Delayed(Env, Core),
+    /// Being evaluated, or not yet given its expression by the recursive
+    /// definition it belongs to. Forcing it again is an error.
+    Forcing,
+    Forced(Value),
+}
+
+/// A possibly unevaluated expression together with the environment it has
+/// to be evaluated in. Forcing a thunk overwrites it with its value.
+#[derive(Debug, Clone)]
+pub struct Thunk(Rc<RefCell<ThunkState>>);
+
+impl Thunk {
+    /// A thunk for `expr`, whose free variables are looked up by name in
+    /// `env`.
+    pub fn delayed(env: Env, expr: Expr) -> Self {
+        Self::suspended(env, Core::from_expr(&expr))
+    }
+
+    fn suspended(env: Env, core: Core) -> Self {
+        Thunk(Rc::new(RefCell::new(ThunkState::Delayed(env, core))))
+    }
+
+    pub fn forced(value: Value) -> Self {
+        Thunk(Rc::new(RefCell::new(ThunkState::Forced(value))))
+    }
+
+    /// A thunk for a recursive definition, to be given its expression once
+    /// the environment binding it exists.
+    fn pending() -> Self {
+        Thunk(Rc::new(RefCell::new(ThunkState::Forcing)))
+    }
+
+    /// The value of the thunk, if it has been forced.
+    pub fn value(&self) -> Option<Value> {
+        match &*self.0.borrow() {
+            ThunkState::Forced(value) => Some(value.clone()),
+            ThunkState::Delayed(_, _) | ThunkState::Forcing => None,
+        }
+    }
+}
+
+impl fmt::Display for Thunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.0.borrow() {
+            ThunkState::Forced(value) => write!(f, "{}", value),
+            ThunkState::Delayed(_, _) | ThunkState::Forcing => write!(f, "?"),
+        }
+    }
+}
+
+/// Immutable variable bindings; extending an environment shares the tail.
+/// Global variables are bound by name and local ones by de Bruijn index.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    globals: Bindings<(String, Thunk)>,
+    locals: Bindings<Thunk>,
+}
+
+#[derive(Debug)]
+struct Bindings<T>(Option<Rc<(T, Bindings<T>)>>);
+
+impl<T> Clone for Bindings<T> {
+    fn clone(&self) -> Self {
+        Bindings(self.0.clone())
+    }
+}
+
+impl<T> Default for Bindings<T> {
+    fn default() -> Self {
+        Bindings(None)
+    }
+}
+
+impl<T> Bindings<T> {
+    fn push(&self, binding: T) -> Self {
+        Bindings(Some(Rc::new((binding, self.clone()))))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        std::iter::successors(self.0.as_deref(), |(_, rest)| rest.0.as_deref())
+            .map(|(binding, _)| binding)
+    }
+}
+
+impl Env {
+    /// Binds the global variable `name`.
+    pub fn extend(&self, name: String, thunk: Thunk) -> Self {
+        Env {
+            globals: self.globals.push((name, thunk)),
+            locals: self.locals.clone(),
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Thunk> {
+        self.globals
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, thunk)| thunk)
+    }
+
+    /// Binds a new local variable, which becomes number 0.
+    fn push(&self, thunk: Thunk) -> Self {
+        Env {
+            globals: self.globals.clone(),
+            locals: self.locals.push(thunk),
+        }
+    }
+
+    fn local(&self, i: usize) -> Option<&Thunk> {
+        self.locals.iter().nth(i)
+    }
+}
+
+/// Output kept in memory, for inspecting what a program printed. Clones
+/// share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Evaluator {
+    pub strategy: Strategy,
+    /// Where the input primitives read from, or standard input if `None`.
+    /// Standard input is only locked while reading, so the REPL can share it.
+    input: Option<Box<dyn BufRead>>,
+    output: Box<dyn Write>,
+    /// The contents of every reference allocated so far. Cells are never
+    /// freed.
+    store: Vec<Value>,
+}
+
+impl Evaluator {
+    /// An evaluator doing I/O on standard input and output.
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            input: None,
+            output: Box::new(io::stdout()),
+            store: Vec::new(),
+        }
+    }
+
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Reads a line without its terminator, failing at the end of the input.
+    pub fn read_line(&mut self) -> Result<String, RuntimeError> {
+        let mut line = String::new();
+        let read = match &mut self.input {
+            Some(input) => input.read_line(&mut line),
+            None => io::stdin().lock().read_line(&mut line),
+        };
+        match read {
+            Ok(0) => Err(RuntimeError::new(RuntimeErrorKind::EndOfInput)),
+            Ok(_) => Ok(line.trim_end_matches(['\n', '\r']).to_string()),
+            Err(err) => Err(RuntimeError::new(RuntimeErrorKind::Io(err.to_string()))),
+        }
+    }
+
+    /// Writes `text` and flushes it, so that output appears in evaluation
+    /// order with whatever the toplevel prints.
+    pub fn write(&mut self, text: &str) -> Result<(), RuntimeError> {
+        self.output
+            .write_all(text.as_bytes())
+            .and_then(|()| self.output.flush())
+            .map_err(|err| RuntimeError::new(RuntimeErrorKind::Io(err.to_string())))
+    }
+
+    /// Evaluates `expr`, whose free variables are looked up by name in `env`.
+    pub fn eval(&mut self, env: &Env, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.evaluate(env, &Core::from_expr(expr))
+    }
+
+    // `evaluate` only dispatches, leaving the work to one method per
+    // construct: in unoptimized builds every temporary gets its own stack
+    // slot, and keeping them out of this frame lets deep recursion go much
+    // further.
+    fn evaluate(&mut self, env: &Env, expr: &Core) -> Result<Value, RuntimeError> {
+        use Core::*;
+        if stacker::remaining_stack().is_some_and(|left| left < STACK_RED_ZONE) {
+            return Err(RuntimeError::new(RuntimeErrorKind::StackOverflow));
+        }
+        match expr {
+            Unit => Ok(Value::Unit),
+            Local(i) => self.local(env, *i),
+            Global(x) => self.variable(env, x),
+            #[allow(clippy::clone_on_copy)] // `Integer` is only `Copy` without `bigint`
+            Int(n) => Ok(Value::Int(n.clone())),
+            Float(x) => Ok(Value::Float(*x)),
+            Bool(b) => Ok(Value::Bool(*b)),
+            Str(s) => Ok(Value::Str(s.as_str().into())),
+            Char(c) => Ok(Value::Char(*c)),
+            Negate(e) => self.negate(env, e),
+            Mult(e1, e2)
+            | Divide(e1, e2)
+            | Mod(e1, e2)
+            | Plus(e1, e2)
+            | Minus(e1, e2)
+            | Equal(e1, e2)
+            | Less(e1, e2)
+            | Concat(e1, e2)
+            | Index(e1, e2) => self.binary(env, expr, e1, e2),
+            Seq(e1, e2) => self.sequence(env, e1, e2),
+            Ref(e) => self.allocate(env, e),
+            Deref(e) => self.dereference(env, e),
+            Assign(e1, e2) => self.assign(env, e1, e2),
+            Raise(e, _) => self.raise(env, e),
+            Try(e, handlers) => self.handle(env, e, handlers),
+            If(e1, e2, e3) => self.branch(env, e1, e2, e3),
+            Func(_, e) => Ok(Value::Closure(env.clone(), (**e).clone())),
+            Apply(e1, e2) => self.apply(env, e1, e2),
+            Tuple(es) => self.tuple(env, es),
+            Project(e, i) => self.project(env, e, *i),
+            Record(fields) => self.record(env, fields),
+            Field(e, x) => self.field(env, e, x),
+            Update(e, fields) => self.update(env, e, fields),
+            Recursion(_, e) => self.recursion(env, e),
+            LetRec(bindings, e) => self.let_rec(env, bindings, e),
+            None(_) => Ok(Value::Nil),
+            Cons(e1, e2) => self.cons(env, e1, e2),
+            Match(e1, _, e2, e3) => self.match_list(env, e1, e2, e3),
+            Spanned(span, e) => self.evaluate(env, e).map_err(|err| err.at(span)),
+        }
+    }
+
+    fn variable(&mut self, env: &Env, x: &str) -> Result<Value, RuntimeError> {
+        let thunk = env
+            .lookup(x)
+            .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::UnboundVariable(x.to_string())))?;
+        self.force(thunk)
+    }
+
+    fn local(&mut self, env: &Env, i: usize) -> Result<Value, RuntimeError> {
+        let thunk = env.local(i).ok_or_else(|| {
+            RuntimeError::new(RuntimeErrorKind::UnboundVariable(format!("#{}", i)))
+        })?;
+        self.force(thunk)
+    }
+
+    fn negate(&mut self, env: &Env, e: &Core) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e)? {
+            Value::Int(n) => num::checked_neg(&n)
+                .map(Value::Int)
+                .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::Overflow)),
+            Value::Float(x) => Ok(Value::Float(-x)),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a number",
+            ))),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        env: &Env,
+        op: &Core,
+        e1: &Core,
+        e2: &Core,
+    ) -> Result<Value, RuntimeError> {
+        let x = self.evaluate(env, e1)?;
+        let y = self.evaluate(env, e2)?;
+        binary(op, x, y)
+    }
+
+    fn sequence(&mut self, env: &Env, e1: &Core, e2: &Core) -> Result<Value, RuntimeError> {
+        self.evaluate(env, e1)?;
+        self.evaluate(env, e2)
+    }
+
+    // The contents of a reference are always evaluated, so that reading it
+    // never runs effects a second time.
+    fn allocate(&mut self, env: &Env, e: &Core) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(env, e)?;
+        self.store.push(value);
+        Ok(Value::Ref(self.store.len() - 1))
+    }
+
+    fn dereference(&mut self, env: &Env, e: &Core) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e)? {
+            Value::Ref(loc) => Ok(self.store[loc].clone()),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a reference",
+            ))),
+        }
+    }
+
+    fn assign(&mut self, env: &Env, e1: &Core, e2: &Core) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e1)? {
+            Value::Ref(loc) => {
+                self.store[loc] = self.evaluate(env, e2)?;
+                Ok(Value::Unit)
+            }
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a reference",
+            ))),
+        }
+    }
+
+    fn raise(&mut self, env: &Env, e: &Core) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e)? {
+            Value::Exn(exn) => Err(RuntimeError::new(RuntimeErrorKind::Raised((*exn).clone()))),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "an exception",
+            ))),
+        }
+    }
+
+    // Errors propagate out of nested applications and recursive calls through
+    // `?`, so unwinding only has to stop here. The value is forced in full
+    // first, so that the failures of lazy components are handled too.
+    fn handle(
+        &mut self,
+        env: &Env,
+        e: &Core,
+        handlers: &[(Handler, Core)],
+    ) -> Result<Value, RuntimeError> {
+        let value = self
+            .evaluate(env, e)
+            .and_then(|value| self.force_all(&value).map(|()| value));
+        let err = match value {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        let Some(exn) = err.exception() else {
+            return Err(err);
+        };
+        for (pattern, handler) in handlers {
+            let env = match pattern {
+                Handler::Constructor(name, _) if *name != exn.name => continue,
+                Handler::Constructor(_, true) => {
+                    let arg = exn.arg.as_deref().unwrap_or_default();
+                    env.push(Thunk::forced(Value::Str(arg.into())))
+                }
+                Handler::Any(true) => env.push(Thunk::forced(Value::Exn(Rc::new(exn)))),
+                Handler::Constructor(_, false) | Handler::Any(false) => env.clone(),
+            };
+            return self.evaluate(&env, handler);
+        }
+        Err(err)
+    }
+
+    fn branch(
+        &mut self,
+        env: &Env,
+        e1: &Core,
+        e2: &Core,
+        e3: &Core,
+    ) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e1)? {
+            Value::Bool(true) => self.evaluate(env, e2),
+            Value::Bool(false) => self.evaluate(env, e3),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a boolean",
+            ))),
+        }
+    }
+
+    fn apply(&mut self, env: &Env, e1: &Core, e2: &Core) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e1)? {
+            Value::Closure(closure_env, body) => {
+                let arg = self.suspend(env, e2)?;
+                self.evaluate(&closure_env.push(arg), &body)
+            }
+            Value::Primitive(prim) => {
+                let arg = self.evaluate(env, e2)?;
+                (prim.apply)(self, arg)
+            }
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a function",
+            ))),
+        }
+    }
+
+    fn tuple(&mut self, env: &Env, es: &[Core]) -> Result<Value, RuntimeError> {
+        let thunks = es
+            .iter()
+            .map(|e| self.suspend(env, e))
+            .collect::<Result<_, _>>()?;
+        Ok(Value::Tuple(thunks))
+    }
+
+    fn project(&mut self, env: &Env, e: &Core, i: usize) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e)? {
+            Value::Tuple(thunks) if i < thunks.len() => self.force(&thunks[i]),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch("a tuple"))),
+        }
+    }
+
+    /// Fields are evaluated in source order but stored sorted by name.
+    fn record(&mut self, env: &Env, fields: &[(String, Core)]) -> Result<Value, RuntimeError> {
+        let mut thunks = fields
+            .iter()
+            .map(|(x, e)| Ok((x.clone(), self.suspend(env, e)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+        thunks.sort_by(|(x, _), (y, _)| x.cmp(y));
+        Ok(Value::Record(thunks))
+    }
+
+    fn field(&mut self, env: &Env, e: &Core, x: &str) -> Result<Value, RuntimeError> {
+        let Value::Record(fields) = self.evaluate(env, e)? else {
+            return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a record",
+            )));
+        };
+        match fields.iter().find(|(y, _)| y == x) {
+            Some((_, thunk)) => self.force(thunk),
+            Option::None => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a record with that field",
+            ))),
+        }
+    }
+
+    fn update(
+        &mut self,
+        env: &Env,
+        e: &Core,
+        updates: &[(String, Core)],
+    ) -> Result<Value, RuntimeError> {
+        let Value::Record(mut fields) = self.evaluate(env, e)? else {
+            return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a record",
+            )));
+        };
+        for (x, e) in updates {
+            let thunk = self.suspend(env, e)?;
+            match fields.iter_mut().find(|(y, _)| y == x) {
+                Some((_, field)) => *field = thunk,
+                Option::None => {
+                    return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                        "a record with that field",
+                    )))
+                }
+            }
+        }
+        Ok(Value::Record(fields))
+    }
+
+    fn recursion(&mut self, env: &Env, e: &Core) -> Result<Value, RuntimeError> {
+        // Tie the knot: the body sees a thunk that evaluates the body.
+        let thunk = Thunk::pending();
+        let env = env.push(thunk.clone());
+        *thunk.0.borrow_mut() = ThunkState::Delayed(env, e.clone());
+        self.force(&thunk)
+    }
+
+    fn let_rec(
+        &mut self,
+        env: &Env,
+        bindings: &[(Type, Core)],
+        e: &Core,
+    ) -> Result<Value, RuntimeError> {
+        let bodies = bindings.iter().map(|(_, e)| e.clone()).collect();
+        let env = self.bind_group(env, bodies, |env, _, thunk| env.push(thunk))?;
+        self.evaluate(&env, e)
+    }
+
+    /// Extends `env` with a `let rec` group of global variables, whose bodies
+    /// all see the whole group. Strict evaluation evaluates the bodies in
+    /// order right away.
+    pub fn bind_recursive(&mut self, env: &Env, bindings: &[Binding]) -> Result<Env, RuntimeError> {
+        let bodies = bindings
+            .iter()
+            .map(|(_, _, e)| Core::from_expr(e))
+            .collect();
+        self.bind_group(env, bodies, |env, i, thunk| {
+            env.extend(bindings[i].0.clone(), thunk)
+        })
+    }
+
+    /// Binds a thunk for each of `bodies` with `bind`, evaluating the bodies
+    /// in the extended environment.
+    fn bind_group(
+        &mut self,
+        env: &Env,
+        bodies: Vec<Core>,
+        bind: impl Fn(&Env, usize, Thunk) -> Env,
+    ) -> Result<Env, RuntimeError> {
+        let thunks: Vec<_> = bodies.iter().map(|_| Thunk::pending()).collect();
+        let env = thunks
+            .iter()
+            .enumerate()
+            .fold(env.clone(), |env, (i, thunk)| bind(&env, i, thunk.clone()));
+        for (e, thunk) in bodies.into_iter().zip(&thunks) {
+            *thunk.0.borrow_mut() = ThunkState::Delayed(env.clone(), e);
+        }
+        if self.strategy == Strategy::Strict {
+            for thunk in &thunks {
+                self.force(thunk)?;
+            }
+        }
+        Ok(env)
+    }
+
+    fn cons(&mut self, env: &Env, e1: &Core, e2: &Core) -> Result<Value, RuntimeError> {
+        Ok(Value::Cons(self.suspend(env, e1)?, self.suspend(env, e2)?))
+    }
+
+    fn match_list(
+        &mut self,
+        env: &Env,
+        e1: &Core,
+        e2: &Core,
+        e3: &Core,
+    ) -> Result<Value, RuntimeError> {
+        match self.evaluate(env, e1)? {
+            Value::Nil => self.evaluate(env, e2),
+            Value::Cons(head, tail) => {
+                let env = env.push(head).push(tail);
+                self.evaluate(&env, e3)
+            }
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch("a list"))),
+        }
+    }
+
+    /// Evaluates the thunk if it has not been evaluated yet.
+    /// Forcing a thunk from within its own evaluation fails instead of
+    /// recursing forever.
+    pub fn force(&mut self, thunk: &Thunk) -> Result<Value, RuntimeError> {
+        let (env, expr) = match &*thunk.0.borrow() {
+            ThunkState::Forced(value) => return Ok(value.clone()),
+            ThunkState::Forcing => return Err(RuntimeError::new(RuntimeErrorKind::RecursiveValue)),
+            ThunkState::Delayed(env, expr) => (env.clone(), expr.clone()),
+        };
+        *thunk.0.borrow_mut() = ThunkState::Forcing;
+        let result = self.evaluate(&env, &expr);
+        *thunk.0.borrow_mut() = match &result {
+            Ok(value) => ThunkState::Forced(value.clone()),
+            // The error may be handled, and the thunk forced again later.
+            Err(_) => ThunkState::Delayed(env, expr),
+        };
+        result
+    }
+
+    /// Forces every component of tuples, records and lists in `value`, so that
+    /// it can be printed in full.
+    pub fn force_all(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        match value {
+            Value::Tuple(thunks) => thunks.iter().try_for_each(|thunk| {
+                let v = self.force(thunk)?;
+                self.force_all(&v)
+            }),
+            Value::Record(fields) => fields.iter().try_for_each(|(_, thunk)| {
+                let v = self.force(thunk)?;
+                self.force_all(&v)
+            }),
+            Value::Cons(t1, t2) => {
+                let v1 = self.force(t1)?;
+                self.force_all(&v1)?;
+                let v2 = self.force(t2)?;
+                self.force_all(&v2)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Binds `expr` according to the evaluation strategy.
+    pub fn delay(&mut self, env: &Env, expr: &Expr) -> Result<Thunk, RuntimeError> {
+        self.suspend(env, &Core::from_expr(expr))
+    }
+
+    fn suspend(&mut self, env: &Env, expr: &Core) -> Result<Thunk, RuntimeError> {
+        match self.strategy {
+            Strategy::Lazy => Ok(Thunk::suspended(env.clone(), expr.clone())),
+            Strategy::Strict => self.evaluate(env, expr).map(Thunk::forced),
+        }
+    }
+}
+
+/// Applies the binary operator `op` to the values of its operands.
+fn binary(op: &Core, x: Value, y: Value) -> Result<Value, RuntimeError> {
+    use Core::*;
+    match op {
+        Mult(_, _) | Divide(_, _) | Mod(_, _) | Plus(_, _) | Minus(_, _) => {
+            let (x, y) = match (x, y) {
+                (Value::Int(x), Value::Int(y)) => (x, y),
+                (Value::Float(x), Value::Float(y)) if !matches!(op, Mod(_, _)) => {
+                    return Ok(Value::Float(match op {
+                        Plus(_, _) => x + y,
+                        Minus(_, _) => x - y,
+                        Mult(_, _) => x * y,
+                        Divide(_, _) => x / y,
+                        _ => unreachable!(),
+                    }));
+                }
+                _ => return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch("numbers"))),
+            };
+            let result = match op {
+                Plus(_, _) => num::checked_add(&x, &y),
+                Minus(_, _) => num::checked_sub(&x, &y),
+                Mult(_, _) => num::checked_mul(&x, &y),
+                Divide(_, _) if num::is_zero(&y) => {
+                    return Err(RuntimeError::new(RuntimeErrorKind::DivisionByZero))
+                }
+                Divide(_, _) => num::checked_div(&x, &y),
+                Mod(_, _) if num::is_zero(&y) => {
+                    return Err(RuntimeError::new(RuntimeErrorKind::ModuloByZero))
+                }
+                Mod(_, _) => num::checked_rem(&x, &y),
+                _ => unreachable!(),
+            };
+            result
+                .map(Value::Int)
+                .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::Overflow))
+        }
+        Equal(_, _) => match (x, y) {
+            (Value::Int(x), Value::Int(y)) => Ok(Value::Bool(x == y)),
+            (Value::Float(x), Value::Float(y)) => Ok(Value::Bool(x == y)),
+            (Value::Bool(x), Value::Bool(y)) => Ok(Value::Bool(x == y)),
+            (Value::Str(x), Value::Str(y)) => Ok(Value::Bool(x == y)),
+            (Value::Char(x), Value::Char(y)) => Ok(Value::Bool(x == y)),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "comparable values",
+            ))),
+        },
+        Less(_, _) => match (x, y) {
+            (Value::Int(x), Value::Int(y)) => Ok(Value::Bool(x < y)),
+            (Value::Float(x), Value::Float(y)) => Ok(Value::Bool(x < y)),
+            (Value::Str(x), Value::Str(y)) => Ok(Value::Bool(x < y)),
+            (Value::Char(x), Value::Char(y)) => Ok(Value::Bool(x < y)),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "comparable values",
+            ))),
+        },
+        Concat(_, _) => match (x, y) {
+            (Value::Str(x), Value::Str(y)) => Ok(Value::Str(format!("{}{}", x, y).into())),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch("strings"))),
+        },
+        // Strings are indexed by character, not by byte.
+        Index(_, _) => match (x, y) {
+            (Value::Str(s), Value::Int(i)) => num::to_usize(&i)
+                .and_then(|i| s.chars().nth(i))
+                .map(Value::Char)
+                .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::IndexOutOfBounds)),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a string and an integer",
+            ))),
+        },
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prim;
+    use crate::toplevel::parse_expr;
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(Integer::from(n)))
+    }
+
+    fn var(x: &str) -> Box<Expr> {
+        Box::new(Expr::Var(x.to_string()))
+    }
+
+    fn eval(expr: &Expr, strategy: Strategy) -> Result<Value, RuntimeError> {
+        Evaluator::new(strategy).eval(&Env::default(), expr)
+    }
+
+    fn eval_int(expr: &Expr) -> Integer {
+        match (eval(expr, Strategy::Lazy), eval(expr, Strategy::Strict)) {
+            (Ok(Value::Int(m)), Ok(Value::Int(n))) if m == n => n,
+            other => panic!("expected the same integer, got {:?}", other),
+        }
+    }
+
+    fn error(expr: &Expr) -> RuntimeError {
+        eval(expr, Strategy::Strict).unwrap_err()
+    }
+
+    /// rec fact : int -> int is fun n : int -> if n = 0 then 1 else n * fact (n - 1)
+    fn factorial() -> Expr {
+        Expr::Recursion(
+            "fact".to_string(),
+            Box::new(Type::Func(Box::new(Type::Int), Box::new(Type::Int))),
+            Box::new(Expr::Func(
+                "n".to_string(),
+                Box::new(Type::Int),
+                Box::new(Expr::If(
+                    Box::new(Expr::Equal(var("n"), int(0))),
+                    int(1),
+                    Box::new(Expr::Spanned(
+                        10..30,
+                        Box::new(Expr::Mult(
+                            var("n"),
+                            Box::new(Expr::Apply(
+                                var("fact"),
+                                Box::new(Expr::Minus(var("n"), int(1))),
+                            )),
+                        )),
+                    )),
+                )),
+            )),
+        )
+    }
+
+    #[test]
+    fn evaluates_recursive_functions() {
+        let expr = Expr::Apply(Box::new(factorial()), int(10));
+        assert_eq!(eval_int(&expr), Integer::from(3628800));
+    }
+
+    #[test]
+    fn matches_on_lists() {
+        // match 1 :: 2 :: [int] with [int] -> 0 | x :: y -> x
+        let expr = Expr::Match(
+            Box::new(Expr::Cons(
+                int(1),
+                Box::new(Expr::Cons(int(2), Box::new(Expr::None(Type::Int)))),
+            )),
+            Box::new(Type::Int),
+            int(0),
+            "x".to_string(),
+            "y".to_string(),
+            var("x"),
+        );
+        assert_eq!(eval_int(&expr), Integer::from(1));
+    }
+
+    #[test]
+    fn lazy_evaluation_skips_unused_arguments() {
+        // (fun x : int -> 1) (1 / 0)
+        let expr = Expr::Apply(
+            Box::new(Expr::Func("x".to_string(), Box::new(Type::Int), int(1))),
+            Box::new(Expr::Divide(int(1), int(0))),
+        );
+        assert!(matches!(eval(&expr, Strategy::Lazy), Ok(Value::Int(n)) if n == Integer::from(1)));
+        assert_eq!(error(&expr).kind, RuntimeErrorKind::DivisionByZero);
+    }
+
+    #[test]
+    fn reports_division_and_modulo_by_zero() {
+        let expr = Expr::Spanned(4..9, Box::new(Expr::Divide(int(1), int(0))));
+        assert_eq!(
+            error(&expr),
+            RuntimeError {
+                kind: RuntimeErrorKind::DivisionByZero,
+                span: Some(4..9),
+            }
+        );
+        let expr = Expr::Mod(int(1), int(0));
+        assert_eq!(error(&expr).kind, RuntimeErrorKind::ModuloByZero);
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn reports_overflow() {
+        for expr in [
+            Expr::Plus(int(i64::MAX), int(1)),
+            Expr::Minus(int(i64::MIN), int(1)),
+            Expr::Mult(int(i64::MAX), int(2)),
+            Expr::Divide(int(i64::MIN), int(-1)),
+        ] {
+            assert_eq!(error(&expr).kind, RuntimeErrorKind::Overflow);
+        }
+        let expr = Expr::Spanned(0..40, Box::new(Expr::Apply(Box::new(factorial()), int(30))));
+        assert_eq!(
+            error(&expr),
+            RuntimeError {
+                kind: RuntimeErrorKind::Overflow,
+                span: Some(10..30),
+            }
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn grows_past_i64() {
+        let expr = Expr::Apply(Box::new(factorial()), int(30));
+        assert_eq!(
+            eval_int(&expr).to_string(),
+            "265252859812191058636308480000000"
+        );
+    }
+
+    #[test]
+    fn keeps_innermost_span() {
+        // 1 + (1 / 0)
+        let expr = Expr::Spanned(
+            0..11,
+            Box::new(Expr::Plus(
+                int(1),
+                Box::new(Expr::Spanned(5..10, Box::new(Expr::Divide(int(1), int(0))))),
+            )),
+        );
+        assert_eq!(
+            error(&expr),
+            RuntimeError {
+                kind: RuntimeErrorKind::DivisionByZero,
+                span: Some(5..10),
+            }
+        );
+    }
+
+    #[test]
+    fn evaluates_float_arithmetic_and_conversions() {
+        // int_of_float (float_of_int 7 / 2.0) < 4
+        let expr = Expr::Less(
+            Box::new(Expr::Apply(
+                var("int_of_float"),
+                Box::new(Expr::Divide(
+                    Box::new(Expr::Apply(var("float_of_int"), int(7))),
+                    Box::new(Expr::Float(2.0)),
+                )),
+            )),
+            int(4),
+        );
+        let value = Evaluator::new(Strategy::Lazy).eval(&prim::env(), &expr);
+        assert!(matches!(value, Ok(Value::Bool(true))));
+        let expr = Expr::Divide(Box::new(Expr::Float(1.0)), Box::new(Expr::Float(0.0)));
+        let value = eval(&expr, Strategy::Strict).unwrap();
+        assert_eq!(value.to_string(), "inf");
+    }
+
+    #[test]
+    fn rejects_non_finite_conversions() {
+        let expr = Expr::Apply(var("int_of_float"), Box::new(Expr::Float(f64::NAN)));
+        let err = Evaluator::new(Strategy::Strict)
+            .eval(&prim::env(), &expr)
+            .unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::Overflow);
+    }
+
+    #[test]
+    fn evaluates_strings_and_chars() {
+        let str = |s: &str| Box::new(Expr::Str(s.to_string()));
+        // string_of_int (string_length ("h\u{e9}" ^ "llo")) ^ "\n"
+        let expr = Expr::Concat(
+            Box::new(Expr::Apply(
+                var("string_of_int"),
+                Box::new(Expr::Apply(
+                    var("string_length"),
+                    Box::new(Expr::Concat(str("h\u{e9}"), str("llo"))),
+                )),
+            )),
+            str("\n"),
+        );
+        let mut evaluator = Evaluator::new(Strategy::Strict);
+        let value = evaluator.eval(&prim::env(), &expr).unwrap();
+        assert_eq!(value.to_string(), r#""5\n""#);
+        let expr = Expr::Index(str("h\u{e9}"), int(1));
+        let value = evaluator.eval(&prim::env(), &expr).unwrap();
+        assert_eq!(value.to_string(), "'\u{e9}'");
+        let expr = Expr::Less(str("abc"), str("abd"));
+        assert!(matches!(eval(&expr, Strategy::Lazy), Ok(Value::Bool(true))));
+        let expr = Expr::Apply(
+            var("int_of_char"),
+            Box::new(Expr::Apply(var("char_of_int"), int(97))),
+        );
+        assert!(matches!(
+            evaluator.eval(&prim::env(), &expr),
+            Ok(Value::Int(n)) if n == Integer::from(97)
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_string_operations() {
+        let mut evaluator = Evaluator::new(Strategy::Strict);
+        let mut kind = |expr: Expr| evaluator.eval(&prim::env(), &expr).unwrap_err().kind;
+        let expr = Expr::Index(Box::new(Expr::Str("ab".to_string())), int(2));
+        assert_eq!(kind(expr), RuntimeErrorKind::IndexOutOfBounds);
+        let expr = Expr::Index(Box::new(Expr::Str("ab".to_string())), int(-1));
+        assert_eq!(kind(expr), RuntimeErrorKind::IndexOutOfBounds);
+        let expr = Expr::Apply(var("int_of_string"), Box::new(Expr::Str("1x".to_string())));
+        assert_eq!(
+            kind(expr),
+            RuntimeErrorKind::InvalidArgument("int_of_string")
+        );
+        let expr = Expr::Apply(var("char_of_int"), int(-1));
+        assert_eq!(kind(expr), RuntimeErrorKind::InvalidArgument("char_of_int"));
+    }
+
+    #[test]
+    fn updates_references_in_the_store() {
+        // (fun r : int ref => r := !r + 1; r := !r * 10; !r) (ref 4)
+        let expr = Expr::Apply(
+            Box::new(Expr::Func(
+                "r".to_string(),
+                Box::new(Type::Ref(Box::new(Type::Int))),
+                Box::new(Expr::Seq(
+                    Box::new(Expr::Assign(
+                        var("r"),
+                        Box::new(Expr::Plus(Box::new(Expr::Deref(var("r"))), int(1))),
+                    )),
+                    Box::new(Expr::Seq(
+                        Box::new(Expr::Assign(
+                            var("r"),
+                            Box::new(Expr::Mult(Box::new(Expr::Deref(var("r"))), int(10))),
+                        )),
+                        Box::new(Expr::Deref(var("r"))),
+                    )),
+                )),
+            )),
+            Box::new(Expr::Ref(int(4))),
+        );
+        // Under lazy evaluation the argument is allocated once, on first use.
+        assert_eq!(eval_int(&expr), Integer::from(50));
+    }
+
+    #[test]
+    fn catches_runtime_errors_as_exceptions() {
+        let handle = |pattern: Pattern| {
+            Expr::Try(
+                Box::new(Expr::Divide(int(1), int(0))),
+                vec![(pattern, *int(7))],
+            )
+        };
+        let expr = handle(Pattern::Constructor("Division_by_zero".to_string(), None));
+        assert_eq!(eval_int(&expr), Integer::from(7));
+        assert_eq!(eval_int(&handle(Pattern::Wildcard)), Integer::from(7));
+        // An exception that no handler matches keeps propagating unchanged.
+        let expr = handle(Pattern::Constructor("Overflow".to_string(), None));
+        let err = eval(&expr, Strategy::Strict).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::DivisionByZero);
+        // Unbound variables are bugs in the interpreter, not exceptions.
+        let expr = Expr::Try(var("x"), vec![(Pattern::Wildcard, *int(0))]);
+        assert!(eval(&expr, Strategy::Lazy).is_err());
+    }
+
+    #[test]
+    fn reports_values_defined_in_terms_of_themselves() {
+        let expr = parse_expr("rec x : int is x + 1").unwrap();
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            assert_eq!(
+                eval(&expr, strategy).unwrap_err(),
+                RuntimeError {
+                    kind: RuntimeErrorKind::RecursiveValue,
+                    span: Some(15..16),
+                }
+            );
+        }
+        // Functions can refer to themselves before they are called.
+        let expr = parse_expr("(rec f : int -> int is fun n : int => 1) 0").unwrap();
+        assert_eq!(eval_int(&expr), Integer::from(1));
+    }
+
+    #[test]
+    fn reports_recursion_too_deep_for_the_stack() {
+        let expr = parse_expr("(rec f : int -> int is fun n : int => 1 + f n) 0").unwrap();
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let err = eval(&expr, strategy).unwrap_err();
+            assert_eq!(err.kind, RuntimeErrorKind::StackOverflow);
+        }
+        let expr = parse_expr(
+            "(rec sum : int -> int is fun n : int => if n < 1 then 0 else n + sum (n - 1)) 100",
+        )
+        .unwrap();
+        assert_eq!(eval_int(&expr), Integer::from(5050));
+    }
+
+    #[test]
+    fn reports_groups_defined_in_terms_of_each_other() {
+        let expr = parse_expr("let rec a : int = b and b : int = a in a").unwrap();
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let err = eval(&expr, strategy).unwrap_err();
+            assert_eq!(err.kind, RuntimeErrorKind::RecursiveValue);
+        }
+        let expr = parse_expr("let rec a : int = b + 1 and b : int = 1 in a").unwrap();
+        assert_eq!(eval_int(&expr), Integer::from(2));
+    }
+
+    #[test]
+    fn handles_failures_in_lazy_components() {
+        for (source, handled) in [
+            ("try (1 / 0, 2) with _ -> (0, 0)", "(0, 0)"),
+            (
+                "try (2, { x = 1 / 0 }) with _ -> (0, { x = 0 })",
+                "(0, { x = 0 })",
+            ),
+            ("try 1 :: (1 / 0) :: [int] with _ -> [int]", "[]"),
+        ] {
+            let mut evaluator = Evaluator::new(Strategy::Lazy);
+            let expr = parse_expr(source).unwrap();
+            let value = evaluator.eval(&Env::default(), &expr).unwrap();
+            evaluator.force_all(&value).unwrap();
+            assert_eq!(value.to_string(), handled);
+        }
+    }
+
+    #[test]
+    fn prints_forced_lists() {
+        let expr = Expr::Cons(
+            int(1),
+            Box::new(Expr::Cons(int(2), Box::new(Expr::None(Type::Int)))),
+        );
+        let mut evaluator = Evaluator::new(Strategy::Lazy);
+        let value = evaluator.eval(&Env::default(), &expr).unwrap();
+        assert_eq!(value.to_string(), "[?, ...]");
+        evaluator.force_all(&value).unwrap();
+        assert_eq!(value.to_string(), "[1, 2]");
+    }
+}
@@ -0,0 +1,197 @@
+//! The interface for embedding flock in other programs.
+
+use crate::ast::{Commands, Expr, Type};
+use crate::convert::{FromValue, IntoValue};
+use crate::eval::{Evaluator, RuntimeError, Strategy, Value};
+use crate::toplevel::{self, Error, Outcome, Session};
+use crate::typecheck::TypeError;
+
+/// An interpreter with the primitives and the prelude in scope, to which
+/// definitions can be added from Rust and from flock source alike.
+///
+/// ```
+/// use flock::Interpreter;
+///
+/// let mut flock = Interpreter::new();
+/// flock.define("xs", vec![1i64, 2, 3]);
+/// flock.register_fn("double", |n: i64| n * 2);
+/// let doubled: Vec<i64> = flock.eval_as("map double xs").unwrap();
+/// assert_eq!(doubled, [2, 4, 6]);
+/// ```
+pub struct Interpreter {
+    session: Session,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter that evaluates strictly.
+    pub fn new() -> Self {
+        Self::with_strategy(Strategy::Strict)
+    }
+
+    pub fn with_strategy(strategy: Strategy) -> Self {
+        Self::with_evaluator(Evaluator::new(strategy))
+    }
+
+    /// An interpreter evaluating with `evaluator`, e.g. to redirect its I/O.
+    pub fn with_evaluator(evaluator: Evaluator) -> Self {
+        Self {
+            session: Session::with_evaluator(evaluator),
+        }
+    }
+
+    /// The session definitions are made in, for running commands one at a
+    /// time.
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    /// The commands in `source`, separated by `;;`.
+    pub fn parse(&self, source: &str) -> Result<Vec<Commands>, Error> {
+        toplevel::parse(source)
+    }
+
+    /// Runs every command in `source`, stopping at the first error or at
+    /// `:quit`.
+    pub fn run(&mut self, source: &str) -> Result<Vec<Outcome>, Error> {
+        let mut outcomes = Vec::new();
+        for command in self.parse(source)? {
+            match self.session.exec(&command)? {
+                Outcome::Exit => break,
+                outcome => outcomes.push(outcome),
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// The type of the expression `source`, without evaluating it.
+    pub fn check(&mut self, source: &str) -> Result<Type, Error> {
+        let expr = toplevel::parse_expr(source)?;
+        self.session.check(&expr)
+    }
+
+    /// The value of the expression `source`, evaluated fully.
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let expr = toplevel::parse_expr(source)?;
+        self.eval_expr(expr)
+    }
+
+    fn eval_expr(&mut self, expr: Expr) -> Result<Value, Error> {
+        match self.session.exec(&Commands::Expr(expr))? {
+            Outcome::Value(_, value) => Ok(value),
+            _ => unreachable!("expressions evaluate to values"),
+        }
+    }
+
+    /// The value of the expression `source` as a `T`, which has to be the
+    /// type of the expression.
+    pub fn eval_as<T: FromValue>(&mut self, source: &str) -> Result<T, Error> {
+        let expr = toplevel::parse_expr(source)?;
+        let ty = self.session.check(&expr)?;
+        if ty != T::flock_type() {
+            let span = match &expr {
+                Expr::Spanned(span, _) => Some(span.clone()),
+                _ => None,
+            };
+            return Err(Error::Type(TypeError {
+                message: format!(
+                    "this expression has type {} but an expression was expected of type {}",
+                    ty,
+                    T::flock_type()
+                ),
+                span,
+            }));
+        }
+        let value = self.eval_expr(expr)?;
+        T::from_value(&value).map_err(Error::Runtime)
+    }
+
+    /// Makes `value` available as `x` to everything run afterwards.
+    pub fn define<T: IntoValue>(&mut self, x: impl Into<String>, value: T) {
+        self.session.define(x, T::flock_type(), value.into_value());
+    }
+
+    /// Makes a function implemented in Rust available as `name`, of type
+    /// `ty`. Its argument is evaluated fully before `apply` is called with
+    /// it; several arguments are best taken as a tuple.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        ty: Type,
+        apply: impl Fn(&mut Evaluator, Value) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        self.session.register(name, ty, apply);
+    }
+
+    /// Makes the Rust function `f` available as `name`, converting its
+    /// argument and result. Several arguments are taken as a tuple.
+    pub fn register_fn<A, R>(&mut self, name: impl Into<String>, f: impl Fn(A) -> R + 'static)
+    where
+        A: FromValue,
+        R: IntoValue,
+    {
+        let ty = Type::Func(Box::new(A::flock_type()), Box::new(R::flock_type()));
+        self.register(name, ty, move |_, v| Ok(f(A::from_value(&v)?).into_value()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_expressions_to_rust_values() {
+        let mut flock = Interpreter::new();
+        flock.define("pairs", vec![(1i64, true), (2, false)]);
+        flock.define("greeting", "hello");
+        assert_eq!(
+            flock.check("pairs").unwrap().to_string(),
+            "(Int * Bool) List"
+        );
+        assert_eq!(
+            flock.eval_as::<String>("greeting ^ \" world\""),
+            Ok("hello world".to_string())
+        );
+        assert_eq!(
+            flock.eval_as::<(i64, bool)>(
+                "match pairs with [int * bool] -> (0, false) | p :: ps -> p"
+            ),
+            Ok((1, true))
+        );
+        assert_eq!(flock.eval("1 + 2").unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn runs_commands_and_calls_rust_functions() {
+        let mut flock = Interpreter::with_strategy(Strategy::Strict);
+        flock.register_fn("add", |(a, b): (i64, i64)| a + b);
+        let outcomes = flock
+            .run("let sum = fold (fun a : int => fun b : int => add (a, b)) 0;; sum (range 0 5)")
+            .unwrap();
+        let outcomes: Vec<_> = outcomes.iter().map(Outcome::to_string).collect();
+        assert_eq!(outcomes, ["val sum : Int List -> Int", "- : Int = 10"]);
+        assert_eq!(
+            flock.eval_as::<Vec<i64>>("map (fun n : int => add (n, n)) (1 :: [int])"),
+            Ok(vec![2])
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut flock = Interpreter::new();
+        assert!(matches!(flock.eval_as::<bool>("1"), Err(Error::Type(_))));
+        assert!(matches!(
+            flock.eval_as::<bool>("  1 + 2 "),
+            Err(Error::Type(TypeError { span: Some(span), .. })) if span == (2..7)
+        ));
+        assert!(matches!(flock.eval("1;; 2"), Err(Error::Parse(_, _))));
+        assert!(matches!(flock.eval("let x = 1"), Err(Error::Parse(_, _))));
+        assert!(matches!(flock.check("1 + true"), Err(Error::Type(_))));
+        assert!(matches!(flock.eval("1 / 0"), Err(Error::Runtime(_))));
+    }
+}
@@ -0,0 +1,533 @@
+//! The nameless core that expressions are evaluated and compared in: local
+//! variables are de Bruijn indices rather than names, so substitution cannot
+//! capture them and alpha-equivalent expressions are equal.
+
+use crate::ast::{Expr, Pattern, Span, Type};
+use crate::lexer::{self, Token};
+use crate::num::Integer;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Core {
+    /// A variable bound inside the expression, by the `n`th binder around it
+    /// counting outwards from zero.
+    Local(usize),
+    /// A variable bound outside the expression, looked up by name.
+    Global(String),
+    Unit,
+    Int(Integer),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Char(char),
+    Negate(Box<Core>),
+    Mult(Box<Core>, Box<Core>),
+    Divide(Box<Core>, Box<Core>),
+    Mod(Box<Core>, Box<Core>),
+    Plus(Box<Core>, Box<Core>),
+    Minus(Box<Core>, Box<Core>),
+    Equal(Box<Core>, Box<Core>),
+    Less(Box<Core>, Box<Core>),
+    Concat(Box<Core>, Box<Core>),
+    Index(Box<Core>, Box<Core>),
+    If(Box<Core>, Box<Core>, Box<Core>),
+    /// `fun x : t => e`, which binds `x` in `e`.
+    Func(Box<Type>, Box<Core>),
+    Apply(Box<Core>, Box<Core>),
+    Tuple(Vec<Core>),
+    Project(Box<Core>, usize),
+    Record(Vec<(String, Core)>),
+    Field(Box<Core>, String),
+    Update(Box<Core>, Vec<(String, Core)>),
+    /// `rec f : t is e`, which binds `f` in `e`.
+    Recursion(Box<Type>, Box<Core>),
+    /// `let rec f1 : t1 = e1 and ... in e`, which binds every `fi` in every
+    /// `ei` and in `e`, the last one innermost.
+    LetRec(Vec<(Type, Core)>, Box<Core>),
+    None(Type),
+    Cons(Box<Core>, Box<Core>),
+    /// `match e1 with [t] -> e2 | x :: y -> e3`, which binds `x` and then `y`
+    /// in `e3`.
+    Match(Box<Core>, Box<Type>, Box<Core>, Box<Core>),
+    Seq(Box<Core>, Box<Core>),
+    Ref(Box<Core>),
+    Deref(Box<Core>),
+    Assign(Box<Core>, Box<Core>),
+    Raise(Box<Core>, Box<Type>),
+    /// `try e with | p1 -> e1 | ...`, where each `ei` is under a binder if
+    /// its pattern binds a variable.
+    Try(Box<Core>, Vec<(Handler, Core)>),
+    Spanned(Span, Box<Core>),
+}
+
+/// What a `try` handler matches, and whether it binds a variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Handler {
+    /// An exception constructor, and whether its argument is bound.
+    Constructor(String, bool),
+    /// Any exception, and whether it is bound.
+    Any(bool),
+}
+
+impl Handler {
+    pub fn binds(&self) -> bool {
+        match self {
+            Handler::Constructor(_, binds) | Handler::Any(binds) => *binds,
+        }
+    }
+}
+
+impl Core {
+    /// `expr` with its local variables numbered, keeping its spans.
+    pub fn from_expr(expr: &Expr) -> Core {
+        Namer::default().convert(expr, true)
+    }
+
+    /// `expr` with its local variables numbered and without spans, which is
+    /// the same for every expression alpha-equivalent to it.
+    pub fn from_expr_unspanned(expr: &Expr) -> Core {
+        Namer::default().convert(expr, false)
+    }
+
+    /// An expression with names for the local variables, chosen after their
+    /// types so as not to capture any other variable.
+    pub fn to_expr(&self) -> Expr {
+        let mut globals = HashSet::new();
+        self.globals(&mut globals);
+        let mut namer = Namer {
+            scope: Vec::new(),
+            taken: globals,
+        };
+        namer.name(self)
+    }
+
+    /// Adds the names of the global variables in `self` to `globals`.
+    fn globals(&self, globals: &mut HashSet<String>) {
+        if let Core::Global(x) = self {
+            globals.insert(x.clone());
+        }
+        self.for_each_child(|e| e.globals(globals));
+    }
+
+    fn for_each_child(&self, mut f: impl FnMut(&Core)) {
+        use Core::*;
+        match self {
+            Local(_) | Global(_) | Unit | Int(_) | Float(_) | Bool(_) | Str(_) | Char(_)
+            | None(_) => {}
+            Negate(e)
+            | Func(_, e)
+            | Project(e, _)
+            | Field(e, _)
+            | Recursion(_, e)
+            | Ref(e)
+            | Deref(e)
+            | Raise(e, _)
+            | Spanned(_, e) => f(e),
+            Mult(e1, e2)
+            | Divide(e1, e2)
+            | Mod(e1, e2)
+            | Plus(e1, e2)
+            | Minus(e1, e2)
+            | Equal(e1, e2)
+            | Less(e1, e2)
+            | Concat(e1, e2)
+            | Index(e1, e2)
+            | Apply(e1, e2)
+            | Cons(e1, e2)
+            | Seq(e1, e2)
+            | Assign(e1, e2) => {
+                f(e1);
+                f(e2);
+            }
+            If(e1, e2, e3) | Match(e1, _, e2, e3) => {
+                f(e1);
+                f(e2);
+                f(e3);
+            }
+            Tuple(es) => es.iter().for_each(f),
+            Record(fields) => fields.iter().for_each(|(_, e)| f(e)),
+            Update(e, fields) => {
+                f(e);
+                fields.iter().for_each(|(_, e)| f(e));
+            }
+            LetRec(bindings, e) => {
+                bindings.iter().for_each(|(_, e)| f(e));
+                f(e);
+            }
+            Try(e, handlers) => {
+                f(e);
+                handlers.iter().for_each(|(_, e)| f(e));
+            }
+        }
+    }
+}
+
+/// The local variables in scope, innermost last, for converting between
+/// names and indices.
+#[derive(Default)]
+struct Namer {
+    scope: Vec<String>,
+    /// Names that new variables must not be given, since global variables
+    /// have them.
+    taken: HashSet<String>,
+}
+
+impl Namer {
+    /// `e` converted under the names in `bound`, which go out of scope
+    /// again afterwards.
+    fn convert_under(&mut self, bound: &[&String], e: &Expr, spans: bool) -> Core {
+        self.scope.extend(bound.iter().map(|x| x.to_string()));
+        let core = self.convert(e, spans);
+        self.scope.truncate(self.scope.len() - bound.len());
+        core
+    }
+
+    fn convert(&mut self, expr: &Expr, spans: bool) -> Core {
+        let mut convert = |e: &Expr| Box::new(self.convert(e, spans));
+        match expr {
+            Expr::Var(x) => match self.scope.iter().rev().position(|y| y == x) {
+                Some(i) => Core::Local(i),
+                Option::None => Core::Global(x.clone()),
+            },
+            Expr::Unit => Core::Unit,
+            #[allow(clippy::clone_on_copy)] // `Integer` is only `Copy` without `bigint`
+            Expr::Int(n) => Core::Int(n.clone()),
+            Expr::Float(x) => Core::Float(*x),
+            Expr::Bool(b) => Core::Bool(*b),
+            Expr::Str(s) => Core::Str(s.clone()),
+            Expr::Char(c) => Core::Char(*c),
+            Expr::None(ty) => Core::None(ty.clone()),
+            Expr::Negate(e) => Core::Negate(convert(e)),
+            Expr::Ref(e) => Core::Ref(convert(e)),
+            Expr::Deref(e) => Core::Deref(convert(e)),
+            Expr::Project(e, i) => Core::Project(convert(e), *i),
+            Expr::Field(e, x) => Core::Field(convert(e), x.clone()),
+            Expr::Raise(e, ty) => Core::Raise(convert(e), ty.clone()),
+            Expr::Mult(e1, e2) => Core::Mult(convert(e1), convert(e2)),
+            Expr::Divide(e1, e2) => Core::Divide(convert(e1), convert(e2)),
+            Expr::Mod(e1, e2) => Core::Mod(convert(e1), convert(e2)),
+            Expr::Plus(e1, e2) => Core::Plus(convert(e1), convert(e2)),
+            Expr::Minus(e1, e2) => Core::Minus(convert(e1), convert(e2)),
+            Expr::Equal(e1, e2) => Core::Equal(convert(e1), convert(e2)),
+            Expr::Less(e1, e2) => Core::Less(convert(e1), convert(e2)),
+            Expr::Concat(e1, e2) => Core::Concat(convert(e1), convert(e2)),
+            Expr::Index(e1, e2) => Core::Index(convert(e1), convert(e2)),
+            Expr::Apply(e1, e2) => Core::Apply(convert(e1), convert(e2)),
+            Expr::Cons(e1, e2) => Core::Cons(convert(e1), convert(e2)),
+            Expr::Seq(e1, e2) => Core::Seq(convert(e1), convert(e2)),
+            Expr::Assign(e1, e2) => Core::Assign(convert(e1), convert(e2)),
+            Expr::If(e1, e2, e3) => Core::If(convert(e1), convert(e2), convert(e3)),
+            Expr::Tuple(es) => Core::Tuple(es.iter().map(|e| *convert(e)).collect()),
+            Expr::Record(fields) => Core::Record(
+                fields
+                    .iter()
+                    .map(|(x, e)| (x.clone(), *convert(e)))
+                    .collect(),
+            ),
+            Expr::Update(e, fields) => Core::Update(
+                convert(e),
+                fields
+                    .iter()
+                    .map(|(x, e)| (x.clone(), *convert(e)))
+                    .collect(),
+            ),
+            Expr::Func(x, ty, e) => {
+                Core::Func(ty.clone(), Box::new(self.convert_under(&[x], e, spans)))
+            }
+            Expr::Recursion(x, ty, e) => {
+                Core::Recursion(ty.clone(), Box::new(self.convert_under(&[x], e, spans)))
+            }
+            Expr::LetRec(bindings, e) => {
+                let names: Vec<_> = bindings.iter().map(|(x, _, _)| x).collect();
+                let bindings = bindings
+                    .iter()
+                    .map(|(_, ty, e)| (ty.clone(), self.convert_under(&names, e, spans)))
+                    .collect();
+                Core::LetRec(bindings, Box::new(self.convert_under(&names, e, spans)))
+            }
+            Expr::Match(e1, ty, e2, x, y, e3) => {
+                let e1 = self.convert(e1, spans);
+                let e2 = self.convert(e2, spans);
+                let e3 = self.convert_under(&[x, y], e3, spans);
+                Core::Match(Box::new(e1), ty.clone(), Box::new(e2), Box::new(e3))
+            }
+            Expr::Try(e, handlers) => {
+                let e = self.convert(e, spans);
+                let handlers = handlers
+                    .iter()
+                    .map(|(pattern, handler)| {
+                        let handler = match pattern.binder() {
+                            Some(x) => self.convert_under(&[x], handler, spans),
+                            Option::None => self.convert(handler, spans),
+                        };
+                        let pattern = match pattern {
+                            Pattern::Constructor(name, x) => {
+                                Handler::Constructor(name.clone(), x.is_some())
+                            }
+                            Pattern::Var(_) => Handler::Any(true),
+                            Pattern::Wildcard => Handler::Any(false),
+                        };
+                        (pattern, handler)
+                    })
+                    .collect();
+                Core::Try(Box::new(e), handlers)
+            }
+            Expr::Spanned(span, e) if spans => Core::Spanned(span.clone(), convert(e)),
+            Expr::Spanned(_, e) => self.convert(e, spans),
+        }
+    }
+
+    /// A name starting with `base` that no variable in scope or global
+    /// variable has, and that is not a keyword.
+    fn fresh(&self, base: &str) -> String {
+        (0..)
+            .map(|i| match i {
+                0 => base.to_string(),
+                i => format!("{}{}", base, i),
+            })
+            .find(|x| {
+                !self.scope.contains(x)
+                    && !self.taken.contains(x)
+                    && matches!(lexer::lex(x).as_deref(), Ok([(Token::Var(_), _)]))
+            })
+            .expect("there are always more names")
+    }
+
+    /// `e` named under fresh names for `bases`, which go out of scope again
+    /// afterwards, together with those names.
+    fn name_under(&mut self, bases: &[String], e: &Core) -> (Vec<String>, Expr) {
+        let mut names = Vec::new();
+        for base in bases {
+            let x = self.fresh(base);
+            self.scope.push(x.clone());
+            names.push(x);
+        }
+        let e = self.name(e);
+        self.scope.truncate(self.scope.len() - bases.len());
+        (names, e)
+    }
+
+    fn name(&mut self, core: &Core) -> Expr {
+        let mut name = |e: &Core| Box::new(self.name(e));
+        match core {
+            Core::Local(i) => Expr::Var(self.scope[self.scope.len() - 1 - i].clone()),
+            Core::Global(x) => Expr::Var(x.clone()),
+            Core::Unit => Expr::Unit,
+            #[allow(clippy::clone_on_copy)] // `Integer` is only `Copy` without `bigint`
+            Core::Int(n) => Expr::Int(n.clone()),
+            Core::Float(x) => Expr::Float(*x),
+            Core::Bool(b) => Expr::Bool(*b),
+            Core::Str(s) => Expr::Str(s.clone()),
+            Core::Char(c) => Expr::Char(*c),
+            Core::None(ty) => Expr::None(ty.clone()),
+            Core::Negate(e) => Expr::Negate(name(e)),
+            Core::Ref(e) => Expr::Ref(name(e)),
+            Core::Deref(e) => Expr::Deref(name(e)),
+            Core::Project(e, i) => Expr::Project(name(e), *i),
+            Core::Field(e, x) => Expr::Field(name(e), x.clone()),
+            Core::Raise(e, ty) => Expr::Raise(name(e), ty.clone()),
+            Core::Mult(e1, e2) => Expr::Mult(name(e1), name(e2)),
+            Core::Divide(e1, e2) => Expr::Divide(name(e1), name(e2)),
+            Core::Mod(e1, e2) => Expr::Mod(name(e1), name(e2)),
+            Core::Plus(e1, e2) => Expr::Plus(name(e1), name(e2)),
+            Core::Minus(e1, e2) => Expr::Minus(name(e1), name(e2)),
+            Core::Equal(e1, e2) => Expr::Equal(name(e1), name(e2)),
+            Core::Less(e1, e2) => Expr::Less(name(e1), name(e2)),
+            Core::Concat(e1, e2) => Expr::Concat(name(e1), name(e2)),
+            Core::Index(e1, e2) => Expr::Index(name(e1), name(e2)),
+            Core::Apply(e1, e2) => Expr::Apply(name(e1), name(e2)),
+            Core::Cons(e1, e2) => Expr::Cons(name(e1), name(e2)),
+            Core::Seq(e1, e2) => Expr::Seq(name(e1), name(e2)),
+            Core::Assign(e1, e2) => Expr::Assign(name(e1), name(e2)),
+            Core::If(e1, e2, e3) => Expr::If(name(e1), name(e2), name(e3)),
+            Core::Tuple(es) => Expr::Tuple(es.iter().map(|e| *name(e)).collect()),
+            Core::Record(fields) => {
+                Expr::Record(fields.iter().map(|(x, e)| (x.clone(), *name(e))).collect())
+            }
+            Core::Update(e, fields) => Expr::Update(
+                name(e),
+                fields.iter().map(|(x, e)| (x.clone(), *name(e))).collect(),
+            ),
+            Core::Func(ty, e) => {
+                let (mut names, e) = self.name_under(&[base_name(ty)], e);
+                Expr::Func(names.remove(0), ty.clone(), Box::new(e))
+            }
+            Core::Recursion(ty, e) => {
+                let (mut names, e) = self.name_under(&[base_name(ty)], e);
+                Expr::Recursion(names.remove(0), ty.clone(), Box::new(e))
+            }
+            Core::LetRec(bindings, e) => {
+                // Every name in the group is in scope in every binding.
+                let bases: Vec<_> = bindings.iter().map(|(ty, _)| base_name(ty)).collect();
+                let mut names = Vec::new();
+                for base in &bases {
+                    let x = self.fresh(base);
+                    self.scope.push(x.clone());
+                    names.push(x);
+                }
+                let bindings = names
+                    .iter()
+                    .zip(bindings)
+                    .map(|(x, (ty, e))| (x.clone(), ty.clone(), self.name(e)))
+                    .collect();
+                let e = self.name(e);
+                self.scope.truncate(self.scope.len() - bases.len());
+                Expr::LetRec(bindings, Box::new(e))
+            }
+            Core::Match(e1, ty, e2, e3) => {
+                let e1 = self.name(e1);
+                let e2 = self.name(e2);
+                let head = base_name(ty);
+                let tail = format!("{}s", head);
+                let (mut names, e3) = self.name_under(&[head, tail], e3);
+                let y = names.pop().expect("named above");
+                let x = names.pop().expect("named above");
+                Expr::Match(Box::new(e1), ty.clone(), Box::new(e2), x, y, Box::new(e3))
+            }
+            Core::Try(e, handlers) => {
+                let e = self.name(e);
+                let handlers = handlers
+                    .iter()
+                    .map(|(handler, e)| {
+                        let base = match handler {
+                            Handler::Constructor(_, _) => "s",
+                            Handler::Any(_) => "e",
+                        };
+                        let (x, e) = if handler.binds() {
+                            let (mut names, e) = self.name_under(&[base.to_string()], e);
+                            (names.pop(), e)
+                        } else {
+                            (Option::None, self.name(e))
+                        };
+                        let pattern = match (handler, x) {
+                            (Handler::Constructor(name, _), x) => {
+                                Pattern::Constructor(name.clone(), x)
+                            }
+                            (Handler::Any(_), Some(x)) => Pattern::Var(x),
+                            (Handler::Any(_), Option::None) => Pattern::Wildcard,
+                        };
+                        (pattern, e)
+                    })
+                    .collect();
+                Expr::Try(Box::new(e), handlers)
+            }
+            Core::Spanned(span, e) => Expr::Spanned(span.clone(), name(e)),
+        }
+    }
+}
+
+/// What a variable of type `ty` is called by default.
+fn base_name(ty: &Type) -> String {
+    let base = match ty {
+        Type::Unit => "u",
+        Type::Int => "n",
+        Type::Float => "x",
+        Type::Bool => "b",
+        Type::String => "s",
+        Type::Char => "c",
+        Type::Tuple(_) => "p",
+        Type::Func(_, _) => "f",
+        Type::List(_) => "l",
+        Type::Ref(_) | Type::Record(_) => "r",
+        Type::Exn => "e",
+        Type::Named(x) => {
+            let initial = x.rsplit('.').next().and_then(|x| x.chars().next());
+            return initial
+                .filter(char::is_ascii_alphabetic)
+                .map_or("x".to_string(), |c| c.to_ascii_lowercase().to_string());
+        }
+    };
+    base.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toplevel::parse_expr;
+
+    fn core(source: &str) -> Core {
+        Core::from_expr_unspanned(&parse_expr(source).unwrap())
+    }
+
+    fn var(i: usize) -> Box<Core> {
+        Box::new(Core::Local(i))
+    }
+
+    #[test]
+    fn numbers_local_variables() {
+        assert_eq!(
+            core("fun x : int => fun y : int => x + y + z"),
+            Core::Func(
+                Box::new(Type::Int),
+                Box::new(Core::Func(
+                    Box::new(Type::Int),
+                    Box::new(Core::Plus(
+                        Box::new(Core::Plus(var(1), var(0))),
+                        Box::new(Core::Global("z".to_string())),
+                    )),
+                )),
+            )
+        );
+        assert_eq!(
+            core("match l with [int] -> l | x :: xs -> xs"),
+            Core::Match(
+                Box::new(Core::Global("l".to_string())),
+                Box::new(Type::Int),
+                Box::new(Core::Global("l".to_string())),
+                var(0),
+            )
+        );
+        assert_eq!(
+            core("let rec f : int = g and g : int = f in f"),
+            Core::LetRec(vec![(Type::Int, *var(0)), (Type::Int, *var(1))], var(1))
+        );
+        assert_eq!(
+            core("fun x : int => fun x : int => x"),
+            core("fun a : int => fun b : int => b")
+        );
+        assert_ne!(
+            core("fun x : int => fun y : int => x"),
+            core("fun a : int => fun b : int => b")
+        );
+    }
+
+    #[test]
+    fn names_variables_readably_without_capture() {
+        let named = |source: &str| core(source).to_expr().to_string();
+        assert_eq!(
+            named("fun x : int => fun y : int => x + y"),
+            "fun n : int => fun n1 : int => n + n1"
+        );
+        assert_eq!(named("fun x : int => x + n"), "fun n1 : int => n1 + n");
+        assert_eq!(
+            named("fun a : int list => match a with [int] -> 0 | h :: t -> h + length t"),
+            "fun l : int list => match l with [int] -> 0 | n :: ns -> n + length ns"
+        );
+        assert_eq!(
+            named("try f 0 with | Failure m -> m | e -> \"\" | _ -> \"\""),
+            "try f 0 with | Failure s -> s | e -> \"\" | _ -> \"\""
+        );
+        assert_eq!(
+            named("let rec even : int -> bool = fun k : int => odd k and odd : int -> bool = even in even"),
+            "let rec f : int -> bool = fun n : int => f1 n and f1 : int -> bool = f in f"
+        );
+        assert_eq!(
+            named("match l with [index] -> 0 | h :: t -> f h t"),
+            "match l with [index] -> 0 | i :: is1 -> f i is1"
+        );
+    }
+
+    #[test]
+    fn converts_back_to_alpha_equivalent_expressions() {
+        for source in [
+            "fun x : int => fun y : int => x",
+            "rec f : int -> int is fun n : int => if n < 1 then 1 else n * f (n - 1)",
+            "fun s : string => { r with x = s }; try raise Failure s : int with Failure m -> 0",
+            "match l with [int] -> 0 | x :: xs -> (fun xs : int => x + xs) 1",
+        ] {
+            let expr = parse_expr(source).unwrap();
+            let back = Core::from_expr(&expr).to_expr();
+            assert!(back.alpha_eq(&expr), "{}", back);
+        }
+    }
+}
@@ -1,17 +1,120 @@
 extern crate logos;
+use crate::ast::{quote, Span};
+use crate::num::Integer;
 use logos::Logos;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LexErrorKind {
+    #[default]
+    UnknownCharacter,
+    MalformedNumber,
+    IntegerOutOfRange,
+    UnterminatedComment,
+    UnterminatedString,
+    InvalidEscape,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+    /// The offending source text.
+    pub text: String,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            LexErrorKind::UnknownCharacter => write!(f, "unknown character `{}`", self.text),
+            LexErrorKind::MalformedNumber => write!(f, "malformed number `{}`", self.text),
+            LexErrorKind::IntegerOutOfRange => {
+                write!(f, "integer literal `{}` is out of range", self.text)
+            }
+            LexErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::InvalidEscape => write!(f, "invalid literal `{}`", self.text),
+        }
+    }
+}
+
+/// Splits `source` into tokens and their spans, stopping at the first error.
+pub fn lex(source: &str) -> Result<Vec<(Token, Span)>, LexError> {
+    let mut tokens = Vec::new();
+    let mut lexer = Token::lexer(source);
+    while let Some(token) = lexer.next() {
+        let span = lexer.span();
+        match token {
+            // Only a `:` directly followed by a name starts a command, and
+            // only at the start of one; anywhere else it is a colon, as in
+            // `fun x:int => x`.
+            Ok(Token::Colon)
+                if starts_command(&tokens)
+                    && source[span.end..].starts_with(|c: char| c.is_ascii_lowercase()) =>
+            {
+                // The argument runs up to the next `;;` outside of a string
+                // or comment, whether or not it lexes.
+                let mut end = span.end;
+                let mut next = None;
+                while let Some(token) = lexer.next() {
+                    if token == Ok(Token::DoubleSemicolon) {
+                        next = Some((Token::DoubleSemicolon, lexer.span()));
+                        break;
+                    }
+                    end = lexer.span().end;
+                }
+                tokens.push((
+                    Token::Meta(source[span.end..end].to_owned()),
+                    span.start..end,
+                ));
+                tokens.extend(next);
+            }
+            Ok(token) => tokens.push((token, span)),
+            Err(kind) => {
+                return Err(LexError {
+                    kind,
+                    span,
+                    text: lexer.slice().to_string(),
+                })
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether the next token after `tokens` starts a command.
+fn starts_command(tokens: &[(Token, Span)]) -> bool {
+    let last = tokens
+        .iter()
+        .rev()
+        .find(|(token, _)| !matches!(token, Token::DocComment(_)));
+    matches!(last, None | Some((Token::DoubleSemicolon, _)))
+}
 
 #[derive(Logos, Debug, PartialEq, Clone)]
+#[logos(error = LexErrorKind)]
 pub enum Token {
+    #[token("and")]
+    And,
+
     #[token("bool")]
     TypeBool,
 
+    #[token("char")]
+    TypeChar,
+
     #[token("else")]
     Else,
 
+    #[token("exn")]
+    TypeExn,
+
     #[token("false")]
     False,
 
+    #[token("float")]
+    TypeFloat,
+
     #[token("fst")]
     Fst,
 
@@ -24,6 +127,12 @@ pub enum Token {
     #[token("int")]
     TypeInt,
 
+    #[token("import")]
+    Import,
+
+    #[token("in")]
+    In,
+
     #[token("is")]
     Is,
 
@@ -36,20 +145,39 @@ pub enum Token {
     #[token("match")]
     Match,
 
+    #[token("raise")]
+    Raise,
+
     #[token("rec")]
     Rec,
 
+    #[token("ref")]
+    Ref,
+
     #[token("snd")]
     Snd,
 
+    #[token("string")]
+    TypeString,
+
     #[token("then")]
     Then,
 
     #[token("true")]
     True,
 
-    #[token(":quit")]
-    Quit,
+    #[token("try")]
+    Try,
+
+    #[token("type")]
+    Type,
+
+    #[token("unit")]
+    TypeUnit,
+
+    /// A toplevel command such as `:quit` or `:type e`, with everything up to
+    /// the next `;;` as its argument. Made by [`lex`] rather than by `logos`.
+    Meta(String),
 
     #[token("with")]
     With,
@@ -66,9 +194,27 @@ pub enum Token {
     #[token(";;")]
     DoubleSemicolon,
 
+    #[token(";")]
+    Semicolon,
+
     #[token("%")]
     Mod,
 
+    #[token("^")]
+    Caret,
+
+    #[token(".[")]
+    DotLSquareBrack,
+
+    #[token(".")]
+    Dot,
+
+    #[token("{")]
+    LBrace,
+
+    #[token("}")]
+    RBrace,
+
     #[token("(")]
     LParen,
 
@@ -93,6 +239,12 @@ pub enum Token {
     #[token(":")]
     Colon,
 
+    #[token(":=")]
+    Assign,
+
+    #[token("!")]
+    Bang,
+
     #[token("<")]
     Less,
 
@@ -108,19 +260,467 @@ pub enum Token {
     #[token("|")]
     Alternative,
 
-    #[regex(r"--[^\n]*\n?", logos::skip)] // Skip comments
+    #[regex(r"--[^\n]*", logos::skip)] // Skip comments
+    #[token("{-", block_comment)]
     #[regex(r"[ \t\n\r]+", logos::skip)] // Skip whitespace
     Comment,
 
-    #[regex(r"[+-]?([0-9]*)(\.([0-9]+))?([eE][+-]?[0-9]+)?", |lex| lex.slice().parse::<f64>().unwrap())]
+    /// A `--|` comment documenting the definition that follows it.
+    #[regex(r"--\|[^\n]*", |lex| lex.slice()[3..].trim().to_owned(), priority = 10)]
+    DocComment(String),
+
+    // Numeric literals are unsigned; `-` is always a separate token so that
+    // `x-1` is a subtraction. A float needs digits on both sides of the point
+    // or an exponent.
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?", parse_float)]
+    #[regex(r"[0-9]+[eE][+-]?[0-9]+", parse_float)]
+    #[regex(r"[0-9]+\.|\.[0-9]+", |_| Err(LexErrorKind::MalformedNumber))]
     Float(f64),
 
-    #[regex("[+-]?[0-9]+", |lex| lex.slice().parse::<i64>().unwrap())]
-    Integer(i64),
+    // Literals cannot span lines, so that a missing quote is reported where
+    // it happened.
+    #[regex(r#""([^"\\\n]|\\.)*""#, |lex| unescape(lex.slice()))]
+    #[regex(r#""([^"\\\n]|\\.)*"#, |_| Err(LexErrorKind::UnterminatedString))]
+    Str(String),
+
+    #[regex(r"'([^'\\\n]|\\[^\n]|\\u\{[^}\n]*\})'", unescape_char)]
+    Char(char),
+
+    /// `#n`, which projects the `n`th component of a tuple.
+    #[regex("#[0-9]+", |lex| lex.slice()[1..].parse::<usize>().map_err(|_| LexErrorKind::IntegerOutOfRange))]
+    Projection(usize),
 
-    #[regex(r"[a-zA-Z]+", |lex| lex.slice().to_owned())]
+    #[regex("[0-9]+", |lex| lex.slice().parse::<Integer>().map_err(|_| LexErrorKind::IntegerOutOfRange))]
+    Integer(Integer),
+
+    #[regex(r"[a-zA-Z_][a-zA-Z0-9_']*", |lex| lex.slice().to_owned())]
     Var(String),
 
     #[end]
-    EOF,
+    Eof,
+}
+
+/// The source text of a token, as used in error messages.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::And => write!(f, "and"),
+            Token::TypeBool => write!(f, "bool"),
+            Token::TypeChar => write!(f, "char"),
+            Token::Else => write!(f, "else"),
+            Token::TypeExn => write!(f, "exn"),
+            Token::False => write!(f, "false"),
+            Token::TypeFloat => write!(f, "float"),
+            Token::Fst => write!(f, "fst"),
+            Token::Fun => write!(f, "fun"),
+            Token::If => write!(f, "if"),
+            Token::TypeInt => write!(f, "int"),
+            Token::Import => write!(f, "import"),
+            Token::In => write!(f, "in"),
+            Token::Is => write!(f, "is"),
+            Token::Let => write!(f, "let"),
+            Token::TypeList => write!(f, "list"),
+            Token::Match => write!(f, "match"),
+            Token::Raise => write!(f, "raise"),
+            Token::Rec => write!(f, "rec"),
+            Token::Ref => write!(f, "ref"),
+            Token::Snd => write!(f, "snd"),
+            Token::TypeString => write!(f, "string"),
+            Token::Then => write!(f, "then"),
+            Token::True => write!(f, "true"),
+            Token::Try => write!(f, "try"),
+            Token::Type => write!(f, "type"),
+            Token::TypeUnit => write!(f, "unit"),
+            Token::Meta(command) => write!(f, ":{}", command),
+            Token::With => write!(f, "with"),
+            Token::DashArrow => write!(f, "->"),
+            Token::EqualsArrow => write!(f, "=>"),
+            Token::Cons => write!(f, "::"),
+            Token::DoubleSemicolon => write!(f, ";;"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Mod => write!(f, "%"),
+            Token::Caret => write!(f, "^"),
+            Token::DotLSquareBrack => write!(f, ".["),
+            Token::Dot => write!(f, "."),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Mult => write!(f, "*"),
+            Token::Plus => write!(f, "+"),
+            Token::Comma => write!(f, ","),
+            Token::Minus => write!(f, "-"),
+            Token::Divide => write!(f, "/"),
+            Token::Colon => write!(f, ":"),
+            Token::Assign => write!(f, ":="),
+            Token::Bang => write!(f, "!"),
+            Token::Less => write!(f, "<"),
+            Token::Equal => write!(f, "="),
+            Token::LSquareBrack => write!(f, "["),
+            Token::RSquareBrack => write!(f, "]"),
+            Token::Alternative => write!(f, "|"),
+            Token::Comment => write!(f, "comment"),
+            Token::DocComment(doc) => write!(f, "--| {}", doc),
+            Token::Float(x) => write!(f, "{:?}", x),
+            Token::Str(s) => write!(f, "{}", quote(s, '"')),
+            Token::Char(c) => write!(f, "{}", quote(&c.to_string(), '\'')),
+            Token::Projection(n) => write!(f, "#{}", n),
+            Token::Integer(n) => write!(f, "{}", n),
+            Token::Var(x) => write!(f, "{}", x),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+/// Skips a `{- ... -}` comment, which may contain nested block comments.
+fn block_comment(lex: &mut logos::Lexer<Token>) -> logos::FilterResult<(), LexErrorKind> {
+    let mut depth = 1;
+    let mut chars = lex.remainder().char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        match (c, next) {
+            ('{', Some('-')) => depth += 1,
+            ('-', Some('}')) => depth -= 1,
+            _ => continue,
+        }
+        chars.next();
+        if depth == 0 {
+            lex.bump(i + 2);
+            return logos::FilterResult::Skip;
+        }
+    }
+    lex.bump(lex.remainder().len());
+    logos::FilterResult::Error(LexErrorKind::UnterminatedComment)
+}
+
+/// The contents of a quoted literal, with escape sequences replaced.
+fn unescape(literal: &str) -> Result<String, LexErrorKind> {
+    let mut chars = literal[1..literal.len() - 1].chars();
+    let mut result = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        result.push(match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '\'')) => c,
+            Some('u') => {
+                let rest = chars.as_str();
+                let (digits, rest) = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .ok_or(LexErrorKind::InvalidEscape)?;
+                chars = rest.chars();
+                u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(LexErrorKind::InvalidEscape)?
+            }
+            _ => return Err(LexErrorKind::InvalidEscape),
+        });
+    }
+    Ok(result)
+}
+
+fn unescape_char(lex: &mut logos::Lexer<Token>) -> Result<char, LexErrorKind> {
+    let text = unescape(lex.slice())?;
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(LexErrorKind::InvalidEscape),
+    }
+}
+
+fn parse_float(lex: &mut logos::Lexer<Token>) -> Result<f64, LexErrorKind> {
+    lex.slice()
+        .parse()
+        .map_err(|_| LexErrorKind::MalformedNumber)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(source: &str) -> Vec<Token> {
+        let tokens = lex(source).expect("lexing failed");
+        tokens.into_iter().map(|(token, _)| token).collect()
+    }
+
+    fn int(n: i64) -> Token {
+        Token::Integer(Integer::from(n))
+    }
+
+    fn var(x: &str) -> Token {
+        Token::Var(x.to_string())
+    }
+
+    #[test]
+    fn subtraction_without_spaces() {
+        assert_eq!(tokenize("x-1"), vec![var("x"), Token::Minus, int(1)]);
+        assert_eq!(tokenize("n+1"), vec![var("n"), Token::Plus, int(1)]);
+        assert_eq!(tokenize("3-2"), vec![int(3), Token::Minus, int(2)]);
+    }
+
+    #[test]
+    fn literals_are_unsigned() {
+        assert_eq!(tokenize("-1"), vec![Token::Minus, int(1)]);
+        assert_eq!(tokenize("-2.5"), vec![Token::Minus, Token::Float(2.5)]);
+        assert_eq!(tokenize("+7"), vec![Token::Plus, int(7)]);
+    }
+
+    #[test]
+    fn integers_and_floats_do_not_overlap() {
+        assert_eq!(tokenize("42"), vec![int(42)]);
+        assert_eq!(tokenize("4.25"), vec![Token::Float(4.25)]);
+        assert_eq!(tokenize("1e3"), vec![Token::Float(1000.0)]);
+        assert_eq!(tokenize("1.5e-2"), vec![Token::Float(0.015)]);
+        assert_eq!(tokenize("2E+2"), vec![Token::Float(200.0)]);
+    }
+
+    fn error(source: &str) -> LexError {
+        super::lex(source).expect_err("lexing succeeded")
+    }
+
+    #[test]
+    fn malformed_floats() {
+        // Neither side of the point may be empty.
+        assert_eq!(
+            error("x + .5"),
+            LexError {
+                kind: LexErrorKind::MalformedNumber,
+                span: 4..6,
+                text: ".5".to_string(),
+            }
+        );
+        assert_eq!(error("1.").kind, LexErrorKind::MalformedNumber);
+    }
+
+    #[test]
+    fn unknown_characters() {
+        assert_eq!(
+            error("1 + $x"),
+            LexError {
+                kind: LexErrorKind::UnknownCharacter,
+                span: 4..5,
+                text: "$".to_string(),
+            }
+        );
+        assert_eq!(error("a & b").to_string(), "unknown character `&`");
+        assert_eq!(error("a & b").span, 2..3);
+    }
+
+    #[test]
+    fn lex_records_spans() {
+        assert_eq!(
+            super::lex("f 10"),
+            Ok(vec![(var("f"), 0..1), (int(10), 2..4)])
+        );
+    }
+
+    #[test]
+    fn nothing_matches_the_empty_string() {
+        assert_eq!(tokenize(""), vec![]);
+        assert_eq!(tokenize("   \n\t"), vec![]);
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(tokenize("x1"), vec![var("x1")]);
+        assert_eq!(tokenize("is_even"), vec![var("is_even")]);
+        assert_eq!(tokenize("f'"), vec![var("f'")]);
+        assert_eq!(tokenize("_tmp f''"), vec![var("_tmp"), var("f''")]);
+        assert_eq!(tokenize("1x"), vec![int(1), var("x")]);
+    }
+
+    #[test]
+    fn keywords_are_not_identifiers() {
+        assert_eq!(tokenize("if iffy"), vec![Token::If, var("iffy")]);
+        assert_eq!(tokenize("fst first"), vec![Token::Fst, var("first")]);
+        assert_eq!(tokenize("int_of_float"), vec![var("int_of_float")]);
+        assert_eq!(tokenize("type types"), vec![Token::Type, var("types")]);
+    }
+
+    #[test]
+    fn meta_commands_only_start_commands() {
+        let meta = |command: &str| Token::Meta(command.to_string());
+        assert_eq!(
+            tokenize(":type 1; 2;; --| doc\n:quit"),
+            vec![
+                meta("type 1; 2"),
+                Token::DoubleSemicolon,
+                Token::DocComment("doc".to_string()),
+                meta("quit"),
+            ]
+        );
+        assert_eq!(
+            tokenize("fun x:int => (x, y:bool)"),
+            vec![
+                Token::Fun,
+                var("x"),
+                Token::Colon,
+                Token::TypeInt,
+                Token::EqualsArrow,
+                Token::LParen,
+                var("x"),
+                Token::Comma,
+                var("y"),
+                Token::Colon,
+                Token::TypeBool,
+                Token::RParen,
+            ]
+        );
+        let spans: Vec<_> = lex("f x:int")
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| span)
+            .collect();
+        assert_eq!(spans, [0..1, 2..3, 3..4, 4..7]);
+    }
+
+    #[test]
+    fn meta_arguments_run_past_quoted_double_semicolons() {
+        let meta = |command: &str| Token::Meta(command.to_string());
+        assert_eq!(
+            tokenize(r#":type "a;;b" {- ;; -} ;; :quit"#),
+            vec![meta(r#"type "a;;b""#), Token::DoubleSemicolon, meta("quit"),]
+        );
+        let spans: Vec<_> = lex(":env ;;")
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| span)
+            .collect();
+        assert_eq!(spans, [0..4, 5..7]);
+        assert_eq!(tokenize(&"x:int ".repeat(100_000)).len(), 300_000);
+    }
+
+    #[test]
+    fn single_and_double_semicolons() {
+        assert_eq!(
+            tokenize("a; b;; c;;;"),
+            vec![
+                var("a"),
+                Token::Semicolon,
+                var("b"),
+                Token::DoubleSemicolon,
+                var("c"),
+                Token::DoubleSemicolon,
+                Token::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_operators() {
+        assert_eq!(
+            tokenize("a -> b -- comment\n:: c"),
+            vec![var("a"), Token::DashArrow, var("b"), Token::Cons, var("c")]
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            tokenize("1 {- a {- b -} c -} + {--} 2"),
+            vec![int(1), Token::Plus, int(2)]
+        );
+        assert_eq!(tokenize("x {- - } -} y"), vec![var("x"), var("y")]);
+    }
+
+    #[test]
+    fn unterminated_block_comments() {
+        assert_eq!(
+            error("1 {- a {- b -} c"),
+            LexError {
+                kind: LexErrorKind::UnterminatedComment,
+                span: 2..16,
+                text: "{- a {- b -} c".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn doc_comments_are_tokens() {
+        assert_eq!(
+            tokenize("--| Doubles its argument.\n-- not documentation\nlet"),
+            vec![
+                Token::DocComment("Doubles its argument.".to_string()),
+                Token::Let
+            ]
+        );
+    }
+
+    #[test]
+    fn string_and_char_literals() {
+        assert_eq!(
+            tokenize(r#""a b" ^ "" 'c' '\''"#),
+            vec![
+                Token::Str("a b".to_string()),
+                Token::Caret,
+                Token::Str(String::new()),
+                Token::Char('c'),
+                Token::Char('\''),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""\"tab\t\\ \u{e9}\n""#),
+            vec![Token::Str("\"tab\t\\ \u{e9}\n".to_string())]
+        );
+        assert_eq!(tokenize("s.[0]")[1], Token::DotLSquareBrack);
+        // A quote after an identifier is part of its name.
+        assert_eq!(tokenize("f'a'"), vec![var("f'a'")]);
+    }
+
+    #[test]
+    fn records_and_projections() {
+        assert_eq!(
+            tokenize("{r with x = #2 t}.x.[0]"),
+            vec![
+                Token::LBrace,
+                var("r"),
+                Token::With,
+                var("x"),
+                Token::Equal,
+                Token::Projection(2),
+                var("t"),
+                Token::RBrace,
+                Token::Dot,
+                var("x"),
+                Token::DotLSquareBrack,
+                int(0),
+                Token::RSquareBrack,
+            ]
+        );
+        assert_eq!(error("#").kind, LexErrorKind::UnknownCharacter);
+    }
+
+    #[test]
+    fn malformed_string_and_char_literals() {
+        assert_eq!(
+            error("\"abc\ndef\""),
+            LexError {
+                kind: LexErrorKind::UnterminatedString,
+                span: 0..4,
+                text: "\"abc".to_string(),
+            }
+        );
+        assert_eq!(error(r#""a\qb""#).kind, LexErrorKind::InvalidEscape);
+        assert_eq!(error(r"'\u{110000}'").kind, LexErrorKind::InvalidEscape);
+        assert_eq!(error(r"'\q'").to_string(), r"invalid literal `'\q'`");
+    }
+
+    #[test]
+    fn long_integer_literals_do_not_panic() {
+        let source = "123456789012345678901234567890";
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(error(source).kind, LexErrorKind::IntegerOutOfRange);
+        #[cfg(feature = "bigint")]
+        assert_eq!(
+            super::lex(source),
+            Ok(vec![(Token::Integer(source.parse().unwrap()), 0..30)])
+        );
+    }
 }
@@ -0,0 +1,46 @@
+//! flock, a small functional language with an ML-like syntax.
+//!
+//! [`Interpreter`] is the entry point for running flock from Rust: it parses,
+//! type-checks and evaluates source text, and exchanges values with Rust
+//! through [`IntoValue`] and [`FromValue`]. The modules behind it are only
+//! public for the `flock` binary, and are not a stable interface.
+
+#[doc(hidden)]
+pub mod analysis;
+#[doc(hidden)]
+pub mod ast;
+mod convert;
+#[doc(hidden)]
+pub mod diagnostic;
+#[cfg(feature = "repl")]
+#[doc(hidden)]
+pub mod editor;
+mod eval;
+mod interpreter;
+mod ir;
+#[doc(hidden)]
+pub mod lexer;
+mod modules;
+mod num;
+#[doc(hidden)]
+pub mod optimize;
+#[doc(hidden)]
+pub mod parser;
+mod prelude;
+#[doc(hidden)]
+pub mod pretty;
+mod prim;
+#[doc(hidden)]
+pub mod toplevel;
+mod typecheck;
+
+pub use crate::ast::{Span, Type};
+pub use crate::convert::{FlockType, FromValue, IntoValue};
+pub use crate::eval::{
+    Evaluator, Exception, RuntimeError, RuntimeErrorKind, SharedOutput, Strategy, Thunk, Value,
+};
+pub use crate::interpreter::Interpreter;
+pub use crate::lexer::{LexError, LexErrorKind};
+pub use crate::num::Integer;
+pub use crate::toplevel::{Error, Outcome, Session};
+pub use crate::typecheck::TypeError;
@@ -1,26 +1,596 @@
-mod ast;
-mod lexer;
-mod parser;
+use flock::analysis;
+use flock::ast::Commands;
+use flock::diagnostic::Diagnostic;
+use flock::editor::{self, Completions, Pending};
+use flock::lexer;
+use flock::optimize;
+use flock::parser::Parser;
+use flock::pretty::{self, Style};
+use flock::toplevel;
+use flock::{Error, Outcome, Session, Strategy};
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::Editor;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process, thread};
 
-use crate::ast::*;
-use crate::lexer::Token;
-use crate::parser::Parser;
-// use chumsky::Parser;
-use logos::Logos;
+/// The evaluator recurses on the Rust stack, so deeply recursive programs
+/// need far more of it than the main thread gets.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+const USAGE: &str = "\
+usage: flock [command] [options]
+
+commands:
+  repl [-I dir]...            read commands interactively (the default)
+  run [-I dir]... file        run the commands in a file
+  check [-I dir]... file      type-check a file without running it
+  eval [-I dir]... -e source  run `source` and print the value of each expression
+  lex [file]                  print the tokens of a file, or of standard input
+  parse [--ast] [file]        check the syntax of a file, printing its syntax tree
+  fmt [--width n] [--indent n] [file]
+                              print a file laid out in canonical form, keeping
+                              lines within n columns (80) and indenting by n (2)
+
+Modules are looked for in the `-I` directories, in order, then in the
+directory of the file being run, or else the current directory.
+
+`repl`, `run` and `eval` also take:
+  -O                          optimize commands before running them, and
+                              drop definitions nothing uses from `run` and
+                              `eval`
+  --size-budget n             optimize, duplicating or inlining terms of up to
+                              n nodes (20)
+  --trace-optimizer           optimize, showing every command changed on
+                              standard error before and after
+
+On a terminal, the REPL completes names with Tab and keeps its history in
+~/.flock_history.
+
+exit status:
+  0  success
+  1  the input cannot be read
+  2  bad usage
+  3  lex error
+  4  parse error
+  5  type error
+  6  runtime error
+  7  import error";
+
+/// Exit codes, so that scripts can tell failures apart.
+mod code {
+    pub const SUCCESS: i32 = 0;
+    pub const UNREADABLE: i32 = 1;
+    pub const USAGE: i32 = 2;
+    pub const LEX: i32 = 3;
+    pub const PARSE: i32 = 4;
+    pub const TYPE: i32 = 5;
+    pub const RUNTIME: i32 = 6;
+    pub const IMPORT: i32 = 7;
+}
+
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Lex(_) => code::LEX,
+        Error::Parse(_, _) => code::PARSE,
+        Error::Type(_) => code::TYPE,
+        Error::Runtime(_) => code::RUNTIME,
+        Error::Import(_, _) => code::IMPORT,
+    }
+}
+
+/// The options given after a command.
+#[derive(Default)]
+struct Options {
+    /// Which options were given, to reject those a command does not take.
+    given: Vec<&'static str>,
+    dirs: Vec<String>,
+    source: Option<String>,
+    ast: bool,
+    style: Style,
+    /// How to optimize commands before running them, if at all.
+    optimize: Option<optimize::Options>,
+    trace: bool,
+    /// A file name, or `-` for standard input.
+    file: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-I" => {
+                    let dir = args.next().ok_or("`-I` needs a directory")?;
+                    options.dirs.push(dir);
+                    options.given.push("-I");
+                }
+                "-e" if options.source.is_none() => {
+                    options.source = Some(args.next().ok_or("`-e` needs source text")?);
+                    options.given.push("-e");
+                }
+                "--ast" => {
+                    options.ast = true;
+                    options.given.push("--ast");
+                }
+                "-O" => {
+                    options.optimize.get_or_insert_with(Default::default);
+                    options.given.push("-O");
+                }
+                "--size-budget" => {
+                    let n = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("`--size-budget` needs a number")?;
+                    options
+                        .optimize
+                        .get_or_insert_with(Default::default)
+                        .size_budget = n;
+                    options.given.push("--size-budget");
+                }
+                "--trace-optimizer" => {
+                    options.optimize.get_or_insert_with(Default::default);
+                    options.trace = true;
+                    options.given.push("--trace-optimizer");
+                }
+                "--width" | "--indent" => {
+                    let n = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or(format!("`{}` needs a number", arg))?;
+                    if arg == "--width" {
+                        options.style.width = n;
+                        options.given.push("--width");
+                    } else {
+                        options.style.indent = n;
+                        options.given.push("--indent");
+                    }
+                }
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unexpected option `{}`", arg));
+                }
+                _ if options.file.is_some() => {
+                    return Err(format!("unexpected argument `{}`", arg));
+                }
+                _ => {
+                    options.file = Some(arg);
+                    options.given.push("file");
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Fails unless every option given is one of `allowed`.
+    fn only(&self, command: &str, allowed: &[&str]) -> Result<(), String> {
+        match self.given.iter().find(|option| !allowed.contains(option)) {
+            Some(&"file") => Err(format!("`{}` does not take a file", command)),
+            Some(option) => Err(format!("`{}` does not take `{}`", command, option)),
+            None => Ok(()),
+        }
+    }
+
+    /// A session looking for modules in the `-I` directories, then in `dir`.
+    fn session(&self, dir: &Path) -> Session {
+        let mut session = Session::new(Strategy::Lazy);
+        for dir in &self.dirs {
+            session.add_search_dir(dir);
+        }
+        session.add_search_dir(dir);
+        if let Some(options) = &self.optimize {
+            session.optimize(options.clone());
+        }
+        if self.trace {
+            session.trace_optimizer(io::stderr());
+        }
+        session
+    }
+
+    /// The name and text of the input file, or of standard input.
+    fn read(&self) -> Result<(String, String), i32> {
+        let mut source = String::new();
+        let (name, read) = match self.file.as_deref() {
+            None | Some("-") => ("<stdin>", io::stdin().read_to_string(&mut source)),
+            Some(path) => (
+                path,
+                fs::File::open(path).and_then(|mut file| file.read_to_string(&mut source)),
+            ),
+        };
+        match read {
+            Ok(_) => Ok((name.to_string(), source)),
+            Err(err) => {
+                eprintln!("cannot read {}: {}", name, err);
+                Err(code::UNREADABLE)
+            }
+        }
+    }
+
+    /// The directory of the input file, where modules are looked for last.
+    fn dir(&self) -> &Path {
+        self.file
+            .as_deref()
+            .and_then(|path| Path::new(path).parent())
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+    }
+}
+
+/// Shows `diagnostic` in the module source it points into, or else in
+/// `source`.
+fn show_diagnostic(session: &Session, diagnostic: Diagnostic, name: &str, source: &str) {
+    match diagnostic
+        .span
+        .as_ref()
+        .and_then(|span| session.locate(span))
+    {
+        Some((path, text, span)) => diagnostic.clone().with_span(Some(span)).eprint(path, text),
+        None => diagnostic.eprint(name, source),
+    }
+}
+
+/// Reports `err` as a diagnostic and returns the exit code for it.
+fn report(session: &Session, err: &Error, name: &str, source: &str) -> i32 {
+    show_diagnostic(session, Diagnostic::from(err), name, source);
+    exit_code(err)
+}
+
+/// Shows the warnings about the commands run so far.
+fn warn(session: &mut Session, name: &str, source: &str) {
+    for warning in session.take_warnings() {
+        show_diagnostic(session, Diagnostic::from(&warning), name, source);
+    }
+}
+
+enum Status {
+    Done,
+    Quit,
+    Failed(i32),
+}
+
+/// Runs every command in `source[start..]`, showing what each produced with
+/// `show`, and reporting the first error as a diagnostic and skipping the
+/// commands after it. With `prune`, definitions that nothing uses are only
+/// type-checked, not run.
+fn run(
+    session: &mut Session,
+    name: &str,
+    source: &str,
+    start: usize,
+    show: fn(Outcome),
+    prune: bool,
+) -> Status {
+    let commands = match toplevel::parse_from(source, start) {
+        Ok(commands) => commands,
+        Err(err) => return Status::Failed(report(session, &err, name, source)),
+    };
+    let live = if prune {
+        analysis::live_commands(&commands)
+    } else {
+        vec![true; commands.len()]
+    };
+    for (command, live) in commands.iter().zip(live) {
+        let result = if live {
+            session.exec(command).map(Some)
+        } else {
+            session.check_command(command).map(|()| None)
+        };
+        warn(session, name, source);
+        match result {
+            Ok(Some(Outcome::Exit)) => return Status::Quit,
+            Ok(Some(outcome)) => show(outcome),
+            Ok(None) => {}
+            Err(err) => return Status::Failed(report(session, &err, name, source)),
+        }
+    }
+    Status::Done
+}
+
+fn show_all(outcome: Outcome) {
+    println!("{}", outcome);
+}
+
+/// Shows only the values of expressions, and documentation that was asked
+/// for.
+fn show_values(outcome: Outcome) {
+    match outcome {
+        Outcome::Value(_, value) => println!("{}", value),
+        Outcome::Doc(_, _, _) => println!("{}", outcome),
+        _ => {}
+    }
+}
+
+enum Line {
+    Read(String),
+    /// Ctrl-C, which drops what has been typed of the current command.
+    Interrupted,
+    End,
+}
+
+/// Where the REPL reads its input from: a line editor on a terminal, or
+/// else standard input as it is, as when it is piped in.
+enum Input {
+    Editor(Box<Editor<Completions, FileHistory>>, Option<PathBuf>),
+    Plain,
+}
+
+impl Input {
+    fn new() -> Self {
+        if !io::stdin().is_terminal() {
+            return Input::Plain;
+        }
+        let Ok(mut editor) = Editor::new() else {
+            return Input::Plain;
+        };
+        editor.set_helper(Some(Completions::default()));
+        let history = env::var_os("HOME").map(|home| Path::new(&home).join(".flock_history"));
+        if let Some(path) = &history {
+            // There is no history yet the first time.
+            let _ = editor.load_history(path);
+        }
+        Input::Editor(Box::new(editor), history)
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Line {
+        match self {
+            Input::Editor(editor, _) => match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = editor.add_history_entry(line.as_str());
+                    }
+                    Line::Read(line + "\n")
+                }
+                Err(ReadlineError::Interrupted) => Line::Interrupted,
+                Err(_) => Line::End,
+            },
+            Input::Plain => {
+                print!("{}", prompt);
+                let _ = io::stdout().flush();
+                let mut line = String::new();
+                match io::stdin().lock().read_line(&mut line) {
+                    Ok(0) | Err(_) => Line::End,
+                    Ok(_) => Line::Read(line),
+                }
+            }
+        }
+    }
+
+    /// Offers the names defined in `session` for completion.
+    fn update(&mut self, session: &Session) {
+        if let Input::Editor(editor, _) = self {
+            if let Some(completions) = editor.helper_mut() {
+                completions.names = session.names();
+            }
+        }
+    }
+
+    fn save_history(&mut self) {
+        if let Input::Editor(editor, Some(path)) = self {
+            let _ = editor.save_history(path);
+        }
+    }
+}
+
+fn repl(mut session: Session) -> i32 {
+    let mut input = Input::new();
+    input.update(&session);
+    // Everything typed so far, so that errors in functions defined by earlier
+    // inputs can still be shown in context.
+    let mut history = String::new();
+    let mut start = 0;
+    let mut prompt = "# ".to_string();
+    loop {
+        match input.read_line(&prompt) {
+            Line::Read(line) => history.push_str(&line),
+            Line::Interrupted => {
+                start = history.len();
+                prompt = "# ".to_string();
+                continue;
+            }
+            Line::End => break,
+        }
+        let pending = &history[start..];
+        if pending.trim().is_empty() {
+            start = history.len();
+            continue;
+        }
+        if let Pending::Incomplete { bracket } = editor::pending(pending) {
+            prompt = format!("{} ", bracket.unwrap_or(' '));
+            continue;
+        }
+        prompt = "# ".to_string();
+        let status = run(&mut session, "<stdin>", &history, start, show_all, false);
+        start = history.len();
+        input.update(&session);
+        if let Status::Quit = status {
+            input.save_history();
+            return code::SUCCESS;
+        }
+    }
+    input.save_history();
+    if !history[start..].trim().is_empty() {
+        run(&mut session, "<stdin>", &history, start, show_all, false);
+    }
+    code::SUCCESS
+}
+
+fn run_file(options: Options) -> Result<i32, String> {
+    options.only(
+        "run",
+        &["-I", "file", "-O", "--size-budget", "--trace-optimizer"],
+    )?;
+    if options.file.is_none() {
+        return Err("`run` needs a file".to_string());
+    }
+    let (name, source) = match options.read() {
+        Ok(input) => input,
+        Err(code) => return Ok(code),
+    };
+    let mut session = options.session(options.dir());
+    let prune = options.optimize.is_some();
+    Ok(
+        match run(&mut session, &name, &source, 0, show_all, prune) {
+            Status::Done | Status::Quit => code::SUCCESS,
+            Status::Failed(code) => code,
+        },
+    )
+}
+
+fn check(options: Options) -> Result<i32, String> {
+    options.only("check", &["-I", "file"])?;
+    if options.file.is_none() {
+        return Err("`check` needs a file".to_string());
+    }
+    let (name, source) = match options.read() {
+        Ok(input) => input,
+        Err(code) => return Ok(code),
+    };
+    let mut session = options.session(options.dir());
+    let checked = toplevel::parse(&source).and_then(|commands| {
+        commands.iter().try_for_each(|command| {
+            let checked = session.check_command(command);
+            warn(&mut session, &name, &source);
+            checked
+        })
+    });
+    Ok(match checked {
+        Ok(()) => code::SUCCESS,
+        Err(err) => report(&session, &err, &name, &source),
+    })
+}
+
+fn eval(options: Options) -> Result<i32, String> {
+    options.only(
+        "eval",
+        &["-I", "-e", "-O", "--size-budget", "--trace-optimizer"],
+    )?;
+    let source = options
+        .source
+        .as_deref()
+        .ok_or("`eval` needs `-e source`")?;
+    let mut session = options.session(Path::new("."));
+    Ok(
+        match run(
+            &mut session,
+            "<source>",
+            source,
+            0,
+            show_values,
+            options.optimize.is_some(),
+        ) {
+            Status::Done | Status::Quit => code::SUCCESS,
+            Status::Failed(code) => code,
+        },
+    )
+}
+
+fn lex(options: Options) -> Result<i32, String> {
+    options.only("lex", &["file"])?;
+    let (name, source) = match options.read() {
+        Ok(input) => input,
+        Err(code) => return Ok(code),
+    };
+    Ok(match lexer::lex(&source) {
+        Ok(tokens) => {
+            for (token, span) in tokens {
+                println!("{:?}\t{}", span, token);
+            }
+            code::SUCCESS
+        }
+        Err(err) => {
+            Diagnostic::from(&err).eprint(&name, &source);
+            code::LEX
+        }
+    })
+}
+
+/// The text of the input and the commands in it, or the exit code for why
+/// there are none.
+fn parse_input(options: &Options) -> Result<(String, Vec<Commands>), i32> {
+    let (name, source) = options.read()?;
+    match toplevel::parse(&source) {
+        Ok(commands) => Ok((source, commands)),
+        Err(err) => {
+            Diagnostic::from(&err).eprint(&name, &source);
+            Err(exit_code(&err))
+        }
+    }
+}
+
+fn parse(options: Options) -> Result<i32, String> {
+    options.only("parse", &["--ast", "file"])?;
+    let (source, commands) = match parse_input(&options) {
+        Ok(input) => input,
+        Err(code) => return Ok(code),
+    };
+    if options.ast {
+        // Parsed again without spans, which would only clutter the tree.
+        let tokens = lexer::lex(&source).unwrap_or_default();
+        let commands = Parser::new(tokens.into_iter().map(|(token, _)| token))
+            .parse_program()
+            .unwrap_or(commands);
+        for command in commands {
+            println!("{:#?}", command);
+        }
+    }
+    Ok(code::SUCCESS)
+}
+
+fn fmt(options: Options) -> Result<i32, String> {
+    options.only("fmt", &["--width", "--indent", "file"])?;
+    let (name, source) = match options.read() {
+        Ok(input) => input,
+        Err(code) => return Ok(code),
+    };
+    Ok(match pretty::format(&source, &options.style) {
+        Ok(formatted) => {
+            print!("{}", formatted);
+            code::SUCCESS
+        }
+        Err(err) => {
+            Diagnostic::from(&err).eprint(&name, &source);
+            exit_code(&err)
+        }
+    })
+}
+
+fn start() -> i32 {
+    let mut args = env::args().skip(1).peekable();
+    // Without a command, the options are those of `repl`.
+    let command = match args.peek() {
+        Some(arg) if !arg.starts_with('-') => args.next(),
+        _ => None,
+    };
+    let result = Options::parse(args).and_then(|options| match command.as_deref() {
+        None | Some("repl") => {
+            options.only("repl", &["-I", "-O", "--size-budget", "--trace-optimizer"])?;
+            Ok(repl(options.session(Path::new("."))))
+        }
+        Some("run") => run_file(options),
+        Some("check") => check(options),
+        Some("eval") => eval(options),
+        Some("lex") => lex(options),
+        Some("parse") => parse(options),
+        Some("fmt") => fmt(options),
+        Some("help") => {
+            println!("{}", USAGE);
+            Ok(code::SUCCESS)
+        }
+        Some(command) => Err(format!("unknown command `{}`", command)),
+    });
+    result.unwrap_or_else(|message| {
+        eprintln!("flock: {}\n\n{}", message, USAGE);
+        code::USAGE
+    })
+}
 
 fn main() {
-    let input = "
-        [int -> bool]
-";
-    let mut lexer = Token::lexer(input);
-    let mut tokens: Vec<Token> = Vec::new();
-    while let Some(token) = lexer.next() {
-        match token {
-            Ok(token) => tokens.push(token),
-            Err(err) => println!("{:?}", err),
-        }
-    }
-    println!("{:?}", &tokens);
-    let mut parser = Parser::new(tokens.into_iter());
-    println!("{:?}", &parser.parse_nil());
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(start)
+        .expect("cannot start the interpreter thread");
+    match interpreter.join() {
+        Ok(code) => process::exit(code),
+        Err(_) => process::exit(101),
+    }
 }
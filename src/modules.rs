@@ -0,0 +1,164 @@
+//! Finding the files modules live in, and keeping their sources around so
+//! that errors in them can still be shown in context.
+
+use crate::ast::{Span, Type};
+use crate::eval::Thunk;
+use crate::prim::Registry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Spans in module sources are shifted to start here, so that they never
+/// overlap spans in the input given to a session directly.
+const FIRST_OFFSET: usize = usize::MAX / 2;
+
+/// What importing a module brings into scope: the definitions made at its
+/// toplevel, but not those it imported itself.
+#[derive(Debug, Default)]
+pub struct Module {
+    /// Each name with its type, value and documentation.
+    pub values: Vec<(String, Type, Thunk, Option<String>)>,
+    /// Type aliases, with what they stand for.
+    pub aliases: Vec<(String, Type)>,
+}
+
+struct Source {
+    path: String,
+    text: String,
+    /// What spans in `text` are shifted by.
+    offset: usize,
+}
+
+/// The modules of a session: where to look for them, what they start with in
+/// scope, what has been loaded, and what is being loaded.
+#[derive(Default)]
+pub struct Modules {
+    search_path: Vec<PathBuf>,
+    pub primitives: Registry,
+    sources: Vec<Source>,
+    loaded: HashMap<String, Rc<Module>>,
+    /// Modules whose loading has started but not finished, innermost last.
+    loading: Vec<String>,
+}
+
+impl Modules {
+    /// Looks for modules in `dir` after the directories added before it.
+    pub fn add_search_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.search_path.push(dir.into());
+    }
+
+    /// The file the module `name` lives in: `name.flock` in the first
+    /// directory of the search path that has one.
+    pub fn find(&self, name: &str) -> Result<PathBuf, String> {
+        let file = format!("{}.flock", name);
+        self.search_path
+            .iter()
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                let dirs: Vec<_> = self
+                    .search_path
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect();
+                format!(
+                    "cannot find `{}` in the search path [{}]",
+                    file,
+                    dirs.join(", ")
+                )
+            })
+    }
+
+    /// Remembers the text of a module read from `path` and returns the offset
+    /// to shift its spans by.
+    pub fn add_source(&mut self, path: &Path, text: String) -> usize {
+        let offset = self
+            .sources
+            .last()
+            .map_or(FIRST_OFFSET, |source| source.offset + source.text.len() + 1);
+        self.sources.push(Source {
+            path: path.display().to_string(),
+            text,
+            offset,
+        });
+        offset
+    }
+
+    /// The path and text of the module source `span` points into, and the
+    /// span within that text.
+    pub fn locate(&self, span: &Span) -> Option<(&str, &str, Span)> {
+        let source = self.sources.iter().rev().find(|source| {
+            source.offset <= span.start && span.end <= source.offset + source.text.len()
+        })?;
+        let span = span.start - source.offset..span.end - source.offset;
+        Some((&source.path, &source.text, span))
+    }
+
+    pub fn loaded(&self, name: &str) -> Option<Rc<Module>> {
+        self.loaded.get(name).cloned()
+    }
+
+    /// Forgets the modules not named by `keep`, so that importing them again
+    /// loads them afresh.
+    pub fn retain_loaded(&mut self, keep: impl Fn(&str) -> bool) {
+        self.loaded.retain(|name, _| keep(name));
+    }
+
+    /// Marks `name` as being loaded. Fails with the chain of imports that led
+    /// back to it if it already was.
+    pub fn start_loading(&mut self, name: &str) -> Result<(), Vec<String>> {
+        if let Some(i) = self.loading.iter().position(|x| x == name) {
+            let mut cycle = self.loading[i..].to_vec();
+            cycle.push(name.to_string());
+            return Err(cycle);
+        }
+        self.loading.push(name.to_string());
+        Ok(())
+    }
+
+    /// Marks the innermost module being loaded as done, keeping `module` for
+    /// later imports unless loading it failed.
+    pub fn finish_loading(&mut self, module: Option<Module>) -> Option<Rc<Module>> {
+        let name = self.loading.pop()?;
+        let module = Rc::new(module?);
+        self.loaded.insert(name, module.clone());
+        Some(module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_spans_in_module_sources() {
+        let mut modules = Modules::default();
+        let a = modules.add_source(Path::new("A.flock"), "let x = 1".to_string());
+        let b = modules.add_source(Path::new("B.flock"), "let y = 2".to_string());
+        assert_eq!(
+            modules.locate(&(b + 4..b + 5)),
+            Some(("B.flock", "let y = 2", 4..5))
+        );
+        assert_eq!(
+            modules.locate(&(a..a + 3)),
+            Some(("A.flock", "let x = 1", 0..3))
+        );
+        assert_eq!(modules.locate(&(0..3)), None);
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let mut modules = Modules::default();
+        assert_eq!(modules.start_loading("A"), Ok(()));
+        assert_eq!(modules.start_loading("B"), Ok(()));
+        assert_eq!(
+            modules.start_loading("A"),
+            Err(vec!["A".to_string(), "B".to_string(), "A".to_string()])
+        );
+        assert!(modules.finish_loading(Some(Module::default())).is_some());
+        assert!(modules.loaded("B").is_some());
+        assert!(modules.finish_loading(None).is_none());
+        assert!(modules.loaded("A").is_none());
+        assert_eq!(modules.start_loading("A"), Ok(()));
+    }
+}
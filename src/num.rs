@@ -0,0 +1,171 @@
+//! The representation of `int` values. By default they are `i64` and every
+//! operation that would overflow fails; with the `bigint` feature they are
+//! arbitrary-precision and only division and modulo by zero can fail.
+
+#[cfg(not(feature = "bigint"))]
+pub type Integer = i64;
+
+#[cfg(feature = "bigint")]
+pub type Integer = num_bigint::BigInt;
+
+#[cfg(not(feature = "bigint"))]
+mod ops {
+    use super::Integer;
+
+    pub fn checked_neg(x: &Integer) -> Option<Integer> {
+        x.checked_neg()
+    }
+
+    pub fn checked_add(x: &Integer, y: &Integer) -> Option<Integer> {
+        x.checked_add(*y)
+    }
+
+    pub fn checked_sub(x: &Integer, y: &Integer) -> Option<Integer> {
+        x.checked_sub(*y)
+    }
+
+    pub fn checked_mul(x: &Integer, y: &Integer) -> Option<Integer> {
+        x.checked_mul(*y)
+    }
+
+    pub fn checked_div(x: &Integer, y: &Integer) -> Option<Integer> {
+        x.checked_div(*y)
+    }
+
+    pub fn checked_rem(x: &Integer, y: &Integer) -> Option<Integer> {
+        x.checked_rem(*y)
+    }
+
+    pub fn to_f64(x: &Integer) -> f64 {
+        *x as f64
+    }
+
+    /// Truncates towards zero, failing for NaN, infinities and values that do
+    /// not fit.
+    pub fn from_f64(x: f64) -> Option<Integer> {
+        let x = x.trunc();
+        // `i64::MAX as f64` rounds up to 2^63, which is already out of range.
+        (x >= i64::MIN as f64 && x < i64::MAX as f64).then_some(x as i64)
+    }
+
+    pub fn to_usize(x: &Integer) -> Option<usize> {
+        usize::try_from(*x).ok()
+    }
+
+    pub fn from_usize(n: usize) -> Option<Integer> {
+        Integer::try_from(n).ok()
+    }
+
+    pub fn to_i64(x: &Integer) -> Option<i64> {
+        Some(*x)
+    }
+}
+
+#[cfg(feature = "bigint")]
+mod ops {
+    use super::Integer;
+    use num_bigint::Sign;
+    use num_traits::{FromPrimitive, ToPrimitive};
+
+    pub fn checked_neg(x: &Integer) -> Option<Integer> {
+        Some(-x)
+    }
+
+    pub fn checked_add(x: &Integer, y: &Integer) -> Option<Integer> {
+        Some(x + y)
+    }
+
+    pub fn checked_sub(x: &Integer, y: &Integer) -> Option<Integer> {
+        Some(x - y)
+    }
+
+    pub fn checked_mul(x: &Integer, y: &Integer) -> Option<Integer> {
+        Some(x * y)
+    }
+
+    // Both truncate towards zero, like the `i64` operations.
+    pub fn checked_div(x: &Integer, y: &Integer) -> Option<Integer> {
+        (y.sign() != Sign::NoSign).then(|| x / y)
+    }
+
+    pub fn checked_rem(x: &Integer, y: &Integer) -> Option<Integer> {
+        (y.sign() != Sign::NoSign).then(|| x % y)
+    }
+
+    pub fn to_f64(x: &Integer) -> f64 {
+        x.to_f64().unwrap_or(f64::NAN)
+    }
+
+    /// Truncates towards zero, failing for NaN and infinities.
+    pub fn from_f64(x: f64) -> Option<Integer> {
+        Integer::from_f64(x.trunc())
+    }
+
+    pub fn to_usize(x: &Integer) -> Option<usize> {
+        x.to_usize()
+    }
+
+    pub fn from_usize(n: usize) -> Option<Integer> {
+        Some(Integer::from(n))
+    }
+
+    pub fn to_i64(x: &Integer) -> Option<i64> {
+        x.to_i64()
+    }
+}
+
+pub use ops::*;
+
+pub fn is_zero(x: &Integer) -> bool {
+    *x == Integer::from(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i64) -> Integer {
+        Integer::from(n)
+    }
+
+    #[test]
+    fn division_truncates_towards_zero() {
+        assert_eq!(checked_div(&int(-7), &int(2)), Some(int(-3)));
+        assert_eq!(checked_rem(&int(-7), &int(2)), Some(int(-1)));
+        assert_eq!(checked_div(&int(7), &int(0)), None);
+        assert_eq!(checked_rem(&int(7), &int(0)), None);
+    }
+
+    #[test]
+    fn converts_floats_by_truncation() {
+        assert_eq!(from_f64(-2.7), Some(int(-2)));
+        assert_eq!(from_f64(f64::NAN), None);
+        assert_eq!(from_f64(f64::INFINITY), None);
+        assert_eq!(to_f64(&int(-3)), -3.0);
+    }
+
+    #[test]
+    fn converts_indices() {
+        assert_eq!(to_usize(&int(3)), Some(3));
+        assert_eq!(to_usize(&int(-1)), None);
+        assert_eq!(from_usize(5), Some(int(5)));
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn overflow_fails() {
+        assert_eq!(from_f64(1e19), None);
+        assert_eq!(checked_add(&int(i64::MAX), &int(1)), None);
+        assert_eq!(checked_mul(&int(i64::MIN), &int(-1)), None);
+        assert_eq!(checked_neg(&int(i64::MIN)), None);
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn grows_past_i64() {
+        let big = checked_add(&int(i64::MAX), &int(1)).unwrap();
+        assert_eq!(big.to_string(), "9223372036854775808");
+        let square = checked_mul(&big, &big).unwrap();
+        assert_eq!(square.to_string(), "85070591730234615865843651857942052864");
+    }
+}
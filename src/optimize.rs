@@ -0,0 +1,591 @@
+use crate::ast::*;
+use crate::num;
+
+/// Knobs for the optimization pipeline.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Largest term (in nodes) that may be duplicated by beta reduction or
+    /// inlined at the use sites of a toplevel definition.
+    pub size_budget: usize,
+    /// Upper bound on the number of times the pipeline is re-run while it
+    /// keeps making progress.
+    pub max_iterations: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            size_budget: 20,
+            max_iterations: 10,
+        }
+    }
+}
+
+type Pass = fn(&Expr, &Options) -> Expr;
+
+/// The passes in the order they are run on every iteration.
+const PIPELINE: &[(&str, Pass)] = &[
+    ("fold", |e, _| fold_constants(e)),
+    ("simplify", |e, _| simplify(e)),
+    ("beta", beta_reduce),
+];
+
+/// Runs the pipeline on `expr` until it reaches a fixpoint or the iteration
+/// limit from `options`.
+pub fn optimize(expr: &Expr, options: &Options) -> Expr {
+    let mut current = expr.clone();
+    for _ in 0..options.max_iterations {
+        let next = PIPELINE
+            .iter()
+            .fold(current.clone(), |e, (_, pass)| pass(&e, options));
+        if next == current {
+            break;
+        }
+        current = next;
+    }
+    current
+}
+
+/// Optimizes a sequence of toplevel commands, inlining small definitions into
+/// the commands that follow them.
+pub fn optimize_commands(commands: &[Commands], options: &Options) -> Vec<Commands> {
+    let mut optimizer = Optimizer::new(options.clone());
+    commands.iter().map(|c| optimizer.command(c)).collect()
+}
+
+/// Optimizes toplevel commands one at a time, remembering the small
+/// definitions made so far to inline them into the commands that follow.
+/// Only values are inlined, since anything else could have effects or
+/// allocate a reference that has to be shared.
+#[derive(Debug, Clone, Default)]
+pub struct Optimizer {
+    pub options: Options,
+    inlinable: Vec<(String, Expr)>,
+}
+
+impl Optimizer {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            inlinable: Vec::new(),
+        }
+    }
+
+    /// Forgets every definition seen so far.
+    pub fn reset(&mut self) {
+        self.inlinable.clear();
+    }
+
+    pub fn command(&mut self, command: &Commands) -> Commands {
+        match command {
+            Commands::Expr(e) => Commands::Expr(self.expr(e)),
+            Commands::Fn(name, e, doc) => {
+                let e = self.expr(e);
+                // A redefinition shadows the old binding, so neither it nor any
+                // inlined body that mentions it may be substituted any more.
+                self.shadow(name);
+                if is_value(&e) && size(&e) <= self.options.size_budget {
+                    self.inlinable.push((name.clone(), e.clone()));
+                }
+                Commands::Fn(name.clone(), e, doc.clone())
+            }
+            Commands::LetRec(bindings, doc) => {
+                let bindings: Vec<_> = bindings
+                    .iter()
+                    .map(|(x, ty, e)| (x.clone(), ty.clone(), self.expr(e)))
+                    .collect();
+                for (name, _, _) in &bindings {
+                    self.shadow(name);
+                }
+                Commands::LetRec(bindings, doc.clone())
+            }
+            // The module, or the file loaded by a toplevel command, may
+            // redefine any name.
+            Commands::Import(_, _) | Commands::Meta(_, _, _) => {
+                self.reset();
+                command.clone()
+            }
+            Commands::Type(_, _) | Commands::Doc(_) | Commands::Exit => command.clone(),
+        }
+    }
+
+    fn expr(&self, e: &Expr) -> Expr {
+        optimize(&inline(&self.inlinable, e), &self.options)
+    }
+
+    fn shadow(&mut self, name: &str) {
+        self.inlinable
+            .retain(|(x, body)| x != name && !mentions(body, name));
+    }
+}
+
+fn inline(definitions: &[(String, Expr)], expr: &Expr) -> Expr {
+    let safe = definitions
+        .iter()
+        .filter(|(_, body)| can_substitute(expr, &[body]))
+        .cloned()
+        .collect();
+    Expr::subst(&safe, expr)
+}
+
+/// Number of nodes in `expr`.
+pub fn size(expr: &Expr) -> usize {
+    let mut n = 1;
+    for_each_child(expr, &mut |e| n += size(e));
+    n
+}
+
+/// Folds arithmetic and comparisons on integer literals, and concatenations
+/// of string literals. Operations that
+/// would overflow or divide by zero are left for the evaluator to report.
+pub fn fold_constants(expr: &Expr) -> Expr {
+    use Expr::*;
+    let expr = map_children(expr, &mut fold_constants);
+    match &expr {
+        Negate(e) => match e.as_ref() {
+            Int(x) => num::checked_neg(x).map(Int).unwrap_or(expr),
+            Float(x) => Float(-x),
+            _ => expr,
+        },
+        Plus(a, b) | Minus(a, b) | Mult(a, b) | Divide(a, b) | Mod(a, b) => {
+            let (Int(x), Int(y)) = (a.as_ref(), b.as_ref()) else {
+                return expr;
+            };
+            let folded = match &expr {
+                Plus(_, _) => num::checked_add(x, y),
+                Minus(_, _) => num::checked_sub(x, y),
+                Mult(_, _) => num::checked_mul(x, y),
+                Divide(_, _) => num::checked_div(x, y),
+                Mod(_, _) => num::checked_rem(x, y),
+                _ => unreachable!(),
+            };
+            folded.map(Int).unwrap_or(expr)
+        }
+        Equal(a, b) | Less(a, b) => match (a.as_ref(), b.as_ref()) {
+            (Int(x), Int(y)) if matches!(expr, Equal(_, _)) => Bool(x == y),
+            (Int(x), Int(y)) => Bool(x < y),
+            _ => expr,
+        },
+        Concat(a, b) => match (a.as_ref(), b.as_ref()) {
+            (Str(x), Str(y)) => Str(format!("{}{}", x, y)),
+            _ => expr,
+        },
+        _ => expr,
+    }
+}
+
+/// Eliminates conditionals on boolean literals, projections and field accesses
+/// on literal tuples and records whose other components are values, matches
+/// on literal lists and values in sequences. Source spans are dropped once the
+/// expression they cover has been reduced to a value, since it can no longer
+/// fail at runtime.
+pub fn simplify(expr: &Expr) -> Expr {
+    use Expr::*;
+    let expr = map_children(expr, &mut simplify);
+    match expr {
+        If(cond, e1, e2) => match *cond {
+            Bool(true) => *e1,
+            Bool(false) => *e2,
+            cond => If(Box::new(cond), e1, e2),
+        },
+        Project(e, i) => match *e {
+            Tuple(mut es)
+                if es.len() > i && es.iter().enumerate().all(|(j, e)| j == i || is_value(e)) =>
+            {
+                es.swap_remove(i)
+            }
+            e => Project(Box::new(e), i),
+        },
+        Field(e, x) => match *e {
+            Record(mut fields)
+                if fields.iter().any(|(y, _)| *y == x)
+                    && fields.iter().all(|(y, e)| *y == x || is_value(e)) =>
+            {
+                let i = fields.iter().position(|(y, _)| *y == x).unwrap();
+                fields.swap_remove(i).1
+            }
+            e => Field(Box::new(e), x),
+        },
+        Seq(e1, e2) if is_value(&e1) => *e2,
+        Match(e1, ty, e2, x, y, e3) => match *e1 {
+            None(_) => *e2,
+            Cons(head, tail)
+                if is_value(&head) && is_value(&tail) && can_substitute(&e3, &[&head, &tail]) =>
+            {
+                Expr::subst(&vec![(x, *head), (y, *tail)], &e3)
+            }
+            e1 => Match(Box::new(e1), ty, e2, x, y, e3),
+        },
+        Spanned(span, e) => {
+            if is_value(&e) {
+                *e
+            } else {
+                Spanned(span, e)
+            }
+        }
+        expr => expr,
+    }
+}
+
+/// Reduces applications of literal functions to values, as long as the
+/// result stays within the size budget.
+pub fn beta_reduce(expr: &Expr, options: &Options) -> Expr {
+    use Expr::*;
+    let expr = map_children(expr, &mut |e| beta_reduce(e, options));
+    match expr {
+        Apply(func, arg) => match *func {
+            Func(x, _, body)
+                if is_value(&arg)
+                    && size(&body) + size(&arg) * occurrences(&body, &x) <= options.size_budget
+                    && can_substitute(&body, &[&arg]) =>
+            {
+                Expr::subst(&vec![(x, *arg)], &body)
+            }
+            func => Apply(Box::new(func), arg),
+        },
+        expr => expr,
+    }
+}
+
+/// Values can be duplicated or discarded without changing what a program
+/// computes.
+pub fn is_value(expr: &Expr) -> bool {
+    use Expr::*;
+    match expr {
+        Var(_)
+        | Unit
+        | Int(_)
+        | Float(_)
+        | Bool(_)
+        | Str(_)
+        | Char(_)
+        | None(_)
+        | Func(_, _, _) => true,
+        Tuple(es) => es.iter().all(is_value),
+        Record(fields) => fields.iter().all(|(_, e)| is_value(e)),
+        Cons(e1, e2) => is_value(e1) && is_value(e2),
+        Spanned(_, e) => is_value(e),
+        _ => false,
+    }
+}
+
+/// `Expr::subst` does not rename binders, so substituting into `body` is only
+/// safe when no variable of the replacements is bound anywhere inside it.
+fn can_substitute(body: &Expr, replacements: &[&Expr]) -> bool {
+    let mut bound = Vec::new();
+    binders(body, &mut bound);
+    !bound
+        .iter()
+        .any(|x| replacements.iter().any(|e| mentions(e, x)))
+}
+
+fn binders(expr: &Expr, acc: &mut Vec<String>) {
+    match expr {
+        Expr::Func(x, _, _) | Expr::Recursion(x, _, _) => acc.push(x.clone()),
+        Expr::LetRec(bindings, _) => acc.extend(bindings.iter().map(|(x, _, _)| x.clone())),
+        Expr::Match(_, _, _, x, y, _) => acc.extend([x.clone(), y.clone()]),
+        Expr::Try(_, handlers) => acc.extend(
+            handlers
+                .iter()
+                .filter_map(|(pattern, _)| pattern.binder().cloned()),
+        ),
+        _ => {}
+    }
+    for_each_child(expr, &mut |e| binders(e, acc));
+}
+
+fn mentions(expr: &Expr, name: &str) -> bool {
+    occurrences(expr, name) > 0
+}
+
+fn occurrences(expr: &Expr, name: &str) -> usize {
+    match expr {
+        Expr::Var(x) if x == name => 1,
+        _ => {
+            let mut n = 0;
+            for_each_child(expr, &mut |e| n += occurrences(e, name));
+            n
+        }
+    }
+}
+
+fn for_each_child(expr: &Expr, f: &mut impl FnMut(&Expr)) {
+    use Expr::*;
+    match expr {
+        Var(_) | Unit | Int(_) | Float(_) | Bool(_) | Str(_) | Char(_) | None(_) => {}
+        Negate(e)
+        | Ref(e)
+        | Deref(e)
+        | Project(e, _)
+        | Field(e, _)
+        | Raise(e, _)
+        | Func(_, _, e)
+        | Recursion(_, _, e)
+        | Spanned(_, e) => f(e),
+        Mult(e1, e2)
+        | Divide(e1, e2)
+        | Mod(e1, e2)
+        | Plus(e1, e2)
+        | Minus(e1, e2)
+        | Equal(e1, e2)
+        | Less(e1, e2)
+        | Concat(e1, e2)
+        | Index(e1, e2)
+        | Seq(e1, e2)
+        | Assign(e1, e2)
+        | Apply(e1, e2)
+        | Cons(e1, e2) => {
+            f(e1);
+            f(e2);
+        }
+        Tuple(es) => es.iter().for_each(f),
+        Record(fields) => fields.iter().for_each(|(_, e)| f(e)),
+        Update(e, fields) => {
+            f(e);
+            fields.iter().for_each(|(_, e)| f(e));
+        }
+        LetRec(bindings, e) => {
+            for (_, _, body) in bindings {
+                f(body);
+            }
+            f(e);
+        }
+        If(e1, e2, e3) | Match(e1, _, e2, _, _, e3) => {
+            f(e1);
+            f(e2);
+            f(e3);
+        }
+        Try(e, handlers) => {
+            f(e);
+            for (_, handler) in handlers {
+                f(handler);
+            }
+        }
+    }
+}
+
+fn map_children(expr: &Expr, f: &mut impl FnMut(&Expr) -> Expr) -> Expr {
+    use Expr::*;
+    let mut g = |e: &Expr| Box::new(f(e));
+    match expr {
+        Var(_) | Unit | Int(_) | Float(_) | Bool(_) | Str(_) | Char(_) | None(_) => expr.clone(),
+        Negate(e) => Negate(g(e)),
+        Mult(e1, e2) => Mult(g(e1), g(e2)),
+        Divide(e1, e2) => Divide(g(e1), g(e2)),
+        Mod(e1, e2) => Mod(g(e1), g(e2)),
+        Plus(e1, e2) => Plus(g(e1), g(e2)),
+        Minus(e1, e2) => Minus(g(e1), g(e2)),
+        Equal(e1, e2) => Equal(g(e1), g(e2)),
+        Less(e1, e2) => Less(g(e1), g(e2)),
+        Concat(e1, e2) => Concat(g(e1), g(e2)),
+        Index(e1, e2) => Index(g(e1), g(e2)),
+        Seq(e1, e2) => Seq(g(e1), g(e2)),
+        Ref(e) => Ref(g(e)),
+        Deref(e) => Deref(g(e)),
+        Assign(e1, e2) => Assign(g(e1), g(e2)),
+        Raise(e, ty) => Raise(g(e), ty.clone()),
+        Try(e, handlers) => Try(
+            g(e),
+            handlers
+                .iter()
+                .map(|(pattern, handler)| (pattern.clone(), f(handler)))
+                .collect(),
+        ),
+        If(e1, e2, e3) => If(g(e1), g(e2), g(e3)),
+        Func(x, ty, e) => Func(x.clone(), ty.clone(), g(e)),
+        Apply(e1, e2) => Apply(g(e1), g(e2)),
+        Tuple(es) => Tuple(es.iter().map(&mut *f).collect()),
+        Project(e, i) => Project(g(e), *i),
+        Record(fields) => Record(fields.iter().map(|(x, e)| (x.clone(), f(e))).collect()),
+        Field(e, x) => Field(g(e), x.clone()),
+        Update(e, fields) => Update(
+            g(e),
+            fields.iter().map(|(x, e)| (x.clone(), f(e))).collect(),
+        ),
+        Recursion(x, ty, e) => Recursion(x.clone(), ty.clone(), g(e)),
+        LetRec(bindings, e) => LetRec(
+            bindings
+                .iter()
+                .map(|(x, ty, body)| (x.clone(), ty.clone(), f(body)))
+                .collect(),
+            Box::new(f(e)),
+        ),
+        Cons(e1, e2) => Cons(g(e1), g(e2)),
+        Match(e1, ty, e2, x, y, e3) => Match(g(e1), ty.clone(), g(e2), x.clone(), y.clone(), g(e3)),
+        Spanned(span, e) => Spanned(span.clone(), g(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::num::Integer;
+    use crate::toplevel::parse_expr;
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(Integer::from(n)))
+    }
+
+    fn var(x: &str) -> Box<Expr> {
+        Box::new(Expr::Var(x.to_string()))
+    }
+
+    fn run(expr: Expr) -> Expr {
+        optimize(&expr, &Options::default())
+    }
+
+    #[test]
+    fn folds_arithmetic_and_comparisons() {
+        // 1 + 2 * 3 < 10
+        let expr = Expr::Less(
+            Box::new(Expr::Plus(int(1), Box::new(Expr::Mult(int(2), int(3))))),
+            int(10),
+        );
+        assert_eq!(run(expr), Expr::Bool(true));
+    }
+
+    #[test]
+    fn leaves_failing_arithmetic_alone() {
+        let expr = Expr::Divide(int(1), int(0));
+        assert_eq!(run(expr.clone()), expr);
+        let expr = Expr::Mod(int(1), int(0));
+        assert_eq!(run(expr.clone()), expr);
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn leaves_overflow_alone() {
+        let expr = Expr::Plus(int(i64::MAX), int(1));
+        assert_eq!(run(expr.clone()), expr);
+    }
+
+    #[test]
+    fn simplifies_if_on_literal() {
+        let expr = Expr::If(Box::new(Expr::Equal(int(1), int(1))), var("a"), var("b"));
+        assert_eq!(run(expr), Expr::Var("a".to_string()));
+    }
+
+    #[test]
+    fn projects_literal_tuples_and_records() {
+        let triple = Expr::Tuple(vec![*int(1), *var("x"), *int(3)]);
+        assert_eq!(
+            run(Expr::Project(Box::new(triple.clone()), 0)),
+            Expr::Int(Integer::from(1))
+        );
+        assert_eq!(
+            run(Expr::Project(Box::new(triple), 1)),
+            Expr::Var("x".to_string())
+        );
+        let record = Expr::Record(vec![
+            ("a".to_string(), *int(1)),
+            ("b".to_string(), Expr::Apply(var("f"), var("x"))),
+        ]);
+        assert_eq!(
+            run(Expr::Field(Box::new(record.clone()), "b".to_string())),
+            Expr::Apply(var("f"), var("x"))
+        );
+        // Dropping `f x` could drop its effects.
+        let expr = Expr::Field(Box::new(record), "a".to_string());
+        assert_eq!(run(expr.clone()), expr);
+    }
+
+    #[test]
+    fn reduces_match_on_literal_list() {
+        // match 1 :: [int] with [int] -> 0 | x :: y -> x + 1
+        let expr = Expr::Match(
+            Box::new(Expr::Cons(int(1), Box::new(Expr::None(Type::Int)))),
+            Box::new(Type::Int),
+            int(0),
+            "x".to_string(),
+            "y".to_string(),
+            Box::new(Expr::Plus(var("x"), int(1))),
+        );
+        assert_eq!(run(expr), Expr::Int(Integer::from(2)));
+    }
+
+    #[test]
+    fn beta_reduces_small_applications() {
+        // (fun x : int -> x * x) 3
+        let expr = Expr::Apply(
+            Box::new(Expr::Func(
+                "x".to_string(),
+                Box::new(Type::Int),
+                Box::new(Expr::Mult(var("x"), var("x"))),
+            )),
+            int(3),
+        );
+        assert_eq!(run(expr), Expr::Int(Integer::from(9)));
+    }
+
+    #[test]
+    fn beta_reduction_respects_budget() {
+        let expr = Expr::Apply(
+            Box::new(Expr::Func(
+                "x".to_string(),
+                Box::new(Type::Int),
+                Box::new(Expr::Mult(var("x"), var("x"))),
+            )),
+            var("y"),
+        );
+        let options = Options {
+            size_budget: 2,
+            ..Options::default()
+        };
+        assert_eq!(optimize(&expr, &options), expr);
+    }
+
+    #[test]
+    fn beta_reduction_avoids_capture() {
+        // (fun x : int -> fun y : int -> x) y
+        let expr = Expr::Apply(
+            Box::new(Expr::Func(
+                "x".to_string(),
+                Box::new(Type::Int),
+                Box::new(Expr::Func("y".to_string(), Box::new(Type::Int), var("x"))),
+            )),
+            var("y"),
+        );
+        assert_eq!(run(expr.clone()), expr);
+    }
+
+    #[test]
+    fn inlines_small_toplevel_definitions() {
+        let commands = vec![
+            Commands::Fn("two".to_string(), Expr::Plus(int(1), int(1)), None),
+            Commands::Expr(Expr::Mult(var("two"), int(5))),
+            Commands::Fn("two".to_string(), Expr::Bool(false), None),
+            Commands::Expr(Expr::If(var("two"), int(1), int(0))),
+        ];
+        assert_eq!(
+            optimize_commands(&commands, &Options::default()),
+            vec![
+                Commands::Fn("two".to_string(), Expr::Int(Integer::from(2)), None),
+                Commands::Expr(Expr::Int(Integer::from(10))),
+                Commands::Fn("two".to_string(), Expr::Bool(false), None),
+                Commands::Expr(Expr::Int(Integer::from(0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_inline_references() {
+        let commands = vec![
+            Commands::Fn("r".to_string(), Expr::Ref(int(0)), None),
+            Commands::Expr(Expr::Seq(
+                Box::new(Expr::Assign(var("r"), int(1))),
+                Box::new(Expr::Deref(var("r"))),
+            )),
+        ];
+        assert_eq!(optimize_commands(&commands, &Options::default()), commands);
+    }
+
+    #[test]
+    fn keeps_effects_of_matched_lists() {
+        let expr =
+            parse_expr("match print_int 7 :: [unit] with [unit] -> 0 | x :: y -> 1").unwrap();
+        let optimized = optimize(&expr, &Options::default());
+        assert!(optimized.to_string().contains("print_int 7"));
+        let expr = parse_expr("match 7 :: [int] with [int] -> 0 | x :: y -> x").unwrap();
+        assert_eq!(optimize(&expr, &Options::default()).to_string(), "7");
+    }
+}
@@ -1,75 +1,262 @@
 use crate::ast::*;
 use crate::lexer::*;
-use std::iter::Peekable;
+use std::collections::HashMap;
 
-pub struct Parser<I: Iterator<Item = Token>> {
-    tokens: Peekable<I>,
+pub struct Parser {
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+    /// Doc comments, keyed by the index of the token that follows them. They
+    /// are kept out of `tokens` so that they can appear anywhere.
+    docs: HashMap<usize, Vec<String>>,
+    /// Whether parsed expressions are wrapped in `Expr::Spanned`. Only useful
+    /// when the tokens came with real positions.
+    spans: bool,
+    error: Option<(Span, String)>,
 }
 
-impl<I> Parser<I>
-where
-    I: Iterator<Item = Token>,
-{
-    pub fn new(tokens: I) -> Self {
-        Self {
-            tokens: tokens.peekable(),
+impl Parser {
+    pub fn new(tokens: impl Iterator<Item = Token>) -> Self {
+        let mut parser = Self::with_spans(tokens.map(|token| (token, 0..0)));
+        parser.spans = false;
+        parser
+    }
+
+    /// A parser for tokens carrying their position in the source, which is
+    /// recorded on every expression it produces.
+    pub fn with_spans(tokens: impl Iterator<Item = (Token, Span)>) -> Self {
+        let mut parser = Self {
+            tokens: Vec::new(),
+            pos: 0,
+            docs: HashMap::new(),
+            spans: true,
+            error: None,
+        };
+        for (token, span) in tokens {
+            match token {
+                Token::DocComment(doc) => parser
+                    .docs
+                    .entry(parser.tokens.len())
+                    .or_default()
+                    .push(doc),
+                token => parser.tokens.push((token, span)),
+            }
         }
+        parser
+    }
+
+    /// Where and why parsing failed, after one of the `parse_*` methods
+    /// returned `None`.
+    pub fn error(&self) -> Option<&(Span, String)> {
+        self.error.as_ref()
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Span of the next token, or an empty span just past the last one.
+    fn span(&self) -> Span {
+        match self.tokens.get(self.pos) {
+            Some((_, span)) => span.clone(),
+            None => self.end_of(self.pos),
+        }
+    }
+
+    fn end_of(&self, pos: usize) -> Span {
+        let end = pos
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map_or(0, |(_, span)| span.end);
+        end..end
+    }
+
+    /// Records a failure at the next token. Returns `None` so that callers can
+    /// bail out with `?`.
+    fn fail<T>(&mut self, expected: &str) -> Option<T> {
+        if self.error.is_none() {
+            let found = match self.peek() {
+                Some(token) => format!("`{}`", token),
+                None => "end of input".to_string(),
+            };
+            self.error = Some((
+                self.span(),
+                format!("expected {}, found {}", expected, found),
+            ));
+        }
+        None
+    }
+
+    /// Records a failure at `span` that is not about what was expected next.
+    fn fail_at<T>(&mut self, span: Span, message: String) -> Option<T> {
+        if self.error.is_none() {
+            self.error = Some((span, message));
+        }
+        None
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Option<()> {
+        if self.peek() == Some(&token) {
+            self.next();
+            Some(())
+        } else {
+            self.fail(expected)
+        }
+    }
+
+    fn expect_var(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::Var(x)) => {
+                let x = x.clone();
+                self.next();
+                Some(x)
+            }
+            _ => self.fail("a variable"),
+        }
+    }
+
+    /// Wraps `expr` in the span from the token at `start` to the last token
+    /// consumed.
+    fn spanned(&self, start: usize, expr: Expr) -> Expr {
+        if !self.spans {
+            return expr;
+        }
+        let from = self.tokens.get(start).map_or(0, |(_, span)| span.start);
+        let to = self.end_of(self.pos).end.max(from);
+        Expr::Spanned(from..to, Box::new(expr))
     }
 
     pub fn parse_ty_simple(&mut self) -> Option<Type> {
-        match self.tokens.peek()? {
-            Token::TypeBool => {
-                self.tokens.next();
+        match self.peek() {
+            Some(Token::TypeBool) => {
+                self.next();
                 Some(Type::Bool)
             }
-            Token::TypeInt => {
-                self.tokens.next();
+            Some(Token::TypeInt) => {
+                self.next();
                 Some(Type::Int)
             }
-            Token::LParen => {
-                self.tokens.next();
-                let ty = self.parse_ty_simple();
-                if matches!(self.tokens.next(), Some(Token::RParen)) {
-                    ty
-                } else {
-                    None
-                }
+            Some(Token::TypeUnit) => {
+                self.next();
+                Some(Type::Unit)
             }
-            _ => None,
+            Some(Token::TypeExn) => {
+                self.next();
+                Some(Type::Exn)
+            }
+            Some(Token::TypeFloat) => {
+                self.next();
+                Some(Type::Float)
+            }
+            Some(Token::TypeString) => {
+                self.next();
+                Some(Type::String)
+            }
+            Some(Token::TypeChar) => {
+                self.next();
+                Some(Type::Char)
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let ty = self.parse_ty()?;
+                self.expect(Token::RParen, "`)`")?;
+                Some(ty)
+            }
+            Some(Token::LBrace) => {
+                self.next();
+                let fields = self.parse_fields(Token::Colon, "`:`", Self::parse_ty)?;
+                Some(Type::record(fields))
+            }
+            Some(Token::Var(_)) => self.parse_name().map(Type::Named),
+            _ => self.fail("a type"),
         }
     }
+
+    /// `x <separator> item; ...` up to and including the closing `}`, as in
+    /// record types, literals and updates. A trailing `;` is allowed.
+    fn parse_fields<T>(
+        &mut self,
+        separator: Token,
+        expected: &str,
+        mut item: impl FnMut(&mut Self) -> Option<T>,
+    ) -> Option<Vec<(String, T)>> {
+        let mut fields: Vec<(String, T)> = Vec::new();
+        loop {
+            let span = self.span();
+            let x = self.expect_var()?;
+            if fields.iter().any(|(y, _)| *y == x) {
+                return self.fail_at(span, format!("field `{}` is given twice", x));
+            }
+            self.expect(separator.clone(), expected)?;
+            fields.push((x, item(self)?));
+            if matches!(self.peek(), Some(Token::Semicolon)) {
+                self.next();
+            }
+            if matches!(self.peek(), Some(Token::RBrace)) {
+                self.next();
+                return Some(fields);
+            }
+            if !matches!(self.peek(), Some(Token::Var(_))) {
+                return self.fail("`;` or `}`");
+            }
+        }
+    }
+
     pub fn parse_ty_list(&mut self) -> Option<Type> {
         let base = self.parse_ty_simple()?;
         let mut result = base;
 
-        while matches!(self.tokens.peek(), Some(Token::TypeList)) {
-            self.tokens.next();
-            result = Type::List(Box::new(result));
+        loop {
+            let constructor: fn(Box<Type>) -> Type = match self.peek() {
+                Some(Token::TypeList) => Type::List,
+                Some(Token::Ref) => Type::Ref,
+                _ => break,
+            };
+            self.next();
+            result = constructor(Box::new(result));
         }
 
         Some(result)
     }
 
+    /// `int * bool * int` is a single tuple type with three components, while
+    /// `(int * bool) * int` is a pair whose first component is a pair.
     pub fn parse_ty_times(&mut self) -> Option<Type> {
-        let mut left = self.parse_ty_list()?;
-
-        // Loop to handle consecutive TIMES operations (e.g., int * int * int)
-        while matches!(self.tokens.peek(), Some(Token::Mult)) {
-            self.tokens.next();
-
-            let right = self.parse_ty_list()?;
-            left = Type::Mult(Box::new(left), Box::new(right));
+        let first = self.parse_ty_list()?;
+        let mut tys = vec![first];
+        while matches!(self.peek(), Some(Token::Mult)) {
+            self.next();
+            tys.push(self.parse_ty_list()?);
         }
 
-        Some(left)
+        if tys.len() == 1 {
+            tys.pop()
+        } else {
+            Some(Type::Tuple(tys))
+        }
     }
 
     pub fn parse_ty(&mut self) -> Option<Type> {
-        // can handle int -> bool for example
-        let ty = self.parse_ty_simple()?; // Start with parsing simple types including parenthesized expressions
+        // can handle int list * bool -> bool for example
+        let ty = self.parse_ty_times()?;
 
-        if matches!(self.tokens.peek(), Some(Token::DashArrow)) {
-            self.tokens.next();
+        if matches!(self.peek(), Some(Token::DashArrow)) {
+            self.next();
             let right_ty = self.parse_ty()?; // Recursively parse the right-hand-side type
             return Some(Type::Func(Box::new(ty), Box::new(right_ty)));
         }
@@ -78,78 +265,495 @@ where
     }
 
     pub fn parse_nil(&mut self) -> Option<Type> {
-        if matches!(self.tokens.next(), Some(Token::LSquareBrack)) {
-            let ty = self.parse_ty()?; // Parse the type within brackets
-            if matches!(self.tokens.next(), Some(Token::RSquareBrack)) {
-                Some(Type::List(Box::new(ty)))
-            } else {
-                None // Error: Expected RSQuarBrack
+        self.expect(Token::LSquareBrack, "`[`")?;
+        let ty = self.parse_ty()?; // Parse the type within brackets
+        self.expect(Token::RSquareBrack, "`]`")?;
+        Some(Type::List(Box::new(ty)))
+    }
+
+    /// A single toplevel command, without the `;;` that ends it.
+    pub fn parse_command(&mut self) -> Option<Commands> {
+        match self.peek() {
+            Some(Token::Meta(command)) => {
+                let command = command.clone();
+                let span = self.span();
+                self.next();
+                let (name, arg) = command.split_at(
+                    command
+                        .find(|c: char| !c.is_ascii_lowercase())
+                        .unwrap_or(command.len()),
+                );
+                // An empty argument is placed right after the name.
+                let space = match arg.trim() {
+                    "" => 0,
+                    _ => arg.len() - arg.trim_start().len(),
+                };
+                let start = span.start + 1 + name.len() + space;
+                let arg = arg.trim();
+                let arg_span = start..start + arg.len();
+                let is_name = arg.split('.').all(|x| {
+                    x.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                        && x.chars()
+                            .all(|c| c.is_alphanumeric() || c == '_' || c == '\'')
+                });
+                match name {
+                    "quit" if !arg.is_empty() => {
+                        self.fail_at(arg_span, "`:quit` takes no argument".to_string())
+                    }
+                    "quit" => Some(Commands::Exit),
+                    "doc" if !is_name => self.fail_at(
+                        arg_span,
+                        format!("expected a name after `:doc`, found `{}`", arg),
+                    ),
+                    "doc" => Some(Commands::Doc(arg.to_string())),
+                    _ => Some(Commands::Meta(name.to_string(), arg.to_string(), start)),
+                }
             }
-        } else {
-            None // Error: Expected LSquareBrack
+            Some(Token::Import) => {
+                self.next();
+                let span = self.span();
+                let name = self.expect_var()?;
+                Some(Commands::Import(name, span))
+            }
+            Some(Token::Type) => {
+                self.next();
+                let x = self.expect_var()?;
+                self.expect(Token::Equal, "`=`")?;
+                let ty = self.parse_ty()?;
+                Some(Commands::Type(x, ty))
+            }
+            Some(Token::Let) if matches!(self.peek_nth(1), Some(Token::Rec)) => {
+                let start = self.pos;
+                let doc = self.docs.get(&self.pos).map(|lines| lines.join("\n"));
+                self.next();
+                self.next();
+                let bindings = self.parse_bindings()?;
+                if !matches!(self.peek(), Some(Token::In)) {
+                    return Some(Commands::LetRec(bindings, doc));
+                }
+                // `let rec ... in e` is an expression, which may go on with `;`.
+                self.next();
+                let body = self.parse_expr()?;
+                let expr = self.spanned(start, Expr::LetRec(bindings, Box::new(body)));
+                Some(Commands::Expr(expr))
+            }
+            Some(Token::Let) => {
+                let doc = self.docs.get(&self.pos).map(|lines| lines.join("\n"));
+                self.next();
+                let x = self.expect_var()?;
+                self.expect(Token::Equal, "`=`")?;
+                let e = self.parse_expr()?;
+                Some(Commands::Fn(x, e, doc))
+            }
+            _ => self.parse_expr().map(Commands::Expr),
         }
     }
 
-    pub fn parse_basic_binary_expr(&mut self) -> Option<Expr> {
-        match self.tokens.peek() {
-            Some(&Token::Integer(value)) => {
-                self.tokens.next();
-                Some(Expr::Int(value))
+    /// Commands separated by `;;`, up to the end of the input. The last `;;`
+    /// is optional.
+    pub fn parse_program(&mut self) -> Option<Vec<Commands>> {
+        let mut commands = Vec::new();
+        while !self.at_end() {
+            commands.push(self.parse_command()?);
+            if !self.at_end() {
+                self.expect(Token::DoubleSemicolon, "`;;`")?;
             }
-            Some(&Token::True) => {
-                self.tokens.next();
-                Some(Expr::Bool(true))
+        }
+        Some(commands)
+    }
+
+    /// `e1; e2`, which associates to the right and binds loosest of all, so
+    /// that the body of a function extends as far as possible.
+    pub fn parse_expr(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let first = self.parse_unsequenced_expr()?;
+        if !matches!(self.peek(), Some(Token::Semicolon)) {
+            return Some(first);
+        }
+        self.next();
+        let rest = self.parse_expr()?;
+        Some(self.spanned(start, Expr::Seq(Box::new(first), Box::new(rest))))
+    }
+
+    fn parse_unsequenced_expr(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let expr = match self.peek() {
+            Some(Token::Fun) => {
+                self.next();
+                let x = self.expect_var()?;
+                self.expect(Token::Colon, "`:`")?;
+                let ty = self.parse_ty()?;
+                self.expect(Token::EqualsArrow, "`=>`")?;
+                let e = self.parse_expr()?;
+                Expr::Func(x, Box::new(ty), Box::new(e))
             }
-            Some(&Token::False) => {
-                self.tokens.next();
-                Some(Expr::Bool(false))
+            Some(Token::Rec) => {
+                self.next();
+                let x = self.expect_var()?;
+                self.expect(Token::Colon, "`:`")?;
+                let ty = self.parse_ty()?;
+                self.expect(Token::Is, "`is`")?;
+                let e = self.parse_expr()?;
+                Expr::Recursion(x, Box::new(ty), Box::new(e))
             }
-            Some(&Token::LParen) => {
-                self.tokens.next();
-                let expr = self.parse_binary_expr();
-                if matches!(self.tokens.next(), Some(Token::RParen)) {
-                    expr
-                } else {
-                    None // Error: Expected ')'
+            Some(Token::If) => {
+                self.next();
+                let e1 = self.parse_expr()?;
+                self.expect(Token::Then, "`then`")?;
+                let e2 = self.parse_expr()?;
+                self.expect(Token::Else, "`else`")?;
+                // `if c then a else b; d` runs `d` after either branch.
+                let e3 = self.parse_unsequenced_expr()?;
+                Expr::If(Box::new(e1), Box::new(e2), Box::new(e3))
+            }
+            Some(Token::Match) => {
+                self.next();
+                let e1 = self.parse_expr()?;
+                self.expect(Token::With, "`with`")?;
+                let ty = match self.parse_nil()? {
+                    Type::List(ty) => ty,
+                    _ => unreachable!(),
+                };
+                self.expect(Token::DashArrow, "`->`")?;
+                let e2 = self.parse_expr()?;
+                self.expect(Token::Alternative, "`|`")?;
+                let x = self.expect_var()?;
+                self.expect(Token::Cons, "`::`")?;
+                let y = self.expect_var()?;
+                self.expect(Token::DashArrow, "`->`")?;
+                let e3 = self.parse_expr()?;
+                Expr::Match(Box::new(e1), ty, Box::new(e2), x, y, Box::new(e3))
+            }
+            Some(Token::Let) => {
+                self.next();
+                self.expect(Token::Rec, "`rec`")?;
+                let bindings = self.parse_bindings()?;
+                self.expect(Token::In, "`in`")?;
+                let e = self.parse_expr()?;
+                Expr::LetRec(bindings, Box::new(e))
+            }
+            Some(Token::Raise) => {
+                self.next();
+                let e = self.parse_binary_expr()?;
+                self.expect(Token::Colon, "`:`")?;
+                let ty = self.parse_ty()?;
+                Expr::Raise(Box::new(e), Box::new(ty))
+            }
+            Some(Token::Try) => {
+                self.next();
+                let e = self.parse_expr()?;
+                self.expect(Token::With, "`with`")?;
+                // The `|` before the first handler is optional.
+                if matches!(self.peek(), Some(Token::Alternative)) {
+                    self.next();
+                }
+                let mut handlers = Vec::new();
+                loop {
+                    let pattern = self.parse_pattern()?;
+                    self.expect(Token::DashArrow, "`->`")?;
+                    handlers.push((pattern, self.parse_expr()?));
+                    if !matches!(self.peek(), Some(Token::Alternative)) {
+                        break;
+                    }
+                    self.next();
                 }
+                Expr::Try(Box::new(e), handlers)
             }
-            // Handle other cases or errors appropriately
+            _ => return self.parse_assign_expr(),
+        };
+        Some(self.spanned(start, expr))
+    }
+
+    /// A variable, which may be qualified by the name of a module as in
+    /// `List.map`. Module names are capitalized, so `r.x` is still a field
+    /// access.
+    fn parse_name(&mut self) -> Option<String> {
+        let name = self.expect_var()?;
+        let qualified = name.starts_with(|c: char| c.is_ascii_uppercase())
+            && matches!(self.peek(), Some(Token::Dot))
+            && matches!(self.peek_nth(1), Some(Token::Var(_)));
+        if !qualified {
+            return Some(name);
+        }
+        self.next();
+        Some(format!("{}.{}", name, self.expect_var()?))
+    }
+
+    /// `f : t = e and ...` after `let rec`.
+    fn parse_bindings(&mut self) -> Option<Vec<Binding>> {
+        let mut bindings: Vec<Binding> = Vec::new();
+        loop {
+            let span = self.span();
+            let x = self.expect_var()?;
+            if bindings.iter().any(|(y, _, _)| *y == x) {
+                return self.fail_at(span, format!("`{}` is defined twice", x));
+            }
+            self.expect(Token::Colon, "`:`")?;
+            let ty = self.parse_ty()?;
+            self.expect(Token::Equal, "`=`")?;
+            bindings.push((x, ty, self.parse_expr()?));
+            if !matches!(self.peek(), Some(Token::And)) {
+                return Some(bindings);
+            }
+            self.next();
+        }
+    }
+
+    /// `_`, a variable, or an exception constructor, which starts with a
+    /// capital letter, followed by a variable if it has an argument.
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        let name = self.expect_var()?;
+        if name == "_" {
+            return Some(Pattern::Wildcard);
+        }
+        if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+            return Some(Pattern::Var(name));
+        }
+        let arg = match self.peek() {
+            Some(Token::Var(_)) => Some(self.expect_var()?),
             _ => None,
+        };
+        Some(Pattern::Constructor(name, arg))
+    }
+
+    /// `e1 := e2`, whose right-hand side may be any unsequenced expression.
+    fn parse_assign_expr(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let target = self.parse_binary_expr()?;
+        if !matches!(self.peek(), Some(Token::Assign)) {
+            return Some(target);
         }
+        self.next();
+        let value = self.parse_unsequenced_expr()?;
+        Some(self.spanned(start, Expr::Assign(Box::new(target), Box::new(value))))
     }
 
+    /// Comparisons, which do not associate.
     pub fn parse_binary_expr(&mut self) -> Option<Expr> {
-        let left = self.parse_basic_binary_expr()?;
+        let start = self.pos;
+        let left = self.parse_cons_expr()?;
+        let op: fn(Box<Expr>, Box<Expr>) -> Expr = match self.peek() {
+            Some(Token::Equal) => Expr::Equal,
+            Some(Token::Less) => Expr::Less,
+            _ => return Some(left),
+        };
+        self.next();
+        let right = self.parse_cons_expr()?;
+        Some(self.spanned(start, op(Box::new(left), Box::new(right))))
+    }
+
+    /// `::`, which associates to the right.
+    fn parse_cons_expr(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let head = self.parse_arith_expr()?;
+        if !matches!(self.peek(), Some(Token::Cons)) {
+            return Some(head);
+        }
+        self.next();
+        let tail = self.parse_cons_expr()?;
+        Some(self.spanned(start, Expr::Cons(Box::new(head), Box::new(tail))))
+    }
+
+    fn parse_arith_expr(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let mut left = self.parse_term()?;
+        loop {
+            let op: fn(Box<Expr>, Box<Expr>) -> Expr = match self.peek() {
+                Some(Token::Plus) => Expr::Plus,
+                Some(Token::Minus) => Expr::Minus,
+                Some(Token::Caret) => Expr::Concat,
+                _ => return Some(left),
+            };
+            self.next();
+            let right = self.parse_term()?;
+            left = self.spanned(start, op(Box::new(left), Box::new(right)));
+        }
+    }
+
+    fn parse_term(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let mut left = self.parse_unary()?;
         loop {
-            match self.tokens.peek() {
-                Some(&Token::Equal) => {
-                    self.tokens.next(); // Consume the Equal token
-                    let right = self.parse_basic_binary_expr()?;
-                    return Some(Expr::Equal(Box::new(left), Box::new(right)));
+            let op: fn(Box<Expr>, Box<Expr>) -> Expr = match self.peek() {
+                Some(Token::Mult) => Expr::Mult,
+                Some(Token::Divide) => Expr::Divide,
+                Some(Token::Mod) => Expr::Mod,
+                _ => return Some(left),
+            };
+            self.next();
+            let right = self.parse_unary()?;
+            left = self.spanned(start, op(Box::new(left), Box::new(right)));
+        }
+    }
+
+    /// Negation binds tighter than the binary operators but looser than
+    /// application, so `-f x` is `-(f x)`.
+    fn parse_unary(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        if !matches!(self.peek(), Some(Token::Minus)) {
+            return self.parse_app();
+        }
+        self.next();
+        let e = self.parse_unary()?;
+        Some(self.spanned(start, Expr::Negate(Box::new(e))))
+    }
+
+    fn parse_app(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let index = match self.peek() {
+            Some(Token::Fst) => Some(0),
+            Some(Token::Snd) => Some(1),
+            Some(&Token::Projection(0)) => return self.fail("a position from `#1`"),
+            Some(&Token::Projection(n)) => Some(n - 1),
+            _ => None,
+        };
+        if let Some(index) = index {
+            self.next();
+            let e = self.parse_atom()?;
+            return Some(self.spanned(start, Expr::Project(Box::new(e), index)));
+        }
+        if matches!(self.peek(), Some(Token::Ref)) {
+            self.next();
+            let e = self.parse_atom()?;
+            return Some(self.spanned(start, Expr::Ref(Box::new(e))));
+        }
+        let mut func = self.parse_atom()?;
+        while self.peek().is_some_and(starts_atom) {
+            let arg = self.parse_atom()?;
+            func = self.spanned(start, Expr::Apply(Box::new(func), Box::new(arg)));
+        }
+        Some(func)
+    }
+
+    /// An atom followed by any number of `.[i]` indexing and `.x` field
+    /// access suffixes.
+    pub fn parse_atom(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let mut expr = self.parse_simple_atom()?;
+        loop {
+            expr = match self.peek() {
+                Some(Token::DotLSquareBrack) => {
+                    self.next();
+                    let index = self.parse_expr()?;
+                    self.expect(Token::RSquareBrack, "`]`")?;
+                    Expr::Index(Box::new(expr), Box::new(index))
                 }
-                Some(&Token::Less) => {
-                    self.tokens.next(); // Consume the Less token
-                    let right = self.parse_basic_binary_expr()?;
-                    return Some(Expr::Less(Box::new(left), Box::new(right)));
+                Some(Token::Dot) => {
+                    self.next();
+                    Expr::Field(Box::new(expr), self.expect_var()?)
                 }
-                // Here's where you'd handle other operations or end the loop
-                _ => break,
-            }
+                _ => return Some(expr),
+            };
+            expr = self.spanned(start, expr);
         }
-        Some(left)
     }
+
+    fn parse_simple_atom(&mut self) -> Option<Expr> {
+        let start = self.pos;
+        let expr = match self.peek() {
+            Some(Token::Var(_)) => Expr::Var(self.parse_name()?),
+            Some(Token::Integer(value)) => {
+                #[allow(clippy::clone_on_copy)] // `Integer` is only `Copy` without `bigint`
+                let value = value.clone();
+                self.next();
+                Expr::Int(value)
+            }
+            Some(&Token::Float(value)) => {
+                self.next();
+                Expr::Float(value)
+            }
+            Some(Token::Bang) => {
+                self.next();
+                Expr::Deref(Box::new(self.parse_simple_atom()?))
+            }
+            Some(Token::Str(s)) => {
+                let s = s.clone();
+                self.next();
+                Expr::Str(s)
+            }
+            Some(&Token::Char(c)) => {
+                self.next();
+                Expr::Char(c)
+            }
+            Some(Token::True) => {
+                self.next();
+                Expr::Bool(true)
+            }
+            Some(Token::False) => {
+                self.next();
+                Expr::Bool(false)
+            }
+            Some(Token::LSquareBrack) => match self.parse_nil()? {
+                Type::List(ty) => Expr::None(*ty),
+                _ => unreachable!(),
+            },
+            Some(Token::LParen) => {
+                self.next();
+                if matches!(self.peek(), Some(Token::RParen)) {
+                    self.next();
+                    return Some(self.spanned(start, Expr::Unit));
+                }
+                let e = self.parse_expr()?;
+                if !matches!(self.peek(), Some(Token::Comma)) {
+                    self.expect(Token::RParen, "`)`")?;
+                    return Some(e);
+                }
+                let mut es = vec![e];
+                while matches!(self.peek(), Some(Token::Comma)) {
+                    self.next();
+                    es.push(self.parse_expr()?);
+                }
+                self.expect(Token::RParen, "`)`")?;
+                Expr::Tuple(es)
+            }
+            Some(Token::LBrace) => {
+                self.next();
+                // `{ x = ...` is a literal; anything else is an update.
+                let is_literal = matches!(self.peek(), Some(Token::Var(_)))
+                    && matches!(self.peek_nth(1), Some(Token::Equal));
+                let field = |parser: &mut Self| parser.parse_unsequenced_expr();
+                if is_literal {
+                    Expr::Record(self.parse_fields(Token::Equal, "`=`", field)?)
+                } else {
+                    let e = self.parse_atom()?;
+                    self.expect(Token::With, "`with`")?;
+                    let fields = self.parse_fields(Token::Equal, "`=`", field)?;
+                    Expr::Update(Box::new(e), fields)
+                }
+            }
+            _ => return self.fail("an expression"),
+        };
+        Some(self.spanned(start, expr))
+    }
+}
+
+fn starts_atom(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Var(_)
+            | Token::Integer(_)
+            | Token::Float(_)
+            | Token::Str(_)
+            | Token::Char(_)
+            | Token::Bang
+            | Token::True
+            | Token::False
+            | Token::LSquareBrack
+            | Token::LParen
+            | Token::LBrace
+    )
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::lexer::Token;
+    use crate::lexer::{self, Token};
     use logos::Logos;
 
     fn tokenize(source: &str) -> Vec<Token> {
-        let lexer = Token::lexer(source);
-        lexer.filter_map(Result::ok).collect()
+        let tokens = lexer::lex(source).expect("lexing failed");
+        tokens.into_iter().map(|(token, _)| token).collect()
     }
 
     #[test]
@@ -197,7 +801,7 @@ mod tests {
     fn parse_simple_ty_times() {
         let tokens = vec![Token::TypeInt, Token::Mult, Token::TypeBool]; // Represents: int * bool
         let mut parser = Parser::new(tokens.into_iter());
-        let expected = Type::Mult(Box::new(Type::Int), Box::new(Type::Bool));
+        let expected = Type::Tuple(vec![Type::Int, Type::Bool]);
         assert_eq!(parser.parse_ty_times(), Some(expected));
     }
 
@@ -212,10 +816,7 @@ mod tests {
             Token::TypeList, // Represents: int * bool * int list
         ];
         let mut parser = Parser::new(tokens.into_iter());
-        let expected = Type::Mult(
-            Box::new(Type::Mult(Box::new(Type::Int), Box::new(Type::Bool))),
-            Box::new(Type::List(Box::new(Type::Int))),
-        );
+        let expected = Type::Tuple(vec![Type::Int, Type::Bool, Type::List(Box::new(Type::Int))]);
         assert_eq!(parser.parse_ty_times(), Some(expected));
     }
 
@@ -229,10 +830,10 @@ mod tests {
             Token::TypeList, // Represents: int list * bool list
         ];
         let mut parser = Parser::new(tokens.into_iter());
-        let expected = Type::Mult(
-            Box::new(Type::List(Box::new(Type::Int))),
-            Box::new(Type::List(Box::new(Type::Bool))),
-        );
+        let expected = Type::Tuple(vec![
+            Type::List(Box::new(Type::Int)),
+            Type::List(Box::new(Type::Bool)),
+        ]);
         assert_eq!(parser.parse_ty_times(), Some(expected));
     }
     #[test]
@@ -247,10 +848,10 @@ mod tests {
         let mut parser = Parser::new(tokens.into_iter());
         let result = parser.parse_ty_times();
 
-        let expected = Some(Type::Mult(
-            Box::new(Type::List(Box::new(Type::Int))),
-            Box::new(Type::Bool),
-        ));
+        let expected = Some(Type::Tuple(vec![
+            Type::List(Box::new(Type::Int)),
+            Type::Bool,
+        ]));
 
         assert_eq!(result, expected);
     }
@@ -292,6 +893,503 @@ mod tests {
             ))))
         );
     }
+    #[test]
+    fn test_parse_float_type_and_literal() {
+        let mut parser = Parser::new(tokenize("float -> float").into_iter());
+        assert_eq!(
+            parser.parse_ty(),
+            Some(Type::Func(Box::new(Type::Float), Box::new(Type::Float)))
+        );
+        let mut parser = Parser::new(tokenize("1.5 < 2.0").into_iter());
+        assert_eq!(
+            parser.parse_binary_expr(),
+            Some(Expr::Less(
+                Box::new(Expr::Float(1.5)),
+                Box::new(Expr::Float(2.0))
+            ))
+        );
+    }
+
+    fn parse(source: &str) -> Option<Expr> {
+        let mut parser = Parser::new(tokenize(source).into_iter());
+        let expr = parser.parse_expr()?;
+        parser.at_end().then_some(expr)
+    }
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(crate::num::Integer::from(n)))
+    }
+
+    fn var(x: &str) -> Box<Expr> {
+        Box::new(Expr::Var(x.to_string()))
+    }
+
+    #[test]
+    fn test_parse_arithmetic_without_spaces() {
+        assert_eq!(parse("x-1"), Some(Expr::Minus(var("x"), int(1))));
+        assert_eq!(parse("n+1"), Some(Expr::Plus(var("n"), int(1))));
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        // 1 + 2 * 3 - 4 < f x
+        assert_eq!(
+            parse("1 + 2 * 3 - 4 < f x"),
+            Some(Expr::Less(
+                Box::new(Expr::Minus(
+                    Box::new(Expr::Plus(int(1), Box::new(Expr::Mult(int(2), int(3))))),
+                    int(4),
+                )),
+                Box::new(Expr::Apply(var("f"), var("x"))),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_unary_minus() {
+        assert_eq!(parse("-1"), Some(Expr::Negate(int(1))));
+        assert_eq!(
+            parse("-f x * 2"),
+            Some(Expr::Mult(
+                Box::new(Expr::Negate(Box::new(Expr::Apply(var("f"), var("x"))))),
+                int(2),
+            ))
+        );
+        assert_eq!(
+            parse("x - -1"),
+            Some(Expr::Minus(var("x"), Box::new(Expr::Negate(int(1)))))
+        );
+    }
+
+    #[test]
+    fn test_parse_application_is_left_associative() {
+        assert_eq!(
+            parse("f x (g y)"),
+            Some(Expr::Apply(
+                Box::new(Expr::Apply(var("f"), var("x"))),
+                Box::new(Expr::Apply(var("g"), var("y"))),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_lists_and_pairs() {
+        assert_eq!(
+            parse("1 :: 2 :: [int]"),
+            Some(Expr::Cons(
+                int(1),
+                Box::new(Expr::Cons(int(2), Box::new(Expr::None(Type::Int)))),
+            ))
+        );
+        assert_eq!(
+            parse("fst (x, true)"),
+            Some(Expr::Project(
+                Box::new(Expr::Tuple(vec![*var("x"), Expr::Bool(true)])),
+                0
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_binders() {
+        let source = "rec f : int -> int is fun n : int => \
+                      if n < 1 then 0 else n + f (n - 1)";
+        assert_eq!(
+            parse(source),
+            Some(Expr::Recursion(
+                "f".to_string(),
+                Box::new(Type::Func(Box::new(Type::Int), Box::new(Type::Int))),
+                Box::new(Expr::Func(
+                    "n".to_string(),
+                    Box::new(Type::Int),
+                    Box::new(Expr::If(
+                        Box::new(Expr::Less(var("n"), int(1))),
+                        int(0),
+                        Box::new(Expr::Plus(
+                            var("n"),
+                            Box::new(Expr::Apply(
+                                var("f"),
+                                Box::new(Expr::Minus(var("n"), int(1)))
+                            )),
+                        )),
+                    )),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_match() {
+        assert_eq!(
+            parse("match l with [int list] -> 0 | x :: xs -> 1"),
+            Some(Expr::Match(
+                var("l"),
+                Box::new(Type::List(Box::new(Type::Int))),
+                int(0),
+                "x".to_string(),
+                "xs".to_string(),
+                int(1),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_function_types() {
+        let mut parser = Parser::new(tokenize("(int -> int) list * bool -> int").into_iter());
+        let fun = Type::Func(Box::new(Type::Int), Box::new(Type::Int));
+        assert_eq!(
+            parser.parse_ty(),
+            Some(Type::Func(
+                Box::new(Type::Tuple(vec![Type::List(Box::new(fun)), Type::Bool])),
+                Box::new(Type::Int),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let mut parser = Parser::new(tokenize("let x = 1;; x + 1;; :quit").into_iter());
+        assert_eq!(
+            parser.parse_program(),
+            Some(vec![
+                Commands::Fn("x".to_string(), *int(1), None),
+                Commands::Expr(Expr::Plus(var("x"), int(1))),
+                Commands::Exit,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_strings() {
+        // Indexing binds tighter than application, `^` like `+`.
+        let expected = Expr::Equal(
+            Box::new(Expr::Concat(
+                Box::new(Expr::Str("a".to_string())),
+                Box::new(Expr::Apply(
+                    var("f"),
+                    Box::new(Expr::Index(var("s"), int(0))),
+                )),
+            )),
+            Box::new(Expr::Char('b')),
+        );
+        assert_eq!(parse(r#""a" ^ f s.[0] = 'b'"#), Some(expected.clone()));
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+        let mut parser = Parser::new(tokenize("string -> char list").into_iter());
+        assert_eq!(
+            parser.parse_ty(),
+            Some(Type::Func(
+                Box::new(Type::String),
+                Box::new(Type::List(Box::new(Type::Char)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_sequences() {
+        let print = |n| Box::new(Expr::Apply(var("print_int"), int(n)));
+        // The `else` branch stops at `;`, a function body does not.
+        let expected = Expr::Func(
+            "u".to_string(),
+            Box::new(Type::Unit),
+            Box::new(Expr::Seq(
+                Box::new(Expr::If(Box::new(Expr::Bool(true)), print(1), print(2))),
+                Box::new(Expr::Seq(print(3), Box::new(Expr::Unit))),
+            )),
+        );
+        let source = "fun u : unit => if true then print_int 1 else print_int 2; print_int 3; ()";
+        assert_eq!(parse(source), Some(expected));
+        let expected = Expr::If(
+            Box::new(Expr::Bool(true)),
+            Box::new(Expr::Seq(print(1), print(2))),
+            Box::new(Expr::Seq(print(3), print(4))),
+        );
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+    }
+
+    #[test]
+    fn test_parse_references() {
+        // r := !r + f !s.[0]; r
+        let expected = Expr::Seq(
+            Box::new(Expr::Assign(
+                var("r"),
+                Box::new(Expr::Plus(
+                    Box::new(Expr::Deref(var("r"))),
+                    Box::new(Expr::Apply(
+                        var("f"),
+                        Box::new(Expr::Index(Box::new(Expr::Deref(var("s"))), int(0))),
+                    )),
+                )),
+            )),
+            var("r"),
+        );
+        assert_eq!(parse("r := !r + f !s.[0]; r"), Some(expected.clone()));
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+        let expected = Expr::Deref(Box::new(Expr::Index(var("s"), int(0))));
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+        let expected = Expr::Ref(Box::new(Expr::Ref(Box::new(Expr::Tuple(vec![
+            *int(1),
+            *int(2),
+        ])))));
+        assert_eq!(parse("ref (ref (1, 2))"), Some(expected));
+        let mut parser = Parser::new(tokenize("int ref list").into_iter());
+        assert_eq!(
+            parser.parse_ty(),
+            Some(Type::List(Box::new(Type::Ref(Box::new(Type::Int)))))
+        );
+    }
+
+    #[test]
+    fn test_parse_exceptions() {
+        let source = "try if b then raise Not_found : int else f x with \
+                      | Failure msg -> 1 | Not_found -> 2 | e -> raise e : int | _ -> 3";
+        let expected = Expr::Try(
+            Box::new(Expr::If(
+                var("b"),
+                Box::new(Expr::Raise(var("Not_found"), Box::new(Type::Int))),
+                Box::new(Expr::Apply(var("f"), var("x"))),
+            )),
+            vec![
+                (
+                    Pattern::Constructor("Failure".to_string(), Some("msg".to_string())),
+                    *int(1),
+                ),
+                (Pattern::Constructor("Not_found".to_string(), None), *int(2)),
+                (
+                    Pattern::Var("e".to_string()),
+                    Expr::Raise(var("e"), Box::new(Type::Int)),
+                ),
+                (Pattern::Wildcard, *int(3)),
+            ],
+        );
+        assert_eq!(parse(source), Some(expected));
+        assert_eq!(
+            parse("raise (Failure \"no\") : int list"),
+            Some(Expr::Raise(
+                Box::new(Expr::Apply(
+                    var("Failure"),
+                    Box::new(Expr::Str("no".to_string()))
+                )),
+                Box::new(Type::List(Box::new(Type::Int)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_tuples_and_records() {
+        let triple = Expr::Tuple(vec![*int(1), *var("x"), *int(3)]);
+        let expected = Expr::Project(Box::new(triple.clone()), 2);
+        assert_eq!(parse("#3 (1, x, 3)"), Some(expected.clone()));
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+        assert_eq!(
+            parse("snd (1, x, 3)"),
+            Some(Expr::Project(Box::new(triple), 1))
+        );
+
+        let expected = Expr::Field(
+            Box::new(Expr::Update(
+                var("p"),
+                vec![
+                    ("y".to_string(), Expr::Apply(var("f"), var("x"))),
+                    (
+                        "z".to_string(),
+                        Expr::Record(vec![("a".to_string(), *int(1))]),
+                    ),
+                ],
+            )),
+            "y".to_string(),
+        );
+        assert_eq!(
+            parse("{ p with y = f x; z = { a = 1; } }.y"),
+            Some(expected.clone())
+        );
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+        let expected = Expr::Deref(Box::new(Expr::Field(var("r"), "x".to_string())));
+        assert_eq!(parse(&expected.to_string()), Some(expected));
+
+        let mut parser = Parser::new(tokenize("{ y : bool; x : int * (int * int) }").into_iter());
+        let pair = Type::Tuple(vec![Type::Int, Type::Int]);
+        assert_eq!(
+            parser.parse_ty(),
+            Some(Type::Record(vec![
+                ("x".to_string(), Type::Tuple(vec![Type::Int, pair])),
+                ("y".to_string(), Type::Bool),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_type_declarations() {
+        let mut parser =
+            Parser::new(tokenize("type pairs = (int * int) list;; [pairs]").into_iter());
+        let pair = Type::Tuple(vec![Type::Int, Type::Int]);
+        assert_eq!(
+            parser.parse_program(),
+            Some(vec![
+                Commands::Type("pairs".to_string(), Type::List(Box::new(pair))),
+                Commands::Expr(Expr::None(Type::Named("pairs".to_string()))),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_meta_commands() {
+        let parse = |source: &str| {
+            let mut parser = Parser::with_spans(lexer::lex(source).unwrap().into_iter());
+            parser
+                .parse_program()
+                .ok_or_else(|| parser.error().cloned().unwrap())
+        };
+        assert_eq!(
+            parse(":type  1 + 2 ;; :env;; :doc List.map;; :quit").unwrap(),
+            [
+                Commands::Meta("type".to_string(), "1 + 2".to_string(), 7),
+                Commands::Meta("env".to_string(), String::new(), 20),
+                Commands::Doc("List.map".to_string()),
+                Commands::Exit,
+            ]
+        );
+        assert_eq!(
+            parse(":doc 1 + x"),
+            Err((
+                5..10,
+                "expected a name after `:doc`, found `1 + x`".to_string()
+            ))
+        );
+        assert_eq!(
+            parse(":quit now"),
+            Err((6..9, "`:quit` takes no argument".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_print_commands() {
+        let source = "--| Two.\n--| Lines.\nlet x = 1 + 2;; import List;; \
+                      let rec f : int = f;; type t = int list;; :doc x;; x;; :quit";
+        let commands = Parser::new(tokenize(source).into_iter())
+            .parse_program()
+            .unwrap();
+        let printed: Vec<_> = commands.iter().map(Commands::to_string).collect();
+        assert_eq!(
+            printed,
+            [
+                "--| Two.\n--| Lines.\nlet x = 1 + 2",
+                "import List",
+                "let rec f : int = f",
+                "type t = int list",
+                ":doc x",
+                "x",
+                ":quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_imports_and_qualified_names() {
+        let source = "import List;; List.map f p.x;; :doc List.map";
+        let mut parser = Parser::new(tokenize(source).into_iter());
+        let commands = parser.parse_program().unwrap();
+        assert!(matches!(&commands[0], Commands::Import(name, _) if name == "List"));
+        assert_eq!(
+            commands[1],
+            Commands::Expr(Expr::Apply(
+                Box::new(Expr::Apply(var("List.map"), var("f"))),
+                Box::new(Expr::Field(var("p"), "x".to_string())),
+            ))
+        );
+        assert_eq!(commands[2], Commands::Doc("List.map".to_string()));
+    }
+
+    #[test]
+    fn test_parse_recursive_groups() {
+        let source = "let rec f : int = g and g : int = f;; let rec h : int = h in h; h";
+        let mut parser = Parser::new(tokenize(source).into_iter());
+        let group = vec![
+            ("f".to_string(), Type::Int, *var("g")),
+            ("g".to_string(), Type::Int, *var("f")),
+        ];
+        let local = Expr::LetRec(
+            vec![("h".to_string(), Type::Int, *var("h"))],
+            Box::new(Expr::Seq(var("h"), var("h"))),
+        );
+        assert_eq!(
+            parser.parse_program(),
+            Some(vec![
+                Commands::LetRec(group, None),
+                Commands::Expr(local.clone()),
+            ])
+        );
+        assert_eq!(parse("1 + let rec h : int = h in h"), None);
+        let local = Expr::LetRec(vec![("h".to_string(), Type::Int, *var("h"))], var("h"));
+        assert_eq!(
+            parse("f (let rec h : int = h in h)"),
+            Some(Expr::Apply(var("f"), Box::new(local)))
+        );
+        let mut parser = Parser::new(tokenize("let rec f : int = 1 and f : int = 2").into_iter());
+        assert_eq!(parser.parse_command(), None);
+        assert_eq!(parser.error().unwrap().1, "`f` is defined twice");
+    }
+
+    #[test]
+    fn test_parse_record_errors() {
+        let mut parser = Parser::new(tokenize("{ x = 1; x = 2 }").into_iter());
+        assert_eq!(parser.parse_expr(), None);
+        assert_eq!(parser.error().unwrap().1, "field `x` is given twice");
+        let mut parser = Parser::new(tokenize("{ x = 1, y = 2 }").into_iter());
+        assert_eq!(parser.parse_expr(), None);
+        assert_eq!(parser.error().unwrap().1, "expected `;` or `}`, found `,`");
+        let mut parser = Parser::new(tokenize("#0 p").into_iter());
+        assert_eq!(parser.parse_expr(), None);
+        assert_eq!(
+            parser.error().unwrap().1,
+            "expected a position from `#1`, found `#0`"
+        );
+    }
+
+    #[test]
+    fn test_parse_doc_comments() {
+        let source = "--| Adds one.\n--| Total.\nlet succ = fun n : int => --| ignored\n n + 1;; \
+                      let x = 1;; :doc succ";
+        let mut parser = Parser::new(tokenize(source).into_iter());
+        let commands = parser.parse_program().unwrap();
+        assert!(matches!(
+            &commands[0],
+            Commands::Fn(x, _, Some(doc)) if x == "succ" && doc == "Adds one.\nTotal."
+        ));
+        assert!(matches!(&commands[1], Commands::Fn(_, _, None)));
+        assert_eq!(commands[2], Commands::Doc("succ".to_string()));
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let source = "if x then 1";
+        let tokens = Token::lexer(source)
+            .spanned()
+            .map(|(token, span)| (token.unwrap(), span));
+        let mut parser = Parser::with_spans(tokens);
+        assert_eq!(parser.parse_expr(), None);
+        assert_eq!(
+            parser.error(),
+            Some(&(11..11, "expected `else`, found end of input".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_records_spans() {
+        let source = "1 + x / 0";
+        let tokens = Token::lexer(source)
+            .spanned()
+            .map(|(token, span)| (token.unwrap(), span));
+        let expr = Parser::with_spans(tokens).parse_expr().unwrap();
+        let Expr::Spanned(span, plus) = expr else {
+            panic!("expected a span");
+        };
+        assert_eq!(span, 0..9);
+        let Expr::Plus(_, divide) = *plus else {
+            panic!("expected an addition");
+        };
+        assert!(matches!(*divide, Expr::Spanned(span, _) if span == (4..9)));
+    }
+
     #[test]
     fn test_simple_boolean_expression() {
         let left = Some(Expr::Equal(
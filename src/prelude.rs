@@ -0,0 +1,93 @@
+//! The prelude: functions written in flock itself that every session starts
+//! with.
+
+/// The name the prelude can be imported under.
+pub const NAME: &str = "Prelude";
+
+/// What errors in the prelude are reported against.
+pub const PATH: &str = "<prelude>";
+
+pub const SOURCE: &str = include_str!("prelude.flock");
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{Evaluator, Strategy};
+    use crate::toplevel::{self, Session};
+
+    fn eval(session: &mut Session, source: &str) -> String {
+        let commands = toplevel::parse(source).unwrap();
+        session.exec(&commands[0]).unwrap().to_string()
+    }
+
+    #[test]
+    fn type_checks_on_its_own() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::without_prelude(Evaluator::new(strategy));
+            assert_eq!(session.load_prelude(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn defines_list_functions() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::new(strategy);
+            for (source, expected) in [
+                ("range 0 5", "- : Int List = [0, 1, 2, 3, 4]"),
+                ("range 3 3", "- : Int List = []"),
+                (
+                    "map (fun x : int => x * x) (range 1 4)",
+                    "- : Int List = [1, 4, 9]",
+                ),
+                (
+                    "filter (fun x : int => x % 2 = 0) (range 0 7)",
+                    "- : Int List = [0, 2, 4, 6]",
+                ),
+                (
+                    "fold (fun a : int => fun x : int => a * 10 + x) 0 (1 :: 2 :: 3 :: [int])",
+                    "- : Int = 123",
+                ),
+                ("length (range 0 10)", "- : Int = 10"),
+                (
+                    "append (1 :: 2 :: [int]) (3 :: [int])",
+                    "- : Int List = [1, 2, 3]",
+                ),
+                ("reverse (1 :: 2 :: 3 :: [int])", "- : Int List = [3, 2, 1]"),
+                ("nth (5 :: 6 :: 7 :: [int]) 2", "- : Int = 7"),
+                (
+                    "zip (1 :: 2 :: [int]) (3 :: 4 :: [int])",
+                    "- : (Int * Int) List = [(1, 3), (2, 4)]",
+                ),
+                (
+                    "try nth (1 :: [int]) 1 with Failure s -> 1 | _ -> 2",
+                    "- : Int = 1",
+                ),
+                (
+                    "try nth (1 :: [int]) (0 - 1) with Invalid_argument s -> 1 | _ -> 2",
+                    "- : Int = 1",
+                ),
+                (
+                    "try (match zip (1 :: [int]) [int] with [int * int] -> 1 | p :: ps -> 2) \
+                     with Invalid_argument s -> 0 | _ -> 3",
+                    "- : Int = 0",
+                ),
+            ] {
+                assert_eq!(eval(&mut session, source), expected, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn can_be_shadowed_and_imported() {
+        let mut session = Session::new(Strategy::Lazy);
+        eval(
+            &mut session,
+            "let length = fun s : string => string_length s",
+        );
+        assert_eq!(eval(&mut session, "length \"abc\""), "- : Int = 3");
+        eval(&mut session, "import Prelude");
+        assert_eq!(
+            eval(&mut session, "Prelude.length (1 :: [int])"),
+            "- : Int = 1"
+        );
+    }
+}
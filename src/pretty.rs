@@ -0,0 +1,715 @@
+//! Printing programs as source text that parses back to the same commands,
+//! breaking lines that would be too wide.
+
+use crate::ast::{quote, Binding, Commands, Expr, Span};
+use crate::lexer::{self, Token};
+use crate::toplevel::{self, Error};
+
+/// How printed programs are laid out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    /// The width lines are kept within where the syntax allows.
+    pub width: usize,
+    /// How far each level of nesting is indented.
+    pub indent: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 2,
+        }
+    }
+}
+
+impl Style {
+    /// Lays everything out on a single line, but for comments.
+    pub fn single_line() -> Self {
+        Self {
+            width: isize::MAX as usize,
+            ..Self::default()
+        }
+    }
+}
+
+/// Text laid out in groups, each of which is broken across lines where it
+/// does not fit on one.
+#[derive(Debug, Clone)]
+enum Doc {
+    Text(String),
+    /// A line break, or the given text if its group fits on one line.
+    Line(&'static str),
+    /// A line break even in a group that would otherwise fit on one line.
+    HardLine,
+    /// Indents the lines broken inside it further.
+    Nest(usize, Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+/// A space, or a line break.
+const LINE: Doc = Doc::Line(" ");
+
+/// Nothing, or a line break.
+const SOFT_LINE: Doc = Doc::Line("");
+
+fn group(docs: Vec<Doc>) -> Doc {
+    Doc::Group(Box::new(Doc::Concat(docs)))
+}
+
+fn join(docs: Vec<Doc>, separator: &[Doc]) -> Vec<Doc> {
+    let mut joined = Vec::new();
+    for (i, doc) in docs.into_iter().enumerate() {
+        if i > 0 {
+            joined.extend_from_slice(separator);
+        }
+        joined.push(doc);
+    }
+    joined
+}
+
+/// Lays out `doc` within `width` where it can, without trailing spaces.
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack = vec![(0, false, doc)];
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                column = match s.rfind('\n') {
+                    Some(i) => s[i + 1..].chars().count(),
+                    None => column + s.chars().count(),
+                };
+            }
+            Doc::Line(s) if flat => {
+                out.push_str(s);
+                column += s.len();
+            }
+            Doc::Line(_) | Doc::HardLine => {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent));
+                column = indent;
+            }
+            Doc::Nest(n, doc) => stack.push((indent + n, flat, doc)),
+            Doc::Group(doc) => {
+                let flat = flat || fits(width.saturating_sub(column), (indent, doc), &stack);
+                stack.push((indent, flat, doc));
+            }
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+        }
+    }
+    let lines: Vec<_> = out.lines().map(str::trim_end).collect();
+    lines.join("\n")
+}
+
+/// Whether `doc` fits in `width` when laid out on one line, together with
+/// whatever in `rest` follows it up to the next line break.
+fn fits(width: usize, (indent, doc): (usize, &Doc), rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut width = width.min(isize::MAX as usize) as isize;
+    let mut stack = vec![(indent, true, doc)];
+    let mut rest = rest.iter().rev();
+    loop {
+        let (indent, flat, doc) = match stack.pop().or_else(|| rest.next().copied()) {
+            Some(next) => next,
+            None => return true,
+        };
+        match doc {
+            Doc::Text(s) => {
+                let first_line = s.split('\n').next().unwrap_or_default();
+                width -= first_line.chars().count() as isize;
+                if s.contains('\n') {
+                    return width >= 0;
+                }
+            }
+            Doc::Line(s) if flat => width -= s.len() as isize,
+            Doc::Line(_) => return true,
+            Doc::HardLine => return !flat,
+            Doc::Nest(n, doc) => stack.push((indent + n, flat, doc)),
+            Doc::Group(doc) => stack.push((indent, flat, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+        }
+        if width < 0 {
+            return false;
+        }
+    }
+}
+
+/// A comment as written in the source, including its `--` or `{- -}`.
+#[derive(Debug)]
+struct Comment {
+    span: Span,
+    text: String,
+}
+
+/// The comments in `source`, which lexes to `tokens`: the ones the lexer
+/// skips between tokens and the `--|` comments documenting definitions.
+fn comments(source: &str, tokens: &[(Token, Span)]) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let mut end = 0;
+    let gaps = tokens
+        .iter()
+        .map(|(token, span)| (Some(token), span.clone()))
+        .chain([(None, source.len()..source.len())]);
+    for (token, span) in gaps {
+        let mut i = end;
+        while i < span.start {
+            let rest = &source[i..span.start];
+            let len = if rest.starts_with("--") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if rest.starts_with("{-") {
+                block_comment_len(rest)
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+                continue;
+            };
+            comments.push(Comment {
+                span: i..i + len,
+                text: rest[..len].trim_end().to_string(),
+            });
+            i += len;
+        }
+        if let Some(Token::DocComment(_)) = token {
+            comments.push(Comment {
+                span: span.clone(),
+                text: source[span.clone()].trim_end().to_string(),
+            });
+        }
+        end = span.end;
+    }
+    comments
+}
+
+/// The length of the block comment `text` starts with, which the lexer has
+/// already checked is terminated.
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("{-") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("-}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    text.len()
+}
+
+/// Whether there is a blank line right before `offset` in `source`.
+fn blank_line_before(source: &str, offset: usize) -> bool {
+    let before = &source[..offset];
+    let space = &before[before.trim_end().len()..];
+    space.matches('\n').count() > 1
+}
+
+/// Builds the layout of commands, placing the comments left in `comments`
+/// before the first construct after them.
+struct Printer<'a> {
+    style: &'a Style,
+    /// In reverse order, so that the next one is last.
+    comments: Vec<Comment>,
+    /// Whether documentation is printed from the commands, rather than
+    /// with the other comments.
+    docs: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(style: &'a Style) -> Self {
+        Self {
+            style,
+            comments: Vec::new(),
+            docs: true,
+        }
+    }
+
+    fn nest(&self, docs: Vec<Doc>) -> Doc {
+        Doc::Nest(self.style.indent, Box::new(Doc::Concat(docs)))
+    }
+
+    /// The comments before `offset`, each on a line of its own, keeping
+    /// blank lines between them from `source` if it is given.
+    fn comments_before(&mut self, offset: usize, source: Option<&str>) -> Vec<Doc> {
+        let mut docs = Vec::new();
+        while let Some(comment) = self.comments.pop_if(|comment| comment.span.start < offset) {
+            if let Some(source) = source {
+                if !docs.is_empty() && blank_line_before(source, comment.span.start) {
+                    docs.push(Doc::HardLine);
+                }
+            }
+            docs.push(text(comment.text));
+            docs.push(Doc::HardLine);
+        }
+        docs
+    }
+
+    /// `e` without the spans around it, unless a comment has to be placed
+    /// before it.
+    fn bare<'e>(&self, mut e: &'e Expr) -> &'e Expr {
+        while let Expr::Spanned(span, inner) = e {
+            match self.comments.last() {
+                Some(comment) if comment.span.start < span.start => break,
+                _ => e = inner,
+            }
+        }
+        e
+    }
+
+    fn command(&mut self, command: &Commands) -> Doc {
+        let doc = |doc: &Option<String>| -> Vec<Doc> {
+            let lines = doc.iter().flat_map(|doc| doc.lines());
+            lines
+                .flat_map(|line| [text(format!("--| {}", line)), Doc::HardLine])
+                .collect()
+        };
+        let (mut docs, command) = match command {
+            Commands::Expr(e) => (Vec::new(), self.expr(e, -1)),
+            Commands::Fn(x, e, d) => {
+                let e = self.expr(e, -1);
+                let definition =
+                    group(vec![text(format!("let {} =", x)), self.nest(vec![LINE, e])]);
+                (doc(d), definition)
+            }
+            Commands::LetRec(bindings, d) => {
+                let bindings = self.bindings(bindings);
+                (doc(d), group([vec![text("let rec ")], bindings].concat()))
+            }
+            Commands::Type(x, ty) => (Vec::new(), text(format!("type {} = {}", x, ty.to_source()))),
+            Commands::Import(name, _) => (Vec::new(), text(format!("import {}", name))),
+            Commands::Doc(x) => (Vec::new(), text(format!(":doc {}", x))),
+            Commands::Exit => (Vec::new(), text(":quit")),
+            Commands::Meta(name, arg, _) if arg.is_empty() => {
+                (Vec::new(), text(format!(":{}", name)))
+            }
+            Commands::Meta(name, arg, _) => (Vec::new(), text(format!(":{} {}", name, arg))),
+        };
+        if !self.docs {
+            docs.clear();
+        }
+        docs.push(command);
+        Doc::Concat(docs)
+    }
+
+    /// `f : t = e and ...`, as in `let rec`.
+    fn bindings(&mut self, bindings: &[Binding]) -> Vec<Doc> {
+        let bindings = bindings
+            .iter()
+            .map(|(x, ty, e)| {
+                let e = self.expr(e, 0);
+                group(vec![
+                    text(format!("{} : {} =", x, ty.to_source())),
+                    self.nest(vec![LINE, e]),
+                ])
+            })
+            .collect();
+        join(bindings, &[LINE, text("and ")])
+    }
+
+    /// `{ x = e; ... }`. Anything that could swallow the `;` after it is
+    /// parenthesized.
+    fn fields(&mut self, open: Doc, fields: &[(String, Expr)]) -> Doc {
+        let fields = fields
+            .iter()
+            .map(|(x, e)| {
+                let e = self.expr(e, 4);
+                group(vec![text(format!("{} =", x)), self.nest(vec![LINE, e])])
+            })
+            .collect();
+        let fields = join(fields, &[text(";"), LINE]);
+        group(vec![
+            open,
+            self.nest([vec![LINE], fields].concat()),
+            LINE,
+            text("}"),
+        ])
+    }
+
+    /// `e`, parenthesized if it binds looser than `outer_precedence`.
+    fn expr(&mut self, e: &Expr, outer_precedence: i32) -> Doc {
+        if let Expr::Spanned(span, e) = e {
+            let mut docs = self.comments_before(span.start, None);
+            docs.push(self.expr(e, outer_precedence));
+            return Doc::Concat(docs);
+        }
+        let (inner_precedence, doc) = self.unparenthesized(e);
+        if inner_precedence > outer_precedence {
+            doc
+        } else {
+            Doc::Concat(vec![text("("), Doc::Nest(1, Box::new(doc)), text(")")])
+        }
+    }
+
+    fn binary(&mut self, e1: &Expr, p1: i32, op: &str, e2: &Expr, p2: i32) -> Doc {
+        let e1 = self.expr(e1, p1);
+        let e2 = self.expr(e2, p2);
+        group(vec![
+            e1,
+            text(format!(" {}", op)),
+            self.nest(vec![LINE, e2]),
+        ])
+    }
+
+    /// The precedence of `e`, and its layout without parentheses around it.
+    fn unparenthesized(&mut self, e: &Expr) -> (i32, Doc) {
+        use Expr::*;
+        match e {
+            Spanned(_, e) => self.unparenthesized(e),
+            Var(x) => (10, text(x)),
+            Unit => (10, text("()")),
+            // Negative numbers only come from evaluation, and print like
+            // negations.
+            Int(n) if n.to_string().starts_with('-') => (8, text(n.to_string())),
+            Int(n) => (10, text(n.to_string())),
+            // `Debug` keeps the decimal point on whole numbers.
+            Float(x) if x.is_sign_negative() => (8, text(format!("{:?}", x))),
+            Float(x) => (10, text(format!("{:?}", x))),
+            Bool(b) => (10, text(b.to_string())),
+            Str(s) => (10, text(quote(s, '"'))),
+            Char(c) => (10, text(quote(&c.to_string(), '\''))),
+            Index(e1, e2) => {
+                let e1 = self.expr(e1, 9);
+                let e2 = self.expr(e2, 0);
+                (10, Doc::Concat(vec![e1, text(".["), e2, text("]")]))
+            }
+            Tuple(es) => {
+                let es = es.iter().map(|e| self.expr(e, 0)).collect();
+                let es = join(es, &[text(","), LINE]);
+                let tuple = group(vec![
+                    text("("),
+                    self.nest([vec![SOFT_LINE], es].concat()),
+                    SOFT_LINE,
+                    text(")"),
+                ]);
+                (10, tuple)
+            }
+            Record(fields) => (10, self.fields(text("{"), fields)),
+            Update(e, fields) => {
+                let e = self.expr(e, 9);
+                let open = Doc::Concat(vec![text("{ "), e, text(" with")]);
+                (10, self.fields(open, fields))
+            }
+            Field(e, x) => {
+                let e = self.expr(e, 9);
+                (10, Doc::Concat(vec![e, text(format!(".{}", x))]))
+            }
+            None(ty) => (10, text(format!("[{}]", ty.to_source()))),
+            // `!` binds tighter than indexing and field access, so `!s.[0]` is
+            // `(!s).[0]`.
+            Deref(e) => match e.unspanned() {
+                Index(_, _) | Field(_, _) => {
+                    let e = self.expr(e, -1);
+                    (10, Doc::Concat(vec![text("!("), e, text(")")]))
+                }
+                _ => (10, Doc::Concat(vec![text("!"), self.expr(e, 9)])),
+            },
+            Ref(e) => (9, Doc::Concat(vec![text("ref "), self.expr(e, 9)])),
+            Project(e, i) => {
+                let e = self.expr(e, 9);
+                (9, Doc::Concat(vec![text(format!("#{} ", i + 1)), e]))
+            }
+            Apply(_, _) => {
+                let mut args = Vec::new();
+                let mut func = e;
+                while let Apply(f, arg) = self.bare(func) {
+                    args.push(arg);
+                    func = f;
+                }
+                let func = self.expr(func, 8);
+                let args: Vec<_> = args
+                    .into_iter()
+                    .rev()
+                    .flat_map(|arg| [LINE, self.expr(arg, 9)])
+                    .collect();
+                (9, group(vec![func, self.nest(args)]))
+            }
+            Negate(e) => (8, Doc::Concat(vec![text("-"), self.expr(e, 8)])),
+            Mult(e1, e2) => (8, self.binary(e1, 7, "*", e2, 8)),
+            Divide(e1, e2) => (8, self.binary(e1, 7, "/", e2, 8)),
+            Mod(e1, e2) => (8, self.binary(e1, 7, "%", e2, 8)),
+            Plus(e1, e2) => (7, self.binary(e1, 6, "+", e2, 7)),
+            Minus(e1, e2) => (7, self.binary(e1, 6, "-", e2, 7)),
+            Concat(e1, e2) => (7, self.binary(e1, 6, "^", e2, 7)),
+            Cons(e1, e2) => (6, self.binary(e1, 6, "::", e2, 5)),
+            Equal(e1, e2) => (5, self.binary(e1, 5, "=", e2, 5)),
+            Less(e1, e2) => (5, self.binary(e1, 5, "<", e2, 5)),
+            // `then` and `else` end the condition and the first branch, but
+            // the second branch has to end before whatever comes after the
+            // `if`.
+            If(e1, e2, e3) => {
+                let e1 = self.expr(e1, -1);
+                let e2 = self.expr(e2, -1);
+                let mut docs = vec![
+                    text("if "),
+                    self.nest(vec![e1]),
+                    text(" then"),
+                    self.nest(vec![LINE, e2]),
+                    LINE,
+                    text("else"),
+                ];
+                // `else if` chains stay at the same depth.
+                if let If(_, _, _) = self.bare(e3) {
+                    docs.extend([text(" "), self.expr(e3, 3)]);
+                } else {
+                    let e3 = self.expr(e3, 3);
+                    docs.push(self.nest(vec![LINE, e3]));
+                }
+                (4, group(docs))
+            }
+            Match(e1, ty, e2, x, y, e3) => {
+                let e1 = self.expr(e1, 3);
+                let e2 = self.expr(e2, 3);
+                let e3 = self.expr(e3, 3);
+                let nil = group(vec![
+                    text(format!("[{}] ->", ty.to_source())),
+                    self.nest(vec![LINE, e2]),
+                ]);
+                let cons = group(vec![
+                    text(format!("| {} :: {} ->", x, y)),
+                    self.nest(vec![LINE, e3]),
+                ]);
+                let docs = vec![
+                    text("match "),
+                    self.nest(vec![e1]),
+                    text(" with"),
+                    self.nest(vec![LINE, nil, LINE, cons]),
+                ];
+                (3, group(docs))
+            }
+            Raise(e, ty) => {
+                let e = self.expr(e, 4);
+                let raise = vec![text("raise "), e, text(format!(" : {}", ty.to_source()))];
+                (4, Doc::Concat(raise))
+            }
+            Try(e, handlers) => {
+                let e = self.expr(e, -1);
+                let mut alternatives = Vec::new();
+                for (pattern, handler) in handlers {
+                    let handler = self.expr(handler, 3);
+                    alternatives.push(LINE);
+                    alternatives.push(group(vec![
+                        text(format!("| {} ->", pattern)),
+                        self.nest(vec![LINE, handler]),
+                    ]));
+                }
+                let docs = vec![
+                    text("try "),
+                    self.nest(vec![e]),
+                    text(" with"),
+                    self.nest(alternatives),
+                ];
+                (3, group(docs))
+            }
+            Func(_, _, _) => {
+                // `fun x : t => fun y : u => e` keeps the parameters together.
+                let mut params = Vec::new();
+                let mut body = e;
+                while let Func(x, ty, e) = self.bare(body) {
+                    params.push(format!("fun {} : {} =>", x, ty.to_source()));
+                    body = e;
+                }
+                let body = self.expr(body, 0);
+                let func = group(vec![text(params.join(" ")), self.nest(vec![LINE, body])]);
+                (2, func)
+            }
+            Recursion(x, ty, e) => {
+                let e = self.expr(e, 0);
+                let header = text(format!("rec {} : {} is", x, ty.to_source()));
+                (1, group(vec![header, self.nest(vec![LINE, e])]))
+            }
+            LetRec(bindings, e) => {
+                let bindings = self.bindings(bindings);
+                let header = [vec![text("let rec ")], bindings, vec![text(" in")]].concat();
+                let e = self.expr(e, 0);
+                (1, group(vec![group(header), LINE, e]))
+            }
+            Assign(e1, e2) => (1, self.binary(e1, 5, ":=", e2, 0)),
+            Seq(e1, e2) => {
+                let e1 = self.expr(e1, 0);
+                let e2 = self.expr(e2, -1);
+                (0, group(vec![e1, text(";"), LINE, e2]))
+            }
+        }
+    }
+}
+
+/// `e` laid out in `style`.
+pub fn expr(e: &Expr, style: &Style) -> String {
+    render(&Printer::new(style).expr(e, -1), style.width)
+}
+
+/// `command` laid out in `style`, without the `;;` that ends it.
+pub fn command(command: &Commands, style: &Style) -> String {
+    render(&Printer::new(style).command(command), style.width)
+}
+
+/// The program `source` laid out in `style`, with every command ending in
+/// `;;` and with its comments kept. Formatting the result again leaves it
+/// as it is.
+pub fn format(source: &str, style: &Style) -> Result<String, Error> {
+    let tokens = lexer::lex(source).map_err(Error::Lex)?;
+    let commands = toplevel::parse(source)?;
+    // Where each command starts, after the comments documenting it.
+    let mut starts = Vec::new();
+    let mut at_start = true;
+    for (token, span) in &tokens {
+        match token {
+            Token::DocComment(_) => {}
+            Token::DoubleSemicolon => at_start = true,
+            _ if at_start => {
+                starts.push(span.start);
+                at_start = false;
+            }
+            _ => {}
+        }
+    }
+    let mut printer = Printer::new(style);
+    printer.comments = comments(source, &tokens);
+    printer.comments.reverse();
+    printer.docs = false;
+    let mut out = String::new();
+    let mut chunk = |printer: &mut Printer, start: usize, command: Option<&Commands>| {
+        let first = match printer.comments.last() {
+            Some(comment) if comment.span.start < start => comment.span.start,
+            _ => start,
+        };
+        let mut docs = printer.comments_before(start, Some(source));
+        if let Some(command) = command {
+            if !docs.is_empty() && blank_line_before(source, start) {
+                docs.push(Doc::HardLine);
+            }
+            docs.extend([printer.command(command), text(";;")]);
+        }
+        if docs.is_empty() {
+            return;
+        }
+        // Blank lines between commands are kept, though only one of them.
+        if !out.is_empty() && blank_line_before(source, first) {
+            out.push('\n');
+        }
+        out.push_str(&render(&Doc::Concat(docs), style.width));
+        out.push('\n');
+    };
+    for (command, start) in commands.iter().zip(starts) {
+        chunk(&mut printer, start, Some(command));
+    }
+    chunk(&mut printer, source.len() + 1, None);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// The commands in `source`, without spans.
+    fn parse(source: &str) -> Vec<Commands> {
+        let tokens = lexer::lex(source).unwrap();
+        Parser::new(tokens.into_iter().map(|(token, _)| token))
+            .parse_program()
+            .unwrap()
+    }
+
+    fn format_with(source: &str, width: usize, indent: usize) -> String {
+        format(source, &Style { width, indent }).unwrap()
+    }
+
+    const PROGRAM: &str = "\
+type point = { x : int; y : int };; let origin = { x = 0; y = 0 };;
+let moved = { origin with x = 1 };; let rec sum : int list -> int =
+fun l : int list => match l with [int] -> 0 | x :: xs -> x + sum xs;;
+let f = fun g : (int -> int) ref => fun n : int =>
+if n < 0 then raise Invalid_argument \"f\" : int else if n = 0 then !g 0
+else (match range 0 n with [int] -> 0 | x :: xs -> x);;
+let s = try nth (1 :: [int]) 3 with | Failure m -> 0 | _ -> -1;;
+let p = (fun x : int => x, rec f : int -> int is fun n : int => f n);;
+let r = ref 1;; r := !r + #2 (1, 2); print_string \"a\\n\" ^ \"b\".[0];;
+let rec even : int -> bool = fun n : int => if n = 0 then true else odd (n - 1)
+and odd : int -> bool = fun n : int => if n = 0 then false else even (n - 1) in even 4;;
+import List;; :type List.map;; :quit";
+
+    #[test]
+    fn prints_what_parses_back_to_the_same_commands() {
+        for width in [10, 40, 80] {
+            let formatted = format_with(PROGRAM, width, 2);
+            assert_eq!(parse(&formatted), parse(PROGRAM), "{}", formatted);
+        }
+        for command in parse(PROGRAM) {
+            assert_eq!(parse(&command.to_string()), [command]);
+        }
+    }
+
+    #[test]
+    fn is_idempotent() {
+        for (width, indent) in [(10, 2), (40, 4), (80, 2)] {
+            let once = format_with(PROGRAM, width, indent);
+            assert_eq!(format_with(&once, width, indent), once);
+        }
+    }
+
+    #[test]
+    fn breaks_long_expressions() {
+        let source =
+            "let sum = fun l : int list => match l with [int] -> 0 | x :: xs -> x + sum xs";
+        assert_eq!(
+            format_with(source, 40, 2),
+            "let sum =\n  fun l : int list =>\n    match l with\n      [int] -> 0\n      | x :: xs -> x + sum xs;;\n"
+        );
+        assert_eq!(
+            format_with("if a then b else if c then d else e", 20, 4),
+            "if a then\n    b\nelse if c then\n    d\nelse\n    e;;\n"
+        );
+        assert_eq!(format_with("f (a,b)   (c)", 80, 2), "f (a, b) c;;\n");
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let source = "\
+-- A file.
+
+{- Two
+   lines. -}
+--| Documented.
+let x = -- before the value
+  1;; -- after it
+
+
+let y = (2, {- inside -} 3);;
+-- At the end.
+";
+        let formatted = format_with(source, 80, 2);
+        assert_eq!(
+            formatted,
+            "\
+-- A file.
+
+{- Two
+   lines. -}
+--| Documented.
+let x =
+  -- before the value
+  1;;
+-- after it
+
+let y =
+  (
+    2,
+    {- inside -}
+    3
+  );;
+-- At the end.
+"
+        );
+        assert_eq!(format_with(&formatted, 80, 2), formatted);
+    }
+}
@@ -0,0 +1,280 @@
+//! Built-in functions that are in scope in every program.
+
+use crate::ast::Type;
+use crate::eval::{Env, Evaluator, Exception, RuntimeError, RuntimeErrorKind, Thunk, Value};
+use crate::num::{self, Integer};
+use std::fmt;
+use std::rc::Rc;
+
+/// What a primitive does with its argument.
+pub type Apply = dyn Fn(&mut Evaluator, Value) -> Result<Value, RuntimeError>;
+
+pub struct Primitive {
+    pub name: String,
+    pub ty: Type,
+    /// Called with the argument already evaluated, and the evaluator for
+    /// doing I/O.
+    pub apply: Box<Apply>,
+}
+
+impl fmt::Debug for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<primitive {} : {}>", self.name, self.ty)
+    }
+}
+
+fn builtin(
+    name: &str,
+    ty: Type,
+    apply: fn(&mut Evaluator, Value) -> Result<Value, RuntimeError>,
+) -> Primitive {
+    Primitive {
+        name: name.to_string(),
+        ty,
+        apply: Box::new(apply),
+    }
+}
+
+fn func(arg: Type, ret: Type) -> Type {
+    Type::Func(Box::new(arg), Box::new(ret))
+}
+
+/// The exception constructors, and whether each carries a string.
+pub const EXCEPTIONS: &[(&str, bool)] = &[
+    ("Failure", true),
+    ("Invalid_argument", true),
+    ("Sys_error", true),
+    ("Division_by_zero", false),
+    ("Overflow", false),
+    ("End_of_file", false),
+    ("Not_found", false),
+];
+
+fn exception(name: &str, v: Value) -> Result<Value, RuntimeError> {
+    match v {
+        Value::Str(s) => Ok(Value::Exn(Rc::new(Exception::new(name, Some(&s))))),
+        _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+            "a string",
+        ))),
+    }
+}
+
+/// The exceptions without an argument, which are values rather than
+/// functions.
+fn constants() -> Vec<(&'static str, Type, Value)> {
+    EXCEPTIONS
+        .iter()
+        .filter(|(_, carries)| !carries)
+        .map(|&(name, _)| {
+            let exn = Exception::new(name, None);
+            (name, Type::Exn, Value::Exn(Rc::new(exn)))
+        })
+        .collect()
+}
+
+/// The primitives every program starts with.
+fn builtins() -> Vec<Primitive> {
+    vec![
+        builtin(
+            "float_of_int",
+            func(Type::Int, Type::Float),
+            |_, v| match v {
+                Value::Int(n) => Ok(Value::Float(num::to_f64(&n))),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "an integer",
+                ))),
+            },
+        ),
+        builtin(
+            "int_of_float",
+            func(Type::Float, Type::Int),
+            |_, v| match v {
+                Value::Float(x) => num::from_f64(x)
+                    .map(Value::Int)
+                    .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::Overflow)),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch("a float"))),
+            },
+        ),
+        builtin(
+            "string_length",
+            func(Type::String, Type::Int),
+            |_, v| match v {
+                Value::Str(s) => num::from_usize(s.chars().count())
+                    .map(Value::Int)
+                    .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::Overflow)),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "a string",
+                ))),
+            },
+        ),
+        builtin(
+            "string_of_int",
+            func(Type::Int, Type::String),
+            |_, v| match v {
+                Value::Int(n) => Ok(Value::Str(n.to_string().into())),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "an integer",
+                ))),
+            },
+        ),
+        builtin(
+            "int_of_string",
+            func(Type::String, Type::Int),
+            |_, v| match v {
+                Value::Str(s) => s.trim().parse().map(Value::Int).map_err(|_| {
+                    RuntimeError::new(RuntimeErrorKind::InvalidArgument("int_of_string"))
+                }),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "a string",
+                ))),
+            },
+        ),
+        builtin("int_of_char", func(Type::Char, Type::Int), |_, v| match v {
+            Value::Char(c) => Ok(Value::Int(Integer::from(c as u32))),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "a character",
+            ))),
+        }),
+        builtin("char_of_int", func(Type::Int, Type::Char), |_, v| match v {
+            Value::Int(n) => num::to_usize(&n)
+                .and_then(|n| u32::try_from(n).ok())
+                .and_then(char::from_u32)
+                .map(Value::Char)
+                .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::InvalidArgument("char_of_int"))),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "an integer",
+            ))),
+        }),
+        builtin(
+            "string_of_char",
+            func(Type::Char, Type::String),
+            |_, v| match v {
+                Value::Char(c) => Ok(Value::Str(c.to_string().into())),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "a character",
+                ))),
+            },
+        ),
+        builtin("Failure", func(Type::String, Type::Exn), |_, v| {
+            exception("Failure", v)
+        }),
+        builtin("Invalid_argument", func(Type::String, Type::Exn), |_, v| {
+            exception("Invalid_argument", v)
+        }),
+        builtin("Sys_error", func(Type::String, Type::Exn), |_, v| {
+            exception("Sys_error", v)
+        }),
+        // Output is written as is; programs add their own newlines.
+        builtin("print_int", func(Type::Int, Type::Unit), |ev, v| match v {
+            Value::Int(n) => ev.write(&n.to_string()).map(|()| Value::Unit),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                "an integer",
+            ))),
+        }),
+        builtin(
+            "print_bool",
+            func(Type::Bool, Type::Unit),
+            |ev, v| match v {
+                Value::Bool(b) => ev.write(&b.to_string()).map(|()| Value::Unit),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "a boolean",
+                ))),
+            },
+        ),
+        builtin(
+            "print_string",
+            func(Type::String, Type::Unit),
+            |ev, v| match v {
+                Value::Str(s) => ev.write(&s).map(|()| Value::Unit),
+                _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch(
+                    "a string",
+                ))),
+            },
+        ),
+        builtin("read_int", func(Type::Unit, Type::Int), |ev, v| match v {
+            Value::Unit => ev
+                .read_line()?
+                .trim()
+                .parse()
+                .map(Value::Int)
+                .map_err(|_| RuntimeError::new(RuntimeErrorKind::InvalidArgument("read_int"))),
+            _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch("unit"))),
+        }),
+    ]
+}
+
+/// The primitives in scope in every program: the built-in ones and any
+/// registered by the program embedding the interpreter.
+#[derive(Clone)]
+pub struct Registry {
+    primitives: Vec<Rc<Primitive>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            primitives: builtins().into_iter().map(Rc::new).collect(),
+        }
+    }
+}
+
+impl Registry {
+    /// Adds a function `name` of type `ty`, replacing any earlier one of that
+    /// name. Its argument is evaluated fully before `apply` is called with it,
+    /// so that the components of tuples, records and lists are forced too.
+    /// Several arguments are best taken as a tuple.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        ty: Type,
+        apply: impl Fn(&mut Evaluator, Value) -> Result<Value, RuntimeError> + 'static,
+    ) -> Rc<Primitive> {
+        let prim = Rc::new(Primitive {
+            name: name.into(),
+            ty,
+            apply: Box::new(move |evaluator, v| {
+                evaluator.force_all(&v)?;
+                apply(evaluator, v)
+            }),
+        });
+        self.primitives.retain(|p| p.name != prim.name);
+        self.primitives.push(prim.clone());
+        prim
+    }
+
+    /// The environment binding every primitive and exception constructor.
+    pub fn env(&self) -> Env {
+        let env = constants()
+            .into_iter()
+            .fold(Env::default(), |env, (name, _, value)| {
+                env.extend(name.to_string(), Thunk::forced(value))
+            });
+        self.primitives.iter().fold(env, |env, prim| {
+            env.extend(
+                prim.name.clone(),
+                Thunk::forced(Value::Primitive(prim.clone())),
+            )
+        })
+    }
+
+    /// The types of every primitive and exception constructor.
+    pub fn context(&self) -> Vec<(String, Type)> {
+        let constants = constants()
+            .into_iter()
+            .map(|(name, ty, _)| (name.to_string(), ty));
+        constants
+            .chain(
+                self.primitives
+                    .iter()
+                    .map(|prim| (prim.name.clone(), prim.ty.clone())),
+            )
+            .collect()
+    }
+}
+
+/// The environment binding the built-in primitives and exception
+/// constructors.
+#[cfg(test)]
+pub fn env() -> Env {
+    Registry::default().env()
+}
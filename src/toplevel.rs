@@ -0,0 +1,1343 @@
+use crate::analysis::{self, Warning};
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::eval::{Env, Evaluator, RuntimeError, Strategy, Thunk, Value};
+use crate::lexer::{self, LexError, Token};
+use crate::modules::{Module, Modules};
+use crate::optimize::{self, Optimizer};
+use crate::parser::Parser;
+use crate::prelude;
+use crate::prim::Registry;
+use crate::typecheck::{TypeChecker, TypeError};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use std::{fmt, fs};
+
+/// Anything that can go wrong between reading source text and printing the
+/// value it evaluates to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Lex(LexError),
+    Parse(Span, String),
+    Type(TypeError),
+    Runtime(RuntimeError),
+    /// A module that cannot be found, read, or imported without a cycle.
+    Import(Span, String),
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Lex(err) => err.into(),
+            Error::Parse(span, message) => Diagnostic::new(format!("syntax error: {}", message))
+                .with_span(Some(span.clone()))
+                .with_label("unexpected input"),
+            Error::Type(err) => err.into(),
+            Error::Runtime(err) => err.into(),
+            Error::Import(span, message) => Diagnostic::new(format!("import error: {}", message))
+                .with_span(Some(span.clone()))
+                .with_label("imported here"),
+        }
+    }
+}
+
+/// What running a command produced.
+pub enum Outcome {
+    /// Names defined together, by `let` or `let rec ... and ...`.
+    Defined(Vec<(String, Type)>),
+    /// A type alias, and the type it was declared as.
+    Alias(String, Type),
+    Imported(String),
+    Value(Type, Value),
+    Doc(String, Type, Option<String>),
+    Exit,
+    /// The type of an expression, as asked for by `:type`.
+    Type(Type),
+    /// The syntax tree of an expression, as asked for by `:ast`.
+    Ast(Expr),
+    /// The tokens asked for by `:tokens`, with spans relative to its
+    /// argument.
+    Tokens(Vec<(Token, Span)>),
+    /// The definitions made in the session so far, oldest first.
+    Env(Vec<(String, Type)>),
+    /// What running each command in a file produced.
+    Loaded(String, Vec<Outcome>),
+    /// What a command produced, and how long it took.
+    Timed(Box<Outcome>, Duration),
+    Info(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Defined(definitions) => {
+                let lines: Vec<_> = definitions
+                    .iter()
+                    .map(|(x, ty)| format!("val {} : {}", x, ty))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Outcome::Alias(x, ty) => write!(f, "type {} = {}", x, ty),
+            Outcome::Imported(name) => write!(f, "module {}", name),
+            Outcome::Value(ty, value) => write!(f, "- : {} = {}", ty, value),
+            Outcome::Doc(x, ty, doc) => {
+                write!(f, "val {} : {}", x, ty)?;
+                match doc {
+                    Some(doc) => write!(f, "\n{}", doc),
+                    None => write!(f, "\n(undocumented)"),
+                }
+            }
+            Outcome::Exit => Ok(()),
+            Outcome::Type(ty) => write!(f, "- : {}", ty),
+            Outcome::Ast(e) => write!(f, "{:#?}", e),
+            Outcome::Tokens(tokens) => {
+                let lines: Vec<_> = tokens
+                    .iter()
+                    .map(|(token, span)| format!("{:?}\t{:?}", span, token))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Outcome::Env(definitions) if definitions.is_empty() => write!(f, "(no definitions)"),
+            Outcome::Env(definitions) => {
+                write!(f, "{}", Outcome::Defined(definitions.clone()))
+            }
+            Outcome::Loaded(path, outcomes) => {
+                for outcome in outcomes {
+                    writeln!(f, "{}", outcome)?;
+                }
+                write!(f, "loaded {}", path)
+            }
+            Outcome::Timed(outcome, time) => write!(f, "{}\n(took {:?})", outcome, time),
+            Outcome::Info(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// What `:help` shows.
+const HELP: &str = "\
+Every command ends with `;;`.
+  e                    evaluate the expression `e`
+  let x = e            define `x`
+  let rec f : t = e    define recursive functions, joined by `and`
+  type x = t           declare `x` to stand for the type `t`
+  import Name          bring the definitions in `Name.flock` into scope
+  :type e              show the type of `e` without evaluating it
+  :ast e               show the syntax tree of `e`
+  :tokens e            show the tokens of `e`
+  :env                 list the definitions made so far
+  :doc x               show the type and documentation of `x`
+  :load file           run the commands in `file`
+  :reload              reset the session and load the last file loaded again
+  :reset               forget every definition made so far
+  :set strategy lazy   evaluate lazily from now on, or `strict`ly
+  :time e              evaluate `e` and show how long it took
+  :help                show this help
+  :quit                leave";
+
+/// The names of the toplevel commands, as listed by `:help`.
+pub const COMMANDS: &[&str] = &[
+    "type", "ast", "tokens", "env", "doc", "load", "reload", "reset", "set", "time", "help", "quit",
+];
+
+/// Lexes and parses a sequence of commands.
+pub fn parse(source: &str) -> Result<Vec<Commands>, Error> {
+    parse_from(source, 0)
+}
+
+/// Parses the commands in `source[start..]`, with spans relative to all of
+/// `source`. The REPL uses this to keep spans in earlier inputs meaningful.
+pub fn parse_from(source: &str, start: usize) -> Result<Vec<Commands>, Error> {
+    parse_at(&source[start..], start)
+}
+
+/// Lexes and parses a single expression.
+pub fn parse_expr(source: &str) -> Result<Expr, Error> {
+    parse_expr_at(source, 0)
+}
+
+/// Parses the single expression `text`, with every span shifted by `offset`.
+fn parse_expr_at(text: &str, offset: usize) -> Result<Expr, Error> {
+    let mut commands = parse_at(text, offset)?;
+    match (commands.pop(), commands.is_empty()) {
+        (Some(Commands::Expr(e)), true) => Ok(e),
+        _ => Err(Error::Parse(
+            offset..offset + text.len(),
+            "expected a single expression".to_string(),
+        )),
+    }
+}
+
+/// Parses the commands in `text`, with every span shifted by `offset`.
+fn parse_at(text: &str, offset: usize) -> Result<Vec<Commands>, Error> {
+    let shift = |span: Span| span.start + offset..span.end + offset;
+    let tokens = lexer::lex(text).map_err(|mut err| {
+        err.span = shift(err.span);
+        Error::Lex(err)
+    })?;
+    let tokens = tokens.into_iter().map(|(token, span)| (token, shift(span)));
+    let mut parser = Parser::with_spans(tokens);
+    parser.parse_program().ok_or_else(|| {
+        let (span, message) = parser.error().cloned().unwrap_or_default();
+        Error::Parse(span, message)
+    })
+}
+
+/// The definitions in scope: their types, values and documentation.
+struct Scope {
+    checker: TypeChecker,
+    env: Env,
+    docs: HashMap<String, String>,
+    /// The names defined by commands, rather than by the primitives or the
+    /// prelude, in order.
+    defined: Vec<String>,
+}
+
+impl Scope {
+    /// A scope with only the primitives in it.
+    fn new(primitives: &Registry) -> Self {
+        Self {
+            checker: TypeChecker::with_primitives(primitives),
+            env: primitives.env(),
+            docs: HashMap::new(),
+            defined: Vec::new(),
+        }
+    }
+
+    fn define(&mut self, x: String, ty: Type, thunk: Thunk, doc: Option<&String>) {
+        match doc {
+            Some(doc) => self.docs.insert(x.clone(), doc.clone()),
+            None => self.docs.remove(&x),
+        };
+        self.checker.define(x.clone(), ty);
+        self.env = self.env.extend(x, thunk);
+    }
+
+    fn exec(
+        &mut self,
+        command: &Commands,
+        evaluator: &mut Evaluator,
+        modules: &mut Modules,
+    ) -> Result<Outcome, Error> {
+        match command {
+            Commands::Expr(e) => {
+                let ty = self.checker.type_of(e).map_err(Error::Type)?;
+                let value = evaluator.eval(&self.env, e).map_err(Error::Runtime)?;
+                evaluator.force_all(&value).map_err(Error::Runtime)?;
+                Ok(Outcome::Value(self.checker.abbreviate(&ty), value))
+            }
+            Commands::Fn(x, e, doc) => {
+                let ty = self.checker.type_of(e).map_err(Error::Type)?;
+                // Definitions are evaluated as they are made, lazily too, so
+                // that their effects happen in order.
+                let value = evaluator.eval(&self.env, e).map_err(Error::Runtime)?;
+                let thunk = Thunk::forced(value);
+                let shown = self.checker.abbreviate(&ty);
+                self.define(x.clone(), ty, thunk, doc.as_ref());
+                self.defined.push(x.clone());
+                Ok(Outcome::Defined(vec![(x.clone(), shown)]))
+            }
+            Commands::LetRec(bindings, doc) => {
+                let tys = self.checker.check_bindings(bindings).map_err(Error::Type)?;
+                let env = evaluator
+                    .bind_recursive(&self.env, bindings)
+                    .map_err(Error::Runtime)?;
+                // Nothing is defined unless every binding evaluates, as for
+                // a single definition.
+                let mut thunks = Vec::new();
+                for (x, _) in &tys {
+                    let thunk = env.lookup(x).cloned().expect("bound by the group");
+                    evaluator.force(&thunk).map_err(Error::Runtime)?;
+                    thunks.push(thunk);
+                }
+                let mut definitions = Vec::new();
+                for ((x, ty), thunk) in tys.into_iter().zip(thunks) {
+                    definitions.push((x.clone(), self.checker.abbreviate(&ty)));
+                    self.define(x.clone(), ty, thunk, doc.as_ref());
+                    self.defined.push(x);
+                }
+                Ok(Outcome::Defined(definitions))
+            }
+            Commands::Type(x, ty) => {
+                self.checker
+                    .define_alias(x.clone(), ty)
+                    .map_err(Error::Type)?;
+                Ok(Outcome::Alias(x.clone(), ty.clone()))
+            }
+            Commands::Import(name, span) => {
+                let module = load(name, span, evaluator, modules)?;
+                self.open(&module, Some(name))?;
+                let names = module.values.iter().map(|(x, _, _, _)| x.clone());
+                self.defined.extend(names);
+                Ok(Outcome::Imported(name.clone()))
+            }
+            Commands::Doc(x) => match self.checker.lookup(x) {
+                Some(ty) => Ok(Outcome::Doc(
+                    x.clone(),
+                    self.checker.abbreviate(ty),
+                    self.docs.get(x).cloned(),
+                )),
+                None => Err(Error::Type(TypeError::new(format!(
+                    "unknown variable `{}`",
+                    x
+                )))),
+            },
+            Commands::Exit => Ok(Outcome::Exit),
+            Commands::Meta(name, arg, offset) => Err(Error::Parse(
+                *offset..*offset + arg.len(),
+                format!("`:{}` can only be used at the toplevel", name),
+            )),
+        }
+    }
+
+    /// Type-checks the expressions in `command` without defining anything.
+    fn type_check(&mut self, command: &Commands) -> Result<(), Error> {
+        match command {
+            Commands::Expr(e) | Commands::Fn(_, e, _) => {
+                self.checker.type_of(e).map_err(Error::Type)?;
+            }
+            Commands::LetRec(bindings, _) => {
+                self.checker.check_bindings(bindings).map_err(Error::Type)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The definitions made by commands, oldest first, as `:env` shows them.
+    fn definitions(&self) -> Vec<(String, Type)> {
+        let mut names = self.defined.clone();
+        dedup_keeping_last(&mut names);
+        names
+            .into_iter()
+            .map(|x| {
+                let ty = self.checker.lookup(&x).expect("defined");
+                let ty = self.checker.abbreviate(ty);
+                (x, ty)
+            })
+            .collect()
+    }
+
+    /// Type-checks `command` without evaluating anything in it. Definitions
+    /// are still bound, but only evaluated once used. Imports are run as
+    /// usual, which evaluates the modules they load.
+    fn check(
+        &mut self,
+        command: &Commands,
+        evaluator: &mut Evaluator,
+        modules: &mut Modules,
+    ) -> Result<(), Error> {
+        match command {
+            Commands::Expr(e) => {
+                self.checker.type_of(e).map_err(Error::Type)?;
+            }
+            Commands::Fn(x, e, doc) => {
+                let ty = self.checker.type_of(e).map_err(Error::Type)?;
+                let thunk = Thunk::delayed(self.env.clone(), e.clone());
+                self.define(x.clone(), ty, thunk, doc.as_ref());
+                self.defined.push(x.clone());
+            }
+            Commands::LetRec(bindings, doc) => {
+                let tys = self.checker.check_bindings(bindings).map_err(Error::Type)?;
+                let env = Evaluator::new(Strategy::Lazy)
+                    .bind_recursive(&self.env, bindings)
+                    .map_err(Error::Runtime)?;
+                for (x, ty) in tys {
+                    let thunk = env.lookup(&x).cloned().expect("bound by the group");
+                    self.define(x.clone(), ty, thunk, doc.as_ref());
+                    self.defined.push(x);
+                }
+            }
+            _ => {
+                self.exec(command, evaluator, modules)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the definitions of `module` into scope, both unqualified and,
+    /// if it is given a name, qualified by it.
+    fn open(&mut self, module: &Module, name: Option<&str>) -> Result<(), Error> {
+        // Qualified names first, so that types are shown unqualified.
+        let names = |x: &String| {
+            name.map(|name| format!("{}.{}", name, x))
+                .into_iter()
+                .chain([x.clone()])
+        };
+        for (x, ty) in &module.aliases {
+            for alias in names(x) {
+                self.checker.define_alias(alias, ty).map_err(Error::Type)?;
+            }
+        }
+        for (x, ty, thunk, doc) in &module.values {
+            for x in names(x) {
+                self.define(x, ty.clone(), thunk.clone(), doc.as_ref());
+            }
+        }
+        Ok(())
+    }
+
+    /// The module made of the latest definitions of `names` and `aliases`.
+    fn export(&self, mut names: Vec<String>, mut aliases: Vec<String>) -> Module {
+        dedup_keeping_last(&mut names);
+        dedup_keeping_last(&mut aliases);
+        let values = names
+            .into_iter()
+            .map(|x| {
+                let ty = self.checker.lookup(&x).cloned().expect("defined");
+                let thunk = self.env.lookup(&x).cloned().expect("defined");
+                let doc = self.docs.get(&x).cloned();
+                (x, ty, thunk, doc)
+            })
+            .collect();
+        let aliases = aliases
+            .into_iter()
+            .map(|x| {
+                let ty = self.checker.alias(&x).cloned().expect("declared");
+                (x, ty)
+            })
+            .collect();
+        Module { values, aliases }
+    }
+}
+
+/// The module `name`, loading it unless an earlier import already did.
+fn load(
+    name: &str,
+    span: &Span,
+    evaluator: &mut Evaluator,
+    modules: &mut Modules,
+) -> Result<Rc<Module>, Error> {
+    if let Some(module) = modules.loaded(name) {
+        return Ok(module);
+    }
+    let path = modules
+        .find(name)
+        .map_err(|message| Error::Import(span.clone(), message))?;
+    let text = fs::read_to_string(&path).map_err(|err| {
+        Error::Import(
+            span.clone(),
+            format!("cannot read {}: {}", path.display(), err),
+        )
+    })?;
+    load_source(name, &path, text, span, evaluator, modules)
+}
+
+/// Loads the module `name` from `text`, read from `path`. Modules start out
+/// with the prelude in scope, once it is loaded.
+fn load_source(
+    name: &str,
+    path: &Path,
+    text: String,
+    span: &Span,
+    evaluator: &mut Evaluator,
+    modules: &mut Modules,
+) -> Result<Rc<Module>, Error> {
+    modules.start_loading(name).map_err(|cycle| {
+        Error::Import(
+            span.clone(),
+            format!("cyclic import: {}", cycle.join(" -> ")),
+        )
+    })?;
+    let offset = modules.add_source(path, text.clone());
+    let module = parse_at(&text, offset).and_then(|commands| {
+        let mut scope = Scope::new(&modules.primitives);
+        if let Some(prelude) = modules.loaded(prelude::NAME) {
+            scope.open(&prelude, None)?;
+        }
+        let mut names = Vec::new();
+        let mut aliases = Vec::new();
+        for command in &commands {
+            match scope.exec(command, evaluator, modules)? {
+                Outcome::Defined(definitions) => {
+                    names.extend(definitions.into_iter().map(|(x, _)| x))
+                }
+                Outcome::Alias(x, _) => aliases.push(x),
+                Outcome::Exit => break,
+                _ => {}
+            }
+        }
+        Ok(scope.export(names, aliases))
+    });
+    match module {
+        Ok(module) => Ok(modules.finish_loading(Some(module)).expect("started above")),
+        Err(err) => {
+            modules.finish_loading(None);
+            Err(err)
+        }
+    }
+}
+
+/// Removes all but the last occurrence of every name.
+fn dedup_keeping_last(names: &mut Vec<String>) {
+    let mut seen = Vec::new();
+    for x in names.drain(..).rev() {
+        if !seen.contains(&x) {
+            seen.push(x);
+        }
+    }
+    seen.reverse();
+    *names = seen;
+}
+
+/// The definitions made so far, both their types and their values, and the
+/// modules they came from.
+pub struct Session {
+    scope: Scope,
+    evaluator: Evaluator,
+    modules: Modules,
+    /// The file last run by `:load`, for `:reload`.
+    loaded: Option<String>,
+    /// What optimizes commands before they run, if anything does.
+    optimizer: Option<Optimizer>,
+    /// Where every command changed by the optimizer is shown before and
+    /// after optimization, if anywhere.
+    trace: Option<Box<dyn Write>>,
+    /// Warnings about the commands run or checked since they were last
+    /// taken.
+    warnings: Vec<Warning>,
+}
+
+impl Session {
+    pub fn new(strategy: Strategy) -> Self {
+        Self::with_evaluator(Evaluator::new(strategy))
+    }
+
+    /// A session evaluating with `evaluator`, e.g. to redirect its I/O.
+    pub fn with_evaluator(evaluator: Evaluator) -> Self {
+        let mut session = Self::without_prelude(evaluator);
+        session
+            .load_prelude()
+            .unwrap_or_else(|err| panic!("the prelude is broken: {:?}", err));
+        session
+    }
+
+    /// A session with only the primitives in scope.
+    pub fn without_prelude(evaluator: Evaluator) -> Self {
+        let modules = Modules::default();
+        Self {
+            scope: Scope::new(&modules.primitives),
+            evaluator,
+            modules,
+            loaded: None,
+            optimizer: None,
+            trace: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Optimizes every later command before running it. Modules are not
+    /// optimized.
+    pub fn optimize(&mut self, options: optimize::Options) {
+        self.optimizer = Some(Optimizer::new(options));
+    }
+
+    /// Shows every command the optimizer changes on `output`, before and
+    /// after optimization.
+    pub fn trace_optimizer(&mut self, output: impl Write + 'static) {
+        self.trace = Some(Box::new(output));
+    }
+
+    /// Forgets every definition made by commands and every module imported,
+    /// keeping the primitives and the prelude.
+    pub fn reset(&mut self) {
+        self.modules.retain_loaded(|name| name == prelude::NAME);
+        self.scope = Scope::new(&self.modules.primitives);
+        if let Some(optimizer) = &mut self.optimizer {
+            optimizer.reset();
+        }
+        if let Some(prelude) = self.modules.loaded(prelude::NAME) {
+            self.scope
+                .open(&prelude, None)
+                .expect("the prelude was opened before");
+        }
+    }
+
+    /// Makes a function implemented in Rust available as `name`, both to
+    /// later commands and to modules imported afterwards. See
+    /// [`Interpreter::register`](crate::Interpreter::register).
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        ty: Type,
+        apply: impl Fn(&mut Evaluator, Value) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let prim = self.modules.primitives.register(name, ty, apply);
+        let value = Thunk::forced(Value::Primitive(prim.clone()));
+        self.scope
+            .define(prim.name.clone(), prim.ty.clone(), value, None);
+    }
+
+    /// Type-checks and evaluates the prelude and brings it into scope. It can
+    /// also be imported by name afterwards.
+    pub fn load_prelude(&mut self) -> Result<(), Error> {
+        let module = load_source(
+            prelude::NAME,
+            Path::new(prelude::PATH),
+            prelude::SOURCE.to_string(),
+            &(0..0),
+            &mut self.evaluator,
+            &mut self.modules,
+        )?;
+        self.scope.open(&module, None)
+    }
+
+    /// Looks for imported modules in `dir` after the directories added
+    /// before it.
+    pub fn add_search_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.modules.add_search_dir(dir);
+    }
+
+    /// The path and text of the module source `span` points into, and the
+    /// span within that text, if it is not in the session's own input.
+    pub fn locate(&self, span: &Span) -> Option<(&str, &str, Span)> {
+        self.modules.locate(span)
+    }
+
+    pub fn exec(&mut self, command: &Commands) -> Result<Outcome, Error> {
+        if let Commands::Meta(name, arg, offset) = command {
+            return self.meta(name, arg, *offset);
+        }
+        self.warn(command);
+        let Some(optimizer) = &mut self.optimizer else {
+            return self
+                .scope
+                .exec(command, &mut self.evaluator, &mut self.modules);
+        };
+        // Optimizing can drop ill-typed code, such as the branch of an `if`
+        // that is never taken, so the command is checked as written first.
+        self.scope.type_check(command)?;
+        let optimized = optimizer.command(command);
+        if let Some(trace) = &mut self.trace {
+            // Dropping spans alone does not change how a command is shown.
+            let (before, after) = (command.to_string(), optimized.to_string());
+            if before != after {
+                let _ = writeln!(trace, "before: {}\nafter:  {}", before, after);
+            }
+        }
+        self.scope
+            .exec(&optimized, &mut self.evaluator, &mut self.modules)
+    }
+
+    /// Runs the toplevel command `:name arg`, where `arg` is at `offset` in
+    /// the source.
+    fn meta(&mut self, name: &str, arg: &str, offset: usize) -> Result<Outcome, Error> {
+        let span = offset..offset + arg.len();
+        let fail = |message: String| Err(Error::Parse(span.clone(), message));
+        match name {
+            "type" => {
+                let ty = self.check(&parse_expr_at(arg, offset)?)?;
+                Ok(Outcome::Type(self.scope.checker.abbreviate(&ty)))
+            }
+            "ast" => {
+                parse_expr_at(arg, offset)?;
+                // Parsed again without spans, which would only clutter the
+                // tree.
+                let tokens = lexer::lex(arg).unwrap_or_default();
+                let mut parser = Parser::new(tokens.into_iter().map(|(token, _)| token));
+                match parser.parse_command() {
+                    Some(Commands::Expr(e)) => Ok(Outcome::Ast(e)),
+                    _ => unreachable!("parsed with spans above"),
+                }
+            }
+            "tokens" => lexer::lex(arg).map(Outcome::Tokens).map_err(|mut err| {
+                err.span = err.span.start + offset..err.span.end + offset;
+                Error::Lex(err)
+            }),
+            "time" => {
+                let e = parse_expr_at(arg, offset)?;
+                let start = Instant::now();
+                let outcome = self.exec(&Commands::Expr(e))?;
+                Ok(Outcome::Timed(Box::new(outcome), start.elapsed()))
+            }
+            "load" if arg.is_empty() => fail("`:load` needs a file".to_string()),
+            "load" => self.load_file(arg, &span),
+            "set" => {
+                let (strategy, how) = match arg.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["strategy", "lazy"] => (Strategy::Lazy, "lazily"),
+                    ["strategy", "strict"] => (Strategy::Strict, "strictly"),
+                    _ => {
+                        return fail(format!(
+                            "expected `strategy lazy` or `strategy strict`, found `{}`",
+                            arg
+                        ))
+                    }
+                };
+                self.evaluator.strategy = strategy;
+                Ok(Outcome::Info(format!("evaluating {} from now on", how)))
+            }
+            "env" | "reset" | "reload" | "help" if !arg.is_empty() => {
+                fail(format!("`:{}` takes no argument", name))
+            }
+            "env" => Ok(Outcome::Env(self.scope.definitions())),
+            "reset" => {
+                self.reset();
+                Ok(Outcome::Info("all definitions forgotten".to_string()))
+            }
+            "reload" => match self.loaded.clone() {
+                Some(path) => {
+                    self.reset();
+                    self.load_file(&path, &span)
+                }
+                None => fail("no file has been loaded yet".to_string()),
+            },
+            "help" => Ok(Outcome::Info(HELP.to_string())),
+            _ => Err(Error::Parse(
+                offset - name.len() - 1..offset,
+                format!("unknown command `:{}`; `:help` lists them", name),
+            )),
+        }
+    }
+
+    /// Runs the commands in the file at `path`, as asked for at `span`,
+    /// stopping at `:quit`.
+    fn load_file(&mut self, path: &str, span: &Span) -> Result<Outcome, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| Error::Import(span.clone(), format!("cannot read {}: {}", path, err)))?;
+        self.loaded = Some(path.to_string());
+        let offset = self.modules.add_source(Path::new(path), text.clone());
+        let mut outcomes = Vec::new();
+        for command in parse_at(&text, offset)? {
+            match self.exec(&command)? {
+                Outcome::Exit => break,
+                outcome => outcomes.push(outcome),
+            }
+        }
+        Ok(Outcome::Loaded(path.to_string(), outcomes))
+    }
+
+    /// The type of `expr`, without evaluating it.
+    pub fn check(&mut self, expr: &Expr) -> Result<Type, Error> {
+        self.scope.checker.type_of(expr).map_err(Error::Type)
+    }
+
+    /// Type-checks `command` without running it, other than the imports in
+    /// it. What it defines is only evaluated once used by a later command.
+    pub fn check_command(&mut self, command: &Commands) -> Result<(), Error> {
+        self.warn(command);
+        self.scope
+            .check(command, &mut self.evaluator, &mut self.modules)
+    }
+
+    /// Takes the warnings about the commands run or checked so far.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn warn(&mut self, command: &Commands) {
+        let warnings = analysis::check_command(command, &self.scope.defined);
+        self.warnings.extend(warnings);
+    }
+
+    /// The names of every value and type in scope, for completion.
+    pub fn names(&self) -> Vec<String> {
+        let names = self.scope.checker.names();
+        names.into_iter().map(str::to_string).collect()
+    }
+
+    /// Makes `value`, of type `ty`, available as `x` to later commands.
+    pub fn define(&mut self, x: impl Into<String>, ty: Type, value: Value) {
+        self.scope.define(x.into(), ty, Thunk::forced(value), None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{RuntimeErrorKind, SharedOutput};
+
+    fn run(session: &mut Session, source: &str) -> Result<Vec<String>, Error> {
+        let mut output = Vec::new();
+        for command in parse(source)? {
+            output.push(session.exec(&command)?.to_string());
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn defines_and_evaluates() {
+        let mut session = Session::new(Strategy::Lazy);
+        let output = run(
+            &mut session,
+            "let double = fun x : int => 2 * x;; double 21;; (1, [bool])",
+        );
+        assert_eq!(
+            output,
+            Ok(vec![
+                "val double : Int -> Int".to_string(),
+                "- : Int = 42".to_string(),
+                "- : Int * Bool List = (1, [])".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn shows_documentation() {
+        let mut session = Session::new(Strategy::Lazy);
+        let output = run(
+            &mut session,
+            "--| The answer.\nlet answer = 42;; :doc answer;; let answer = 0;; :doc answer",
+        );
+        assert_eq!(
+            output,
+            Ok(vec![
+                "val answer : Int".to_string(),
+                "val answer : Int\nThe answer.".to_string(),
+                "val answer : Int".to_string(),
+                "val answer : Int\n(undocumented)".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn performs_io_in_evaluation_order() {
+        let output = SharedOutput::default();
+        let evaluator = Evaluator::new(Strategy::Strict)
+            .with_input(&b"20\n 22 \n"[..])
+            .with_output(output.clone());
+        let mut session = Session::with_evaluator(evaluator);
+        let values = run(
+            &mut session,
+            "let n = read_int () + read_int ();; \
+             print_string \"n = \"; print_int n; print_string \"\\n\"; n = 42",
+        );
+        assert_eq!(
+            values,
+            Ok(vec![
+                "val n : Int".to_string(),
+                "- : Bool = true".to_string()
+            ])
+        );
+        assert_eq!(
+            run(&mut session, "read_int ()"),
+            Err(Error::Runtime(RuntimeError {
+                kind: RuntimeErrorKind::EndOfInput,
+                span: Some(0..11),
+            }))
+        );
+        assert_eq!(output.contents(), "n = 42\n");
+        let mut session = Session::new(Strategy::Lazy);
+        assert!(matches!(run(&mut session, "1; 2"), Err(Error::Type(_))));
+    }
+
+    #[test]
+    fn runs_toplevel_definitions_when_made() {
+        let output = SharedOutput::default();
+        let evaluator = Evaluator::new(Strategy::Lazy).with_output(output.clone());
+        let mut session = Session::with_evaluator(evaluator);
+        run(
+            &mut session,
+            "let u = print_string \"a\";; \
+             let rec v : unit = print_string \"b\";; \
+             print_string \"c\"",
+        )
+        .unwrap();
+        assert_eq!(output.contents(), "abc");
+    }
+
+    #[test]
+    fn defines_nothing_from_failed_groups() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::new(strategy);
+            assert!(matches!(
+                run(&mut session, "let rec a : int = 1 and b : int = 1 / 0"),
+                Err(Error::Runtime(_))
+            ));
+            assert!(matches!(run(&mut session, "a"), Err(Error::Type(_))));
+        }
+    }
+
+    #[test]
+    fn drops_effects_of_unused_arguments_lazily() {
+        let source = "let r = ref 0;; (fun x : int => 0) (r := 5; 0);; !r";
+        for (strategy, value) in [(Strategy::Lazy, "0"), (Strategy::Strict, "5")] {
+            let mut session = Session::new(strategy);
+            let output = run(&mut session, source).unwrap();
+            assert_eq!(output[2], format!("- : Int = {}", value));
+        }
+    }
+
+    #[test]
+    fn shares_references_between_commands() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::new(strategy);
+            let output = run(
+                &mut session,
+                "let count = ref 0;; \
+                 let tick = fun u : unit => count := !count + 1; !count;; \
+                 tick ();; tick ();; !count",
+            )
+            .unwrap();
+            assert_eq!(output[2..], ["- : Int = 1", "- : Int = 2", "- : Int = 2"]);
+        }
+    }
+
+    #[test]
+    fn raises_and_handles_exceptions() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::new(strategy);
+            let output = run(
+                &mut session,
+                "let down = rec f : int -> int is fun n : int => \
+                   if n = 0 then raise (Failure \"bottom\") : int else 1 + f (n - 1);; \
+                 try down 100 with Failure msg -> string_length msg;; \
+                 try string_of_char \"abc\".[5] with Invalid_argument msg -> msg;; \
+                 try raise Not_found : bool with e -> true;; \
+                 let count = ref 0;; \
+                 try count := 1; raise Not_found : unit with _ -> count := !count + 1;; \
+                 !count",
+            )
+            .unwrap();
+            assert_eq!(
+                output[1..],
+                [
+                    "- : Int = 6",
+                    "- : String = \"index out of bounds\"",
+                    "- : Bool = true",
+                    "val count : Int Ref",
+                    "- : Unit = ()",
+                    "- : Int = 2",
+                ]
+            );
+            let err = run(&mut session, "1 + down 3").unwrap_err();
+            assert_eq!(
+                Diagnostic::from(&err).message,
+                "runtime error: uncaught exception Failure \"bottom\""
+            );
+            assert!(matches!(
+                run(&mut session, "try 1 with Failure -> 2"),
+                Err(Error::Type(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn builds_tuples_and_records() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::new(strategy);
+            let output = run(
+                &mut session,
+                "let t = (1, \"two\", (3.0, '4'));; #3 t;; fst (snd (#3 t), 0);; \
+                 let p = { y = 2; x = 1 };; \
+                 let move = fun p : { x : int; y : int } => { p with x = p.x + 10 };; \
+                 move p;; (move (move p)).x;; p",
+            )
+            .unwrap();
+            assert_eq!(
+                output,
+                [
+                    "val t : Int * String * (Float * Char)",
+                    "- : Float * Char = (3.0, '4')",
+                    "- : Char = '4'",
+                    "val p : { x : Int; y : Int }",
+                    "val move : { x : Int; y : Int } -> { x : Int; y : Int }",
+                    "- : { x : Int; y : Int } = { x = 11; y = 2 }",
+                    "- : Int = 21",
+                    "- : { x : Int; y : Int } = { x = 1; y = 2 }",
+                ]
+            );
+            assert!(matches!(run(&mut session, "#4 t"), Err(Error::Type(_))));
+            assert!(matches!(run(&mut session, "p.z"), Err(Error::Type(_))));
+        }
+    }
+
+    #[test]
+    fn declares_type_aliases() {
+        let mut session = Session::new(Strategy::Lazy);
+        let output = run(
+            &mut session,
+            "type point = int * int;; type path = point list;; \
+             let origin = (0, 0);; \
+             let swap = fun p : point => (snd p, fst p);; \
+             let reverse = rec r : path -> path -> path is \
+               fun l : path => fun acc : path => \
+                 match l with [point] -> acc | p :: ps -> r ps (swap p :: acc);; \
+             reverse (origin :: (1, 2) :: [point]) [point]",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            [
+                "type point = Int * Int",
+                "type path = point List",
+                "val origin : point",
+                "val swap : point -> point",
+                "val reverse : path -> path -> path",
+                "- : path = [(2, 1), (0, 0)]",
+            ]
+        );
+        let err = run(&mut session, "swap 1").unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&err).message,
+            "type error: this expression has type Int but an expression was expected of type point"
+        );
+        assert!(matches!(
+            run(&mut session, "fun p : pt => p"),
+            Err(Error::Type(TypeError { message, .. })) if message == "unknown type `pt`"
+        ));
+    }
+
+    #[test]
+    fn defines_mutually_recursive_functions() {
+        for strategy in [Strategy::Lazy, Strategy::Strict] {
+            let mut session = Session::new(strategy);
+            let output = run(
+                &mut session,
+                "--| Parity.\n\
+                 let rec even : int -> bool = fun n : int => if n = 0 then true else odd (n - 1) \
+                 and odd : int -> bool = fun n : int => if n = 0 then false else even (n - 1);; \
+                 even 10;; odd 7;; :doc odd;; \
+                 let rec ping : int -> int list = fun n : int => \
+                   if n = 0 then [int] else n :: pong (n - 1) \
+                 and pong : int -> int list = fun n : int => \
+                   if n = 0 then [int] else (0 - n) :: ping (n - 1) \
+                 in ping 4",
+            )
+            .unwrap();
+            assert_eq!(
+                output,
+                [
+                    "val even : Int -> Bool\nval odd : Int -> Bool",
+                    "- : Bool = true",
+                    "- : Bool = true",
+                    "val odd : Int -> Bool\nParity.",
+                    "- : Int List = [4, -3, 2, -1]",
+                ]
+            );
+            assert!(matches!(run(&mut session, "ping 1"), Err(Error::Type(_))));
+            // Values defined in terms of each other have none to take.
+            let err = run(
+                &mut session,
+                "let rec a : int = b and b : int = a;; a;; let rec c : int = d and d : int = 1 in c",
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err,
+                Error::Runtime(RuntimeError {
+                    kind: RuntimeErrorKind::RecursiveValue,
+                    ..
+                })
+            ));
+            let output = run(&mut session, "let rec c : int = d and d : int = 1 in c");
+            assert_eq!(output, Ok(vec!["- : Int = 1".to_string()]));
+        }
+    }
+
+    /// A fresh directory holding `files`, each given by its name and text.
+    fn module_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flock-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn imports_modules() {
+        let dir = module_dir(
+            "imports",
+            &[
+                (
+                    "Geometry.flock",
+                    "import Util;;\n\
+                     type point = int * int;;\n\
+                     --| The point at the origin.\n\
+                     let origin = (0, 0);;\n\
+                     let shift = fun p : point => (Util.inc (fst p), snd p);;",
+                ),
+                ("Util.flock", "let inc = fun n : int => n + 1;;"),
+            ],
+        );
+        let mut session = Session::new(Strategy::Lazy);
+        session.add_search_dir(&dir);
+        let output = run(
+            &mut session,
+            "import Geometry;; Geometry.shift origin;; shift (1, 2);; \
+             fun p : Geometry.point => p;; :doc Geometry.origin;; import Geometry",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            [
+                "module Geometry",
+                "- : point = (1, 0)",
+                "- : point = (2, 2)",
+                "- : point -> point = <fun>",
+                "val Geometry.origin : point\nThe point at the origin.",
+                "module Geometry",
+            ]
+        );
+        // Only what a module defines itself is exported.
+        assert!(matches!(run(&mut session, "inc 1"), Err(Error::Type(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_import_errors() {
+        let dir = module_dir(
+            "import-errors",
+            &[
+                ("A.flock", "import B;;"),
+                ("B.flock", "import A;;"),
+                ("Bad.flock", "let x = 1;;\nlet y = x + true;;"),
+            ],
+        );
+        let mut session = Session::new(Strategy::Lazy);
+        session.add_search_dir(&dir);
+        assert!(matches!(
+            run(&mut session, "import A"),
+            Err(Error::Import(_, message)) if message == "cyclic import: A -> B -> A"
+        ));
+        assert!(matches!(
+            run(&mut session, "import Missing"),
+            Err(Error::Import(span, message))
+                if span == (7..14) && message.starts_with("cannot find `Missing.flock`")
+        ));
+        let err = run(&mut session, "import Bad").unwrap_err();
+        let span = Diagnostic::from(&err).span.unwrap();
+        let (path, text, span) = session.locate(&span).unwrap();
+        assert!(path.ends_with("Bad.flock"));
+        assert_eq!(&text[span], "true");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn calls_registered_primitives() {
+        use crate::eval::Exception;
+        use std::cell::Cell;
+
+        let mut session = Session::new(Strategy::Lazy);
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let pair = Type::Tuple(vec![Type::String, Type::Int]);
+        session.register(
+            "repeat",
+            Type::Func(Box::new(pair), Box::new(Type::String)),
+            move |evaluator, v| {
+                counter.set(counter.get() + 1);
+                let Value::Tuple(args) = v else {
+                    unreachable!("type checked")
+                };
+                match (evaluator.force(&args[0])?, evaluator.force(&args[1])?) {
+                    (Value::Str(s), Value::Int(n)) if n >= 0.into() => {
+                        let n = n.to_string().parse().unwrap();
+                        Ok(Value::Str(s.repeat(n).into()))
+                    }
+                    _ => Err(RuntimeError::new(RuntimeErrorKind::Raised(Exception::new(
+                        "Invalid_argument",
+                        Some("repeat"),
+                    )))),
+                }
+            },
+        );
+        let dir = module_dir(
+            "primitives",
+            &[(
+                "Echo.flock",
+                "let twice = fun s : string => repeat (s, 2);;",
+            )],
+        );
+        session.add_search_dir(&dir);
+        let output = run(
+            &mut session,
+            "repeat (\"ab\", 3);; :doc repeat;; \
+             try repeat (\"ab\", 0 - 1) with Invalid_argument s -> s;; \
+             import Echo;; twice \"x\"",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            [
+                "- : String = \"ababab\"",
+                "val repeat : String * Int -> String\n(undocumented)",
+                "- : String = \"repeat\"",
+                "module Echo",
+                "- : String = \"xx\"",
+            ]
+        );
+        assert_eq!(calls.get(), 3);
+        assert!(matches!(run(&mut session, "repeat 1"), Err(Error::Type(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn checks_commands_without_running_them() {
+        let output = SharedOutput::default();
+        let evaluator = Evaluator::new(Strategy::Strict).with_output(output.clone());
+        let mut session = Session::with_evaluator(evaluator);
+        let source = "let x = print_string \"x\"; 1;; \
+                      let rec f : int -> int = fun n : int => g n and g : int -> int = f;; \
+                      type t = int;; print_string \"y\"; x + 1";
+        for command in parse(source).unwrap() {
+            session.check_command(&command).unwrap();
+        }
+        assert_eq!(output.contents(), "");
+        let err = parse("x + true")
+            .unwrap()
+            .iter()
+            .try_for_each(|command| session.check_command(command));
+        assert!(matches!(err, Err(Error::Type(_))));
+        assert_eq!(
+            run(&mut session, "fun n : t => x + n").unwrap(),
+            ["- : Int -> Int = <fun>"]
+        );
+        assert_eq!(run(&mut session, "x").unwrap(), ["- : Int = 1"]);
+        assert_eq!(output.contents(), "x");
+    }
+
+    #[test]
+    fn lists_names_in_scope() {
+        let mut session = Session::new(Strategy::Lazy);
+        run(
+            &mut session,
+            "type point = int * int;; let map = 1;; let x = 2",
+        )
+        .unwrap();
+        let names = session.names();
+        assert_eq!(&names[..2], ["x", "map"]);
+        assert_eq!(names.iter().filter(|x| *x == "map").count(), 1);
+        assert!(names.contains(&"point".to_string()));
+        assert!(names.contains(&"print_string".to_string()));
+    }
+
+    #[test]
+    fn runs_toplevel_commands() {
+        let dir = module_dir(
+            "toplevel-commands",
+            &[("defs.flock", "let a = 1;;\n:quit;;\nlet b = 2;;")],
+        );
+        let path = dir.join("defs.flock").display().to_string();
+        let mut session = Session::new(Strategy::Lazy);
+        let output = run(
+            &mut session,
+            &format!(
+                ":env;; let x = 1;; :type fun n : int => n + x;; :tokens x.[0];; \
+                 :load {};; :env;; :set strategy strict;; :reset;; :env;; :reload;; a",
+                path
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            [
+                "(no definitions)".to_string(),
+                "val x : Int".to_string(),
+                "- : Int -> Int".to_string(),
+                "0..1\tVar(\"x\")\n1..3\tDotLSquareBrack\n3..4\tInteger(0)\n4..5\tRSquareBrack"
+                    .to_string(),
+                format!("val a : Int\nloaded {}", path),
+                "val x : Int\nval a : Int".to_string(),
+                "evaluating strictly from now on".to_string(),
+                "all definitions forgotten".to_string(),
+                "(no definitions)".to_string(),
+                format!("val a : Int\nloaded {}", path),
+                "- : Int = 1".to_string(),
+            ]
+        );
+        assert_eq!(session.evaluator.strategy, Strategy::Strict);
+        assert!(matches!(
+            &run(&mut session, ":ast if true then 1 else 2").unwrap()[..],
+            [ast] if ast.starts_with("If(\n    Bool(\n        true,")
+        ));
+        let timed = run(&mut session, ":time 6 * 7").unwrap();
+        assert!(timed[0].starts_with("- : Int = 42\n(took "));
+        assert!(run(&mut session, ":help").unwrap()[0].contains(":reload"));
+        assert_eq!(
+            run(&mut session, r#":type "a;;b" ^ "c";; 1"#).unwrap(),
+            ["- : String", "- : Int = 1"]
+        );
+        assert!(matches!(
+            run(&mut session, ":frobnicate"),
+            Err(Error::Parse(span, message))
+                if span == (0..11) && message == "unknown command `:frobnicate`; `:help` lists them"
+        ));
+        assert!(matches!(
+            run(&mut session, ":type 1 + true"),
+            Err(Error::Type(TypeError { span: Some(span), .. })) if span == (10..14)
+        ));
+        assert!(matches!(
+            run(&mut session, ":set strategy eager"),
+            Err(Error::Parse(_, _))
+        ));
+        assert!(matches!(
+            run(&mut session, ":env x"),
+            Err(Error::Parse(_, _))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn collects_warnings_about_commands() {
+        let mut session = Session::new(Strategy::Strict);
+        run(&mut session, "let n = 1;; (fun n : int => 0) 2").unwrap();
+        let warnings: Vec<_> = session
+            .take_warnings()
+            .iter()
+            .map(|warning| (warning.to_string(), warning.span.clone()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                ("`n` shadows an earlier binding".to_string(), Some(13..29)),
+                ("unused parameter `n`".to_string(), Some(13..29)),
+            ]
+        );
+        assert!(session.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn optimizes_commands_before_running_them() {
+        let trace = SharedOutput::default();
+        let mut session = Session::new(Strategy::Strict);
+        session.optimize(optimize::Options::default());
+        session.trace_optimizer(trace.clone());
+        let output = run(&mut session, "let two = 1 + 1;; two * 5;; :reset;; 7");
+        assert_eq!(
+            output,
+            Ok(vec![
+                "val two : Int".to_string(),
+                "- : Int = 10".to_string(),
+                "all definitions forgotten".to_string(),
+                "- : Int = 7".to_string(),
+            ])
+        );
+        assert_eq!(
+            trace.contents(),
+            "before: let two = 1 + 1\nafter:  let two = 2\nbefore: two * 5\nafter:  10\n"
+        );
+        // The branch that is never taken is still checked.
+        assert!(matches!(
+            run(&mut session, "if true then 1 else \"x\""),
+            Err(Error::Type(_))
+        ));
+        assert!(matches!(run(&mut session, "two"), Err(Error::Type(_))));
+    }
+
+    #[test]
+    fn parses_with_offset() {
+        let source = "1;; 2 +";
+        assert!(matches!(
+            parse_from(source, 3),
+            Err(Error::Parse(span, _)) if span == (7..7)
+        ));
+    }
+
+    #[test]
+    fn reports_errors_from_every_stage() {
+        let mut session = Session::new(Strategy::Strict);
+        assert!(matches!(run(&mut session, "1 + $"), Err(Error::Lex(_))));
+        assert!(matches!(
+            run(&mut session, "1 +"),
+            Err(Error::Parse(span, _)) if span == (3..3)
+        ));
+        assert!(matches!(run(&mut session, "1 + true"), Err(Error::Type(_))));
+        assert!(matches!(
+            run(&mut session, "10 / (2 - 2)"),
+            Err(Error::Runtime(RuntimeError { span: Some(span), .. })) if span == (0..12)
+        ));
+    }
+}
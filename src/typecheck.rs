@@ -0,0 +1,703 @@
+use crate::ast::*;
+use crate::prim::{self, Registry};
+use std::convert::Infallible;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    /// Span of the innermost source construct the error was found in.
+    pub span: Option<Span>,
+}
+
+impl TypeError {
+    pub fn new(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            span: None,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Types of the variables in scope and the type aliases declared so far.
+/// Later bindings shadow earlier ones.
+pub struct TypeChecker {
+    context: Vec<(String, Type)>,
+    /// What each alias stands for, with no aliases left in it.
+    aliases: Vec<(String, Type)>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    /// A type checker that knows the types of the built-in primitives.
+    pub fn new() -> Self {
+        Self::with_primitives(&Registry::default())
+    }
+
+    /// A type checker that knows the types of the primitives in `registry`.
+    pub fn with_primitives(registry: &Registry) -> Self {
+        Self {
+            context: registry.context(),
+            aliases: Vec::new(),
+        }
+    }
+
+    /// Makes `name` available with type `ty` to everything checked later.
+    pub fn define(&mut self, name: String, ty: Type) {
+        self.context.push((name, ty));
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Type> {
+        self.context
+            .iter()
+            .rev()
+            .find(|(x, _)| x == name)
+            .map(|(_, ty)| ty)
+    }
+
+    /// The names of every value and alias in scope, latest first, each once.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        let values = self.context.iter().rev();
+        for (x, _) in values.chain(self.aliases.iter().rev()) {
+            if !names.contains(&x.as_str()) {
+                names.push(x);
+            }
+        }
+        names
+    }
+
+    /// Declares `name` as an alias of `ty`, which may only use aliases
+    /// declared before it. Returns what the alias stands for.
+    pub fn define_alias(&mut self, name: String, ty: &Type) -> Result<Type, TypeError> {
+        let ty = self.resolve(ty)?;
+        self.aliases.push((name, ty.clone()));
+        Ok(ty)
+    }
+
+    /// What the alias `name` stands for.
+    pub fn alias(&self, name: &str) -> Option<&Type> {
+        self.aliases
+            .iter()
+            .rev()
+            .find(|(x, _)| x == name)
+            .map(|(_, ty)| ty)
+    }
+
+    /// `ty` with every alias in it replaced by what it stands for.
+    pub fn resolve(&self, ty: &Type) -> Result<Type, TypeError> {
+        match ty {
+            Type::Named(name) => self
+                .aliases
+                .iter()
+                .rev()
+                .find(|(x, _)| x == name)
+                .map(|(_, ty)| ty.clone())
+                .ok_or_else(|| TypeError::new(format!("unknown type `{}`", name))),
+            ty => ty.try_map_children(&mut |ty| self.resolve(ty)),
+        }
+    }
+
+    /// `ty` for showing to the user, with the latest alias for any compound
+    /// part of it written instead of that part. Aliases of base types are not
+    /// used, since `int` is no harder to read than its alias.
+    pub fn abbreviate(&self, ty: &Type) -> Type {
+        let compound = !matches!(
+            ty,
+            Type::Unit
+                | Type::Int
+                | Type::Float
+                | Type::Bool
+                | Type::String
+                | Type::Char
+                | Type::Exn
+        );
+        if let Some((name, _)) = self.aliases.iter().rev().find(|(_, t)| t == ty) {
+            if compound {
+                return Type::Named(name.clone());
+            }
+        }
+        let Ok(ty) = ty.try_map_children(&mut |ty| Ok::<_, Infallible>(self.abbreviate(ty)));
+        ty
+    }
+
+    fn mismatch(&self, expected: &Type, found: &Type) -> TypeError {
+        TypeError::new(format!(
+            "this expression has type {} but an expression was expected of type {}",
+            self.abbreviate(found),
+            self.abbreviate(expected)
+        ))
+    }
+
+    /// The type of the field `x` of values of type `ty`.
+    fn field_type(&self, ty: &Type, x: &str) -> Result<Type, TypeError> {
+        let Type::Record(fields) = ty else {
+            return Err(TypeError::new(format!(
+                "this expression has type {} but a record was expected",
+                self.abbreviate(ty)
+            )));
+        };
+        fields
+            .iter()
+            .find(|(y, _)| y == x)
+            .map(|(_, ty)| ty.clone())
+            .ok_or_else(|| {
+                TypeError::new(format!(
+                    "the type {} has no field `{}`",
+                    self.abbreviate(ty),
+                    x
+                ))
+            })
+    }
+
+    pub fn type_of(&mut self, expr: &Expr) -> Result<Type, TypeError> {
+        use Expr::*;
+        match expr {
+            Var(x) => self
+                .lookup(x)
+                .cloned()
+                .ok_or_else(|| TypeError::new(format!("unknown variable `{}`", x))),
+            Unit => Ok(Type::Unit),
+            Int(_) => Ok(Type::Int),
+            Float(_) => Ok(Type::Float),
+            Bool(_) => Ok(Type::Bool),
+            Str(_) => Ok(Type::String),
+            Char(_) => Ok(Type::Char),
+            Negate(e) => match self.type_of(e)? {
+                ty @ (Type::Int | Type::Float) => Ok(ty),
+                ty => Err(self.mismatch(&Type::Int, &ty)),
+            },
+            Mult(e1, e2) | Divide(e1, e2) | Plus(e1, e2) | Minus(e1, e2) => {
+                match self.type_of(e1)? {
+                    ty @ (Type::Int | Type::Float) => {
+                        self.check(e2, &ty)?;
+                        Ok(ty)
+                    }
+                    ty => Err(self.mismatch(&Type::Int, &ty)),
+                }
+            }
+            Mod(e1, e2) => {
+                self.check(e1, &Type::Int)?;
+                self.check(e2, &Type::Int)?;
+                Ok(Type::Int)
+            }
+            Equal(e1, e2) => match self.type_of(e1)? {
+                ty @ (Type::Int | Type::Float | Type::Bool | Type::String | Type::Char) => {
+                    self.check(e2, &ty)?;
+                    Ok(Type::Bool)
+                }
+                ty => Err(TypeError::new(format!(
+                    "values of type {} cannot be compared",
+                    self.abbreviate(&ty)
+                ))),
+            },
+            Less(e1, e2) => match self.type_of(e1)? {
+                ty @ (Type::Int | Type::Float | Type::String | Type::Char) => {
+                    self.check(e2, &ty)?;
+                    Ok(Type::Bool)
+                }
+                ty => Err(self.mismatch(&Type::Int, &ty)),
+            },
+            Concat(e1, e2) => {
+                self.check(e1, &Type::String)?;
+                self.check(e2, &Type::String)?;
+                Ok(Type::String)
+            }
+            Index(e1, e2) => {
+                self.check(e1, &Type::String)?;
+                self.check(e2, &Type::Int)?;
+                Ok(Type::Char)
+            }
+            If(e1, e2, e3) => {
+                self.check(e1, &Type::Bool)?;
+                let ty = self.type_of(e2)?;
+                self.check(e3, &ty)?;
+                Ok(ty)
+            }
+            Func(x, ty, e) => {
+                let ty = self.resolve(ty)?;
+                let ret = self.with_binding(x, &ty, |tc| tc.type_of(e))?;
+                Ok(Type::Func(Box::new(ty), Box::new(ret)))
+            }
+            Apply(e1, e2) => match self.type_of(e1)? {
+                Type::Func(arg, ret) => {
+                    self.check(e2, &arg)?;
+                    Ok(*ret)
+                }
+                ty => Err(TypeError::new(format!(
+                    "this expression has type {} and cannot be applied",
+                    self.abbreviate(&ty)
+                ))),
+            },
+            Tuple(es) => Ok(Type::Tuple(
+                es.iter()
+                    .map(|e| self.type_of(e))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Project(e, i) => match self.type_of(e)? {
+                Type::Tuple(mut tys) if *i < tys.len() => Ok(tys.swap_remove(*i)),
+                ty => Err(TypeError::new(format!(
+                    "this expression has type {} but a tuple with at least {} components was expected",
+                    self.abbreviate(&ty),
+                    (i + 1).max(2)
+                ))),
+            },
+            Record(fields) => {
+                let mut tys = Vec::new();
+                for (x, e) in fields {
+                    if tys.iter().any(|(y, _)| y == x) {
+                        return Err(TypeError::new(format!("field `{}` is given twice", x)));
+                    }
+                    tys.push((x.clone(), self.type_of(e)?));
+                }
+                Ok(Type::record(tys))
+            }
+            Field(e, x) => {
+                let ty = self.type_of(e)?;
+                self.field_type(&ty, x)
+            }
+            Update(e, fields) => {
+                let ty = self.type_of(e)?;
+                for (x, e) in fields {
+                    let field_ty = self.field_type(&ty, x)?;
+                    self.check(e, &field_ty)?;
+                }
+                Ok(ty)
+            }
+            Recursion(x, ty, e) => {
+                let ty = self.resolve(ty)?;
+                self.with_binding(x, &ty, |tc| tc.check(e, &ty))?;
+                Ok(ty)
+            }
+            LetRec(bindings, e) => {
+                let tys = self.check_bindings(bindings)?;
+                let depth = self.context.len();
+                self.context.extend(tys);
+                let result = self.type_of(e);
+                self.context.truncate(depth);
+                result
+            }
+            None(ty) => Ok(Type::List(Box::new(self.resolve(ty)?))),
+            Cons(e1, e2) => {
+                let ty = Type::List(Box::new(self.type_of(e1)?));
+                self.check(e2, &ty)?;
+                Ok(ty)
+            }
+            Match(e1, ty, e2, x, y, e3) => {
+                let ty = self.resolve(ty)?;
+                let list = Type::List(Box::new(ty.clone()));
+                self.check(e1, &list)?;
+                let result = self.type_of(e2)?;
+                self.with_binding(x, &ty, |tc| {
+                    tc.with_binding(y, &list, |tc| tc.check(e3, &result))
+                })?;
+                Ok(result)
+            }
+            Seq(e1, e2) => {
+                self.check(e1, &Type::Unit)?;
+                self.type_of(e2)
+            }
+            // Every binder has a monomorphic type annotation, so references
+            // need no value restriction. Generalizing `let` would require one.
+            Ref(e) => Ok(Type::Ref(Box::new(self.type_of(e)?))),
+            Deref(e) => match self.type_of(e)? {
+                Type::Ref(ty) => Ok(*ty),
+                ty => Err(TypeError::new(format!(
+                    "this expression has type {} but a reference was expected",
+                    self.abbreviate(&ty)
+                ))),
+            },
+            Raise(e, ty) => {
+                self.check(e, &Type::Exn)?;
+                self.resolve(ty)
+            }
+            Try(e, handlers) => {
+                let ty = self.type_of(e)?;
+                for (pattern, handler) in handlers {
+                    match pattern {
+                        Pattern::Constructor(name, arg) => {
+                            let carries = prim::EXCEPTIONS
+                                .iter()
+                                .find(|(x, _)| x == name)
+                                .map(|&(_, carries)| carries)
+                                .ok_or_else(|| {
+                                    TypeError::new(format!("unknown exception `{}`", name))
+                                })?;
+                            match arg {
+                                Some(x) if carries => {
+                                    self.with_binding(x, &Type::String, |tc| {
+                                        tc.check(handler, &ty)
+                                    })?
+                                }
+                                _ if carries => {
+                                    return Err(TypeError::new(format!(
+                                        "the exception `{}` has an argument",
+                                        name
+                                    )))
+                                }
+                                Some(_) => {
+                                    return Err(TypeError::new(format!(
+                                        "the exception `{}` has no argument",
+                                        name
+                                    )))
+                                }
+                                _ => self.check(handler, &ty)?,
+                            }
+                        }
+                        Pattern::Var(x) => {
+                            self.with_binding(x, &Type::Exn, |tc| tc.check(handler, &ty))?
+                        }
+                        Pattern::Wildcard => self.check(handler, &ty)?,
+                    }
+                }
+                Ok(ty)
+            }
+            Assign(e1, e2) => match self.type_of(e1)? {
+                Type::Ref(ty) => {
+                    self.check(e2, &ty)?;
+                    Ok(Type::Unit)
+                }
+                ty => Err(TypeError::new(format!(
+                    "this expression has type {} but a reference was expected",
+                    self.abbreviate(&ty)
+                ))),
+            },
+            Spanned(span, e) => self.type_of(e).map_err(|mut err| {
+                err.span.get_or_insert_with(|| span.clone());
+                err
+            }),
+        }
+    }
+
+    /// Checks that `expr` has type `expected`.
+    pub fn check(&mut self, expr: &Expr, expected: &Type) -> Result<(), TypeError> {
+        let ty = self.type_of(expr)?;
+        if &ty == expected {
+            return Ok(());
+        }
+        let mut err = self.mismatch(expected, &ty);
+        if let Expr::Spanned(span, _) = expr {
+            err.span = Some(span.clone());
+        }
+        Err(err)
+    }
+
+    /// Checks a `let rec` group, where every body sees every name, and returns
+    /// the names with their types.
+    pub fn check_bindings(
+        &mut self,
+        bindings: &[Binding],
+    ) -> Result<Vec<(String, Type)>, TypeError> {
+        let tys = bindings
+            .iter()
+            .map(|(x, ty, _)| Ok((x.clone(), self.resolve(ty)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let depth = self.context.len();
+        self.context.extend(tys.iter().cloned());
+        let result = bindings
+            .iter()
+            .zip(&tys)
+            .try_for_each(|((_, _, e), (_, ty))| self.check(e, ty));
+        self.context.truncate(depth);
+        result.map(|()| tys)
+    }
+
+    fn with_binding<T>(
+        &mut self,
+        name: &str,
+        ty: &Type,
+        f: impl FnOnce(&mut Self) -> Result<T, TypeError>,
+    ) -> Result<T, TypeError> {
+        self.context.push((name.to_string(), ty.clone()));
+        let result = f(self);
+        self.context.pop();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::num::Integer;
+
+    fn int(n: i64) -> Box<Expr> {
+        Box::new(Expr::Int(Integer::from(n)))
+    }
+
+    fn float(x: f64) -> Box<Expr> {
+        Box::new(Expr::Float(x))
+    }
+
+    fn var(x: &str) -> Box<Expr> {
+        Box::new(Expr::Var(x.to_string()))
+    }
+
+    fn type_of(expr: &Expr) -> Result<Type, TypeError> {
+        TypeChecker::new().type_of(expr)
+    }
+
+    #[test]
+    fn types_recursive_functions() {
+        let ty = Type::Func(Box::new(Type::Int), Box::new(Type::Int));
+        let expr = Expr::Recursion(
+            "f".to_string(),
+            Box::new(ty.clone()),
+            Box::new(Expr::Func(
+                "n".to_string(),
+                Box::new(Type::Int),
+                Box::new(Expr::If(
+                    Box::new(Expr::Less(var("n"), int(1))),
+                    int(0),
+                    Box::new(Expr::Apply(
+                        var("f"),
+                        Box::new(Expr::Minus(var("n"), int(1))),
+                    )),
+                )),
+            )),
+        );
+        assert_eq!(type_of(&expr), Ok(ty));
+    }
+
+    #[test]
+    fn types_float_arithmetic() {
+        let expr = Expr::Less(Box::new(Expr::Mult(float(1.5), float(2.0))), float(3.0));
+        assert_eq!(type_of(&expr), Ok(Type::Bool));
+    }
+
+    #[test]
+    fn keeps_int_and_float_separate() {
+        let expr = Expr::Spanned(0..7, Box::new(Expr::Plus(int(1), float(2.0))));
+        assert_eq!(
+            type_of(&expr),
+            Err(TypeError {
+                message:
+                    "this expression has type Float but an expression was expected of type Int"
+                        .to_string(),
+                span: Some(0..7),
+            })
+        );
+        assert!(type_of(&Expr::Mod(float(1.0), float(2.0))).is_err());
+    }
+
+    #[test]
+    fn points_at_the_mismatched_operand() {
+        // 1 + true
+        let expr = Expr::Spanned(
+            0..8,
+            Box::new(Expr::Plus(
+                Box::new(Expr::Spanned(0..1, int(1))),
+                Box::new(Expr::Spanned(4..8, Box::new(Expr::Bool(true)))),
+            )),
+        );
+        assert_eq!(type_of(&expr).unwrap_err().span, Some(4..8));
+    }
+
+    #[test]
+    fn types_conversions() {
+        let expr = Expr::Plus(
+            Box::new(Expr::Apply(var("float_of_int"), int(1))),
+            float(0.5),
+        );
+        assert_eq!(type_of(&expr), Ok(Type::Float));
+        let expr = Expr::Apply(var("int_of_float"), float(0.5));
+        assert_eq!(type_of(&expr), Ok(Type::Int));
+    }
+
+    #[test]
+    fn types_list_matches() {
+        // match 1 :: [int] with [int] -> [int] | x :: y -> y
+        let list = Type::List(Box::new(Type::Int));
+        let expr = Expr::Match(
+            Box::new(Expr::Cons(int(1), Box::new(Expr::None(Type::Int)))),
+            Box::new(Type::Int),
+            Box::new(Expr::None(Type::Int)),
+            "x".to_string(),
+            "y".to_string(),
+            var("y"),
+        );
+        assert_eq!(type_of(&expr), Ok(list));
+    }
+
+    #[test]
+    fn types_strings_and_chars() {
+        let s = || Box::new(Expr::Str("ab".to_string()));
+        // ("ab" ^ "ab").[1] = 'b'
+        let expr = Expr::Equal(
+            Box::new(Expr::Index(Box::new(Expr::Concat(s(), s())), int(1))),
+            Box::new(Expr::Char('b')),
+        );
+        assert_eq!(type_of(&expr), Ok(Type::Bool));
+        assert_eq!(type_of(&Expr::Less(s(), s())), Ok(Type::Bool));
+        let expr = Expr::Apply(var("string_length"), s());
+        assert_eq!(type_of(&expr), Ok(Type::Int));
+        assert!(type_of(&Expr::Concat(s(), Box::new(Expr::Char('c')))).is_err());
+        assert!(type_of(&Expr::Index(s(), float(0.0))).is_err());
+    }
+
+    #[test]
+    fn types_references() {
+        let cell = Type::Ref(Box::new(Type::Int));
+        let expr = Expr::Func(
+            "r".to_string(),
+            Box::new(cell.clone()),
+            Box::new(Expr::Assign(
+                var("r"),
+                Box::new(Expr::Plus(Box::new(Expr::Deref(var("r"))), int(1))),
+            )),
+        );
+        assert_eq!(
+            type_of(&expr),
+            Ok(Type::Func(Box::new(cell.clone()), Box::new(Type::Unit)))
+        );
+        assert_eq!(type_of(&Expr::Ref(int(0))), Ok(cell));
+        assert!(type_of(&Expr::Assign(Box::new(Expr::Ref(int(0))), float(1.0))).is_err());
+        assert!(type_of(&Expr::Deref(int(0))).is_err());
+    }
+
+    #[test]
+    fn types_exceptions() {
+        let handler =
+            |pattern, body| Expr::Try(int(1), vec![(pattern, body), (Pattern::Wildcard, *int(0))]);
+        let failure = |arg: Option<&str>| {
+            Pattern::Constructor("Failure".to_string(), arg.map(str::to_string))
+        };
+        let expr = handler(
+            failure(Some("msg")),
+            Expr::Apply(var("string_length"), var("msg")),
+        );
+        assert_eq!(type_of(&expr), Ok(Type::Int));
+        let expr = handler(
+            Pattern::Var("e".to_string()),
+            Expr::Raise(var("e"), Box::new(Type::Int)),
+        );
+        assert_eq!(type_of(&expr), Ok(Type::Int));
+        assert!(type_of(&handler(failure(None), *int(2))).is_err());
+        assert!(type_of(&handler(failure(Some("msg")), *float(2.0))).is_err());
+        let unknown = Pattern::Constructor("Oops".to_string(), None);
+        assert!(type_of(&handler(unknown, *int(2))).is_err());
+        assert!(type_of(&Expr::Raise(int(1), Box::new(Type::Int))).is_err());
+    }
+
+    #[test]
+    fn types_tuples_and_records() {
+        let triple = Expr::Tuple(vec![*int(1), Expr::Bool(true), *float(2.0)]);
+        assert_eq!(
+            type_of(&triple),
+            Ok(Type::Tuple(vec![Type::Int, Type::Bool, Type::Float]))
+        );
+        assert_eq!(
+            type_of(&Expr::Project(Box::new(triple.clone()), 2)),
+            Ok(Type::Float)
+        );
+        assert!(type_of(&Expr::Project(Box::new(triple), 3)).is_err());
+
+        let point = || {
+            Box::new(Expr::Record(vec![
+                ("y".to_string(), *int(2)),
+                ("x".to_string(), *float(1.0)),
+            ]))
+        };
+        let ty = Type::record(vec![
+            ("x".to_string(), Type::Float),
+            ("y".to_string(), Type::Int),
+        ]);
+        assert_eq!(type_of(&point()), Ok(ty.clone()));
+        assert_eq!(
+            type_of(&Expr::Field(point(), "y".to_string())),
+            Ok(Type::Int)
+        );
+        let update = |x: &str, e| Expr::Update(point(), vec![(x.to_string(), e)]);
+        assert_eq!(type_of(&update("x", *float(0.0))), Ok(ty));
+        assert!(type_of(&update("x", *int(0))).is_err());
+        assert_eq!(
+            type_of(&update("z", *int(0))).unwrap_err().message,
+            "the type { x : Float; y : Int } has no field `z`"
+        );
+        let twice = Expr::Record(vec![("x".to_string(), *int(1)), ("x".to_string(), *int(2))]);
+        assert!(type_of(&twice).is_err());
+        assert!(type_of(&Expr::Field(int(1), "x".to_string())).is_err());
+    }
+
+    #[test]
+    fn resolves_and_prefers_aliases() {
+        let mut tc = TypeChecker::new();
+        let pair = Type::Tuple(vec![Type::Int, Type::Int]);
+        let pairs = Type::List(Box::new(Type::Named("pair".to_string())));
+        tc.define_alias("pair".to_string(), &pair).unwrap();
+        tc.define_alias("count".to_string(), &Type::Int).unwrap();
+        let expected = Type::List(Box::new(pair.clone()));
+        assert_eq!(
+            tc.define_alias("pairs".to_string(), &pairs),
+            Ok(expected.clone())
+        );
+
+        // fun l : pairs => l
+        let id = Expr::Func("l".to_string(), Box::new(pairs), var("l"));
+        let ty = tc.type_of(&id).unwrap();
+        assert_eq!(
+            ty,
+            Type::Func(Box::new(expected.clone()), Box::new(expected))
+        );
+        assert_eq!(tc.abbreviate(&ty).to_string(), "pairs -> pairs");
+        assert_eq!(tc.abbreviate(&Type::Int).to_string(), "Int");
+        assert_eq!(
+            tc.type_of(&Expr::Apply(Box::new(id), int(1)))
+                .unwrap_err()
+                .message,
+            "this expression has type Int but an expression was expected of type pairs"
+        );
+        let unknown = Expr::None(Type::Named("point".to_string()));
+        assert_eq!(
+            tc.type_of(&unknown).unwrap_err().message,
+            "unknown type `point`"
+        );
+    }
+
+    #[test]
+    fn types_mutually_recursive_groups() {
+        let pred = Type::Func(Box::new(Type::Int), Box::new(Type::Bool));
+        // fun n : int => if n = 0 then b else other (n - 1)
+        let body = |b: bool, other: &str| {
+            Expr::Func(
+                "n".to_string(),
+                Box::new(Type::Int),
+                Box::new(Expr::If(
+                    Box::new(Expr::Equal(var("n"), int(0))),
+                    Box::new(Expr::Bool(b)),
+                    Box::new(Expr::Apply(
+                        var(other),
+                        Box::new(Expr::Minus(var("n"), int(1))),
+                    )),
+                )),
+            )
+        };
+        let group = |ty: &Type| {
+            vec![
+                ("even".to_string(), pred.clone(), body(true, "odd")),
+                ("odd".to_string(), ty.clone(), body(false, "even")),
+            ]
+        };
+        let expr = Expr::LetRec(group(&pred), Box::new(Expr::Apply(var("odd"), int(7))));
+        assert_eq!(type_of(&expr), Ok(Type::Bool));
+        // The names are only in scope inside the group.
+        assert!(type_of(&Expr::Apply(var("even"), int(1))).is_err());
+        let wrong = Type::Func(Box::new(Type::Int), Box::new(Type::Int));
+        assert!(type_of(&Expr::LetRec(group(&wrong), int(0))).is_err());
+    }
+
+    #[test]
+    fn reports_unknown_variables() {
+        assert!(type_of(&Expr::Var("x".to_string())).is_err());
+    }
+}